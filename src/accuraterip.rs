@@ -0,0 +1,316 @@
+use std::{
+    collections::VecDeque,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use hound::{SampleFormat, WavSpec};
+use serde_json::json;
+
+use crate::{
+    cli::Cli,
+    report::{Failure, Report},
+    utils::progress,
+};
+
+/// Stereo samples per CD frame.
+const SAMPLES_PER_FRAME: u32 = 588;
+
+/// Samples at the start of the first and the end of the last track that are
+/// left out of the CRCs, 5 CD frames.
+const SKIPPED_SAMPLES: u32 = 5 * SAMPLES_PER_FRAME;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrackCrc {
+    pub v1: u32,
+    pub v2: u32,
+}
+
+/// Entry of an AccurateRip `.bin` response that matches a track CRC.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CrcMatch {
+    pub confidence: u8,
+    pub v2: bool,
+}
+
+#[derive(Default)]
+struct TrackState {
+    v1: u32,
+    v2: u32,
+    /// Multiplier of the next stereo sample, 1 based position in the track
+    multiplier: u32,
+    /// Last summed samples of the last track as (multiplier, sample), their
+    /// part is taken out again when the track ends
+    tail: VecDeque<(u32, u32)>,
+}
+
+/// AccurateRip v1 and v2 CRCs of the tracks of a CD image. Tracks range from
+/// their INDEX 01 to the INDEX 01 of the next track, so pregaps belong to the
+/// previous track and audio before track 1 to none.
+pub struct AccurateRip {
+    tracks: Vec<TrackState>,
+    /// Ranges of the current file as (track, start, end) in inter-channel
+    /// samples
+    ranges: Vec<(usize, u64, u64)>,
+    range: usize,
+    position: u64,
+    left: Option<i32>,
+}
+
+impl AccurateRip {
+    pub fn new(tracks: usize) -> AccurateRip {
+        AccurateRip {
+            tracks: (0..tracks)
+                .map(|_| TrackState {
+                    multiplier: 1,
+                    ..Default::default()
+                })
+                .collect(),
+            ranges: vec![],
+            range: 0,
+            position: 0,
+            left: None,
+        }
+    }
+
+    /// Starts the next audio file of the image. `ranges` are the parts of the
+    /// file as (track, start, end) in inter-channel samples, ordered and
+    /// starting at or after the first sample.
+    pub fn begin_file(
+        &mut self,
+        spec: WavSpec,
+        ranges: Vec<(usize, u64, u64)>,
+    ) -> Result<(), anyhow::Error> {
+        if spec.sample_rate != 44100
+            || spec.bits_per_sample != 16
+            || spec.channels != 2
+            || spec.sample_format != SampleFormat::Int
+        {
+            bail!("AccurateRip needs CD audio: 44100 Hz, 16 bit int, 2 channels");
+        }
+
+        self.ranges = ranges;
+        self.range = 0;
+        self.position = 0;
+        self.left = None;
+
+        Ok(())
+    }
+
+    /// Interleaved samples of the current file that still have to be passed
+    /// to `update`.
+    pub fn remaining(&self) -> u64 {
+        let end = self.ranges.last().map(|r| r.2 * 2).unwrap_or(0);
+        let read = self.position * 2 + self.left.is_some() as u64;
+        end.saturating_sub(read)
+    }
+
+    /// Takes the next interleaved sample of the current file.
+    pub fn update(&mut self, sample: i32) {
+        let left = match self.left.take() {
+            Some(l) => l,
+            None => {
+                self.left = Some(sample);
+                return;
+            }
+        };
+
+        let position = self.position;
+        self.position += 1;
+
+        while self.range < self.ranges.len() && self.ranges[self.range].2 <= position {
+            self.range += 1;
+        }
+
+        let track = match self.ranges.get(self.range) {
+            Some(r) if r.1 <= position => r.0,
+            _ => return,
+        };
+
+        let first = track == 0;
+        let last = track + 1 == self.tracks.len();
+        let state = &mut self.tracks[track];
+        let multiplier = state.multiplier;
+        state.multiplier = state.multiplier.wrapping_add(1);
+
+        if first && multiplier < SKIPPED_SAMPLES {
+            return;
+        }
+
+        let value = (sample as u16 as u32) << 16 | left as u16 as u32;
+        add(state, multiplier, value);
+
+        if last {
+            state.tail.push_back((multiplier, value));
+            if state.tail.len() > SKIPPED_SAMPLES as usize {
+                state.tail.pop_front();
+            }
+        }
+    }
+
+    /// CRCs of all tracks, the end of the last track is taken out.
+    pub fn finish(mut self) -> Vec<TrackCrc> {
+        if let Some(state) = self.tracks.last_mut() {
+            for (multiplier, value) in std::mem::take(&mut state.tail) {
+                subtract(state, multiplier, value);
+            }
+        }
+
+        self.tracks
+            .iter()
+            .map(|s| TrackCrc { v1: s.v1, v2: s.v2 })
+            .collect()
+    }
+}
+
+fn add(state: &mut TrackState, multiplier: u32, value: u32) {
+    let product = value as u64 * multiplier as u64;
+    state.v1 = state.v1.wrapping_add(product as u32);
+    state.v2 = state
+        .v2
+        .wrapping_add(product as u32)
+        .wrapping_add((product >> 32) as u32);
+}
+
+fn subtract(state: &mut TrackState, multiplier: u32, value: u32) {
+    let product = value as u64 * multiplier as u64;
+    state.v1 = state.v1.wrapping_sub(product as u32);
+    state.v2 = state
+        .v2
+        .wrapping_sub(product as u32)
+        .wrapping_sub((product >> 32) as u32);
+}
+
+/// Track CRCs of an AccurateRip `.bin` response. Every entry holds the track
+/// count, three disc IDs and per track the confidence, the CRC and the CRC of
+/// frame 450.
+pub fn read_bin(file: &Path) -> Result<Vec<Vec<(u8, u32)>>, anyhow::Error> {
+    let data = fs::read(file).with_context(|| format!("Failed to read {}", file.display()))?;
+    let u32_at = |p: usize| u32::from_le_bytes([data[p], data[p + 1], data[p + 2], data[p + 3]]);
+
+    let mut entries = vec![];
+    let mut p = 0;
+
+    while p < data.len() {
+        if p + 13 > data.len() {
+            bail!("Truncated AccurateRip response {}", file.display());
+        }
+
+        let count = data[p] as usize;
+        p += 13;
+
+        if p + count * 9 > data.len() {
+            bail!("Truncated AccurateRip response {}", file.display());
+        }
+
+        entries.push(
+            (0..count)
+                .map(|t| (data[p + t * 9], u32_at(p + t * 9 + 1)))
+                .collect(),
+        );
+        p += count * 9;
+    }
+
+    Ok(entries)
+}
+
+/// Best match of every track in the responses with the same track count.
+pub fn compare(crcs: &[TrackCrc], responses: &[Vec<(u8, u32)>]) -> Vec<Option<CrcMatch>> {
+    crcs.iter()
+        .enumerate()
+        .map(|(i, crc)| {
+            responses
+                .iter()
+                .filter(|r| r.len() == crcs.len())
+                .filter_map(|r| match r[i] {
+                    (confidence, c) if c == crc.v2 => Some(CrcMatch {
+                        confidence,
+                        v2: true,
+                    }),
+                    (confidence, c) if c == crc.v1 => Some(CrcMatch {
+                        confidence,
+                        v2: false,
+                    }),
+                    _ => None,
+                })
+                .max_by_key(|m| m.confidence)
+        })
+        .collect()
+}
+
+/// Prints the CRCs and, if responses are given, fails unless every track is
+/// found in them.
+pub fn report(
+    crcs: &[TrackCrc],
+    bin_files: &[PathBuf],
+    cli: &Cli,
+    report: &mut Report,
+) -> Result<(), anyhow::Error> {
+    let mut responses = vec![];
+    for file in bin_files {
+        responses.extend(read_bin(file)?);
+    }
+
+    let matches = compare(crcs, &responses);
+
+    if !cli.totally_silent {
+        progress!(cli, "AccurateRip CRCs:");
+        for (i, (crc, m)) in crcs.iter().zip(&matches).enumerate() {
+            let status = match (bin_files.is_empty(), m) {
+                (true, _) => String::new(),
+                (false, Some(m)) => format!(
+                    "  accurate (confidence {}, {})",
+                    m.confidence,
+                    if m.v2 { "v2" } else { "v1" }
+                ),
+                (false, None) => "  not found".to_string(),
+            };
+            progress!(
+                cli,
+                "  Track {:02}  v1 {:08X}  v2 {:08X}{}",
+                i + 1,
+                crc.v1,
+                crc.v2,
+                status
+            );
+        }
+    }
+
+    let tracks = crcs
+        .iter()
+        .zip(&matches)
+        .enumerate()
+        .map(|(i, (crc, m))| {
+            json!({
+                "track": i + 1,
+                "v1": format!("{:08X}", crc.v1),
+                "v2": format!("{:08X}", crc.v2),
+                "confidence": m.map(|m| m.confidence),
+            })
+        })
+        .collect();
+    report.results.push(("accuraterip", tracks));
+
+    if bin_files.is_empty() {
+        return Ok(());
+    }
+
+    let missing: Vec<String> = matches
+        .iter()
+        .enumerate()
+        .filter(|(_, m)| m.is_none())
+        .map(|(i, _)| format!("{:02}", i + 1))
+        .collect();
+
+    if !missing.is_empty() {
+        bail!(Failure::AccurateRip(format!(
+            "AccurateRip: {} of {} tracks not found in the responses: {}",
+            missing.len(),
+            crcs.len(),
+            missing.join(", ")
+        )));
+    }
+
+    Ok(())
+}
@@ -1,24 +1,38 @@
 use std::{
     fs::{self, File},
-    io::BufWriter,
-    path::PathBuf,
+    io::{BufReader, BufWriter, Read},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    thread,
 };
 
-use anyhow::{bail, Context};
-use blake3::Hasher;
+use anyhow::{anyhow, bail, Context};
 use claxon::FlacReader;
 use hound::{Sample, SampleFormat, WavReader, WavSpec, WavWriter};
-use num_traits::ToBytes;
+use indicatif::ProgressBar;
+use num_traits::{ToBytes, ToPrimitive};
 
 use crate::{
-    cli::Cli,
-    utils::{create_sample_progress, promt_overwrite},
+    accuraterip::AccurateRip,
+    cli::{Cli, OutputFormat, SplitArgs},
+    flac::{FlacSampleReader, FlacWriter},
+    tags::{append_wav_chunks, Tags},
+    utils::{create_sample_progress, progress, promt_overwrite},
+    verify::{OutputVerifier, SampleSource, Segment, StreamHasher},
 };
 
+/// Samples of a track written by a parallel split before the shared progress
+/// bar is updated.
+const PROGRESS_STEP: u64 = 1 << 16;
+
 pub struct Duration {
     pub file: PathBuf,
-    pub duration_seconds: f64,
+    /// Position of the first sample in the image, samples between two
+    /// durations are not part of any file
+    pub start_samples: u64,
     pub duration_samples: u64,
+    /// Tags of the output file of a split track
+    pub tags: Tags,
 }
 
 pub struct AudioInfo {
@@ -26,31 +40,168 @@ pub struct AudioInfo {
     pub total_samples: u64,
 }
 
+pub enum AudioWriter {
+    /// WAV writer, the file and the chunks appended after the samples
+    Wav(WavWriter<BufWriter<File>>, PathBuf, Vec<Vec<u8>>),
+    Flac(Box<FlacWriter>),
+}
+
+impl AudioWriter {
+    /// `expected_samples` is the number of inter-channel samples that will be
+    /// written, FLAC uses it to reserve the seek table.
+    pub fn create(
+        file: &Path,
+        spec: WavSpec,
+        format: OutputFormat,
+        compression_level: u8,
+        expected_samples: u64,
+        tags: &Tags,
+    ) -> Result<AudioWriter, anyhow::Error> {
+        match format {
+            OutputFormat::Wav => {
+                let mut chunks = vec![tags.riff_info_chunk()];
+                if tags.id3_chunk {
+                    chunks.push(tags.id3_chunk());
+                }
+
+                Ok(AudioWriter::Wav(
+                    WavWriter::create(file, spec).context("Failed to create output WAV file")?,
+                    file.to_path_buf(),
+                    chunks,
+                ))
+            }
+            OutputFormat::Flac => Ok(AudioWriter::Flac(Box::new(
+                FlacWriter::create(
+                    file,
+                    spec,
+                    compression_level,
+                    expected_samples,
+                    &tags.vorbis_comments(),
+                )
+                .context("Failed to create output FLAC file")?,
+            ))),
+        }
+    }
+
+    pub fn write_sample<S>(&mut self, sample: S) -> Result<(), anyhow::Error>
+    where
+        S: Sample + ToPrimitive,
+    {
+        match self {
+            AudioWriter::Wav(w, _, _) => w.write_sample(sample)?,
+            AudioWriter::Flac(w) => {
+                w.write_sample(sample.to_i32().context("Sample is not an integer")?)?
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn finalize(self) -> Result<(), anyhow::Error> {
+        match self {
+            AudioWriter::Wav(w, file, chunks) => {
+                w.finalize().context("Failed to update the WAVE header")?;
+                append_wav_chunks(&file, &chunks).context("Failed to write WAV tags")
+            }
+            AudioWriter::Flac(w) => w.finalize().context("Failed to update the FLAC header"),
+        }
+    }
+}
+
+impl OutputFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Wav => "wav",
+            OutputFormat::Flac => "flac",
+        }
+    }
+}
+
+pub fn get_output_format(
+    file: &Path,
+    format: &Option<OutputFormat>,
+) -> Result<OutputFormat, anyhow::Error> {
+    if let Some(f) = format {
+        return Ok(*f);
+    }
+
+    match file
+        .extension()
+        .context("Failed to get file extension")?
+        .to_str()
+        .context("to_str failed")?
+        .to_lowercase()
+        .as_ref()
+    {
+        "wav" => Ok(OutputFormat::Wav),
+        "flac" => Ok(OutputFormat::Flac),
+        _ => bail!("Unsupported output format"),
+    }
+}
+
 pub fn wav_split_samples<S>(
-    input_file: &PathBuf,
+    input_file: &Path,
     audio_spec: WavSpec,
-    durations: &Vec<Duration>,
+    durations: &[Duration],
+    args: &SplitArgs,
+    verifier: Option<&OutputVerifier>,
+    mut accuraterip: Option<&mut AccurateRip>,
     cli: &Cli,
 ) -> Result<Vec<PathBuf>, anyhow::Error>
 where
-    S: Sample,
+    S: Sample + ToPrimitive + ToBytes,
 {
     let mut reader = WavReader::open(input_file)?;
-    let mut samples = reader.samples::<S>();
+    let bytes_per_sample = wav_bytes_per_sample(input_file)?;
+    let mut position: u64 = 0;
     let mut output_wavs: Vec<PathBuf> = vec![];
 
     for duration in durations {
-        if !cli.force && fs::exists(&duration.file).context("Can't check existence of file")? {
-            if !promt_overwrite(&duration.file).context("Promt failed")? {
-                continue;
+        if !cli.force
+            && fs::exists(&duration.file).context("Can't check existence of file")?
+            && !promt_overwrite(&duration.file).context("Promt failed")?
+        {
+            continue;
+        }
+
+        let skip = duration
+            .start_samples
+            .checked_sub(position)
+            .context("Tracks overlap")?;
+
+        // AccurateRip CRCs need every sample, tracks that are left out are
+        // only read for them
+        match accuraterip.as_deref_mut() {
+            None => wav_seek::<S>(
+                &mut reader,
+                audio_spec,
+                bytes_per_sample,
+                position,
+                duration.start_samples,
+            )?,
+            Some(ar) => {
+                let mut samples = reader.samples::<S>();
+                for _ in 0..skip {
+                    let sample = samples.next().context("Failed to get next sample")??;
+                    ar.update(sample.to_i32().context("Sample is not an integer")?);
+                }
             }
         }
 
-        let mut output_wav = WavWriter::create(&duration.file, audio_spec)
-            .context("Failed to create output WAV file")?;
+        position = duration.start_samples + duration.duration_samples;
+
+        let mut output_wav = AudioWriter::create(
+            &duration.file,
+            audio_spec,
+            args.output_format,
+            args.compression_level,
+            duration.duration_samples / audio_spec.channels as u64,
+            &duration.tags,
+        )?;
 
         if !cli.silent {
-            println!(
+            progress!(
+                cli,
                 "Writing {} ...",
                 &duration
                     .file
@@ -62,19 +213,242 @@ where
         }
 
         let pb = create_sample_progress(duration.duration_samples, cli)?;
+        let mut hasher = verifier.map(OutputVerifier::hasher);
+        let mut samples = reader.samples::<S>();
+
+        for _ in 0..duration.duration_samples {
+            let sample = samples.next().context("Failed to get next sample")??;
+
+            if let Some(ref mut h) = hasher {
+                h.update_sample(&sample)?;
+            }
+
+            if let Some(ar) = accuraterip.as_deref_mut() {
+                ar.update(sample.to_i32().context("Sample is not an integer")?);
+            }
+
+            output_wav.write_sample(sample)?;
+
+            if let Some(ref v) = pb {
+                v.inc(1)
+            }
+        }
+
+        output_wav.finalize()?;
+
+        if let (Some(v), Some(h)) = (verifier, hasher) {
+            v.check(
+                &duration.file,
+                audio_spec,
+                vec![track_segment(input_file, duration, h)?],
+            )?;
+        }
+
+        output_wavs.push(duration.file.clone());
+    }
+
+    // The last track of the image may go on after the last output file
+    if let Some(ar) = accuraterip {
+        let mut samples = reader.samples::<S>();
+        for _ in 0..ar.remaining() {
+            let sample = samples.next().context("Failed to get next sample")??;
+            ar.update(sample.to_i32().context("Sample is not an integer")?);
+        }
+    }
+
+    Ok(output_wavs)
+}
 
-        for _ in 0..duration.duration_samples as u64 {
-            output_wav.write_sample(samples.next().context("Failed to get next sample")??)?;
+/// Same output as `wav_split_samples` with up to `args.jobs` tracks written at
+/// the same time, every track by its own reader that starts at the first sample
+/// of the track.
+pub fn wav_split_samples_parallel<S>(
+    input_file: &Path,
+    audio_spec: WavSpec,
+    durations: &[Duration],
+    args: &SplitArgs,
+    verifier: Option<&OutputVerifier>,
+    cli: &Cli,
+) -> Result<Vec<PathBuf>, anyhow::Error>
+where
+    S: Sample + ToPrimitive + ToBytes + Send,
+{
+    let bytes_per_sample = wav_bytes_per_sample(input_file)?;
+
+    split_samples_parallel(
+        input_file,
+        audio_spec,
+        durations,
+        verifier,
+        args.jobs as usize,
+        |duration, pb| {
+            let mut reader = WavReader::open(input_file)?;
+            wav_seek::<S>(
+                &mut reader,
+                audio_spec,
+                bytes_per_sample,
+                0,
+                duration.start_samples,
+            )?;
+
+            write_track(
+                &mut reader.samples::<S>(),
+                audio_spec,
+                duration,
+                args.output_format,
+                args.compression_level,
+                verifier,
+                pb,
+            )
+        },
+        cli,
+    )
+}
 
-            match pb {
-                Some(ref v) => v.inc(1),
-                None => (),
+/// Same output as `flac_split_samples` with up to `args.jobs` tracks written at
+/// the same time, every track by its own reader that seeks to the frame of
+/// its first sample.
+pub fn flac_split_samples_parallel(
+    input_file: &Path,
+    audio_spec: WavSpec,
+    durations: &[Duration],
+    args: &SplitArgs,
+    verifier: Option<&OutputVerifier>,
+    cli: &Cli,
+) -> Result<Vec<PathBuf>, anyhow::Error> {
+    split_samples_parallel(
+        input_file,
+        audio_spec,
+        durations,
+        verifier,
+        args.jobs as usize,
+        |duration, pb| {
+            let mut samples = FlacSampleReader::open(input_file)?;
+
+            if duration.start_samples > 0 {
+                samples.seek(duration.start_samples / audio_spec.channels as u64)?;
             }
+
+            write_track(
+                &mut samples,
+                audio_spec,
+                duration,
+                args.output_format,
+                args.compression_level,
+                verifier,
+                pb,
+            )
+        },
+        cli,
+    )
+}
+
+/// Runs `write_track` for every track on up to `jobs` threads and checks the
+/// written tracks in track order, the same as when they are written one at a
+/// time.
+fn split_samples_parallel<F>(
+    input_file: &Path,
+    audio_spec: WavSpec,
+    durations: &[Duration],
+    verifier: Option<&OutputVerifier>,
+    jobs: usize,
+    write_track: F,
+    cli: &Cli,
+) -> Result<Vec<PathBuf>, anyhow::Error>
+where
+    F: Fn(&Duration, Option<&ProgressBar>) -> Result<Option<StreamHasher>, anyhow::Error> + Sync,
+{
+    let mut selected: Vec<&Duration> = vec![];
+    let mut position: u64 = 0;
+
+    for duration in durations {
+        if !cli.force
+            && fs::exists(&duration.file).context("Can't check existence of file")?
+            && !promt_overwrite(&duration.file).context("Promt failed")?
+        {
+            continue;
         }
 
-        output_wav
-            .finalize()
-            .context("Failed to update the WAVE header")?;
+        if duration.start_samples < position {
+            bail!("Tracks overlap");
+        }
+        position = duration.start_samples + duration.duration_samples;
+        selected.push(duration);
+    }
+
+    let pb = create_sample_progress(selected.iter().map(|d| d.duration_samples).sum(), cli)?;
+    let write = |duration: &Duration| {
+        if !cli.silent {
+            let name = duration
+                .file
+                .file_name()
+                .context("Failed to get file name")?
+                .to_str()
+                .context("to_str failed")?;
+            let print = || progress!(cli, "Writing {} ...", name);
+
+            match &pb {
+                Some(pb) => pb.suspend(print),
+                None => print(),
+            }
+        }
+
+        write_track(duration, pb.as_ref())
+    };
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+
+    let written = thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs.min(selected.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut written = vec![];
+
+                    while !failed.load(Ordering::Relaxed) {
+                        let k = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(duration) = selected.get(k) else {
+                            break;
+                        };
+
+                        match write(duration) {
+                            Ok(hasher) => written.push((k, hasher)),
+                            Err(e) => {
+                                failed.store(true, Ordering::Relaxed);
+                                return Err(e);
+                            }
+                        }
+                    }
+
+                    Ok(written)
+                })
+            })
+            .collect();
+
+        workers
+            .into_iter()
+            .map(|w| {
+                w.join()
+                    .map_err(|_| anyhow!("Split thread panicked"))
+                    .and_then(|r| r)
+            })
+            .collect::<Result<Vec<_>, anyhow::Error>>()
+    })?;
+
+    let mut written: Vec<(usize, Option<StreamHasher>)> = written.into_iter().flatten().collect();
+    written.sort_by_key(|(k, _)| *k);
+
+    let mut output_wavs: Vec<PathBuf> = vec![];
+
+    for (k, hasher) in written {
+        let duration = selected[k];
+
+        if let (Some(v), Some(h)) = (verifier, hasher) {
+            v.check(
+                &duration.file,
+                audio_spec,
+                vec![track_segment(input_file, duration, h)?],
+            )?;
+        }
 
         output_wavs.push(duration.file.clone());
     }
@@ -82,28 +456,188 @@ where
     Ok(output_wavs)
 }
 
+/// Writes one track from `samples`, which start at the first sample of the
+/// track. Returns the hasher of the written samples if there is a `verifier`.
+fn write_track<S, E>(
+    samples: &mut impl Iterator<Item = Result<S, E>>,
+    audio_spec: WavSpec,
+    duration: &Duration,
+    output_format: OutputFormat,
+    compression_level: u8,
+    verifier: Option<&OutputVerifier>,
+    pb: Option<&ProgressBar>,
+) -> Result<Option<StreamHasher>, anyhow::Error>
+where
+    S: Sample + ToPrimitive + ToBytes,
+    anyhow::Error: From<E>,
+{
+    let mut output_wav = AudioWriter::create(
+        &duration.file,
+        audio_spec,
+        output_format,
+        compression_level,
+        duration.duration_samples / audio_spec.channels as u64,
+        &duration.tags,
+    )?;
+    let mut hasher = verifier.map(OutputVerifier::hasher);
+
+    for k in 1..=duration.duration_samples {
+        let sample = samples.next().context("Failed to get next sample")??;
+
+        if let Some(ref mut h) = hasher {
+            h.update_sample(&sample)?;
+        }
+
+        output_wav.write_sample(sample)?;
+
+        // The bar is shared by all workers, so it is updated in steps
+        if let Some(pb) = pb.filter(|_| k % PROGRESS_STEP == 0) {
+            pb.inc(PROGRESS_STEP);
+        }
+    }
+
+    if let Some(pb) = pb {
+        pb.inc(duration.duration_samples % PROGRESS_STEP);
+    }
+
+    output_wav.finalize()?;
+
+    Ok(hasher)
+}
+
+/// Moves a WAV reader that has read `position` interleaved samples on to
+/// `start`. hound seeks by bits_per_sample / 8 bytes per sample, samples in
+/// wider containers such as 20 bits in 3 bytes or 24 bits in 4 bytes are read
+/// up to the start instead.
+fn wav_seek<S: Sample>(
+    reader: &mut WavReader<BufReader<File>>,
+    audio_spec: WavSpec,
+    bytes_per_sample: u16,
+    position: u64,
+    start: u64,
+) -> Result<(), anyhow::Error> {
+    if start == position {
+        return Ok(());
+    }
+
+    if bytes_per_sample != audio_spec.bits_per_sample / 8 {
+        let mut samples = reader.samples::<S>();
+        for _ in position..start {
+            samples.next().context("Failed to get next sample")??;
+        }
+        return Ok(());
+    }
+
+    let frame = u32::try_from(start / audio_spec.channels as u64)
+        .context("Track starts after the end of a WAV file")?;
+    reader.seek(frame).context("Failed to seek")
+}
+
+/// Bytes of one sample in the data chunk of a WAV file, the block align of
+/// the fmt chunk divided by the channels.
+fn wav_bytes_per_sample(file: &Path) -> Result<u16, anyhow::Error> {
+    let mut reader = BufReader::new(
+        File::open(file).with_context(|| format!("Failed to open {}", file.display()))?,
+    );
+    let mut header = [0u8; 12];
+    reader.read_exact(&mut header)?;
+
+    loop {
+        let mut chunk = [0u8; 8];
+        reader
+            .read_exact(&mut chunk)
+            .context("WAV file has no fmt chunk")?;
+        let length = u32::from_le_bytes(chunk[4..8].try_into()?) as i64;
+
+        if &chunk[..4] == b"fmt " {
+            let mut fmt = [0u8; 16];
+            reader.read_exact(&mut fmt)?;
+            let channels = u16::from_le_bytes([fmt[2], fmt[3]]);
+            let block_align = u16::from_le_bytes([fmt[12], fmt[13]]);
+            return Ok(block_align / channels.max(1));
+        }
+
+        reader.seek_relative(length + length % 2)?;
+    }
+}
+
+/// Verify segment of a split track, the whole output file.
+fn track_segment(
+    input_file: &Path,
+    duration: &Duration,
+    hasher: StreamHasher,
+) -> Result<Segment, anyhow::Error> {
+    Ok(Segment {
+        name: duration
+            .file
+            .file_name()
+            .context("Failed to get file name")?
+            .to_string_lossy()
+            .to_string(),
+        source: SampleSource::Range {
+            file: input_file.to_path_buf(),
+            start: duration.start_samples,
+            length: duration.duration_samples,
+        },
+        hasher,
+    })
+}
+
 pub fn flac_split_samples(
-    input_file: &PathBuf,
+    input_file: &Path,
     audio_spec: WavSpec,
-    durations: &Vec<Duration>,
+    durations: &[Duration],
+    args: &SplitArgs,
+    verifier: Option<&OutputVerifier>,
+    mut accuraterip: Option<&mut AccurateRip>,
     cli: &Cli,
 ) -> Result<Vec<PathBuf>, anyhow::Error> {
-    let mut reader = FlacReader::open(input_file)?;
-    let mut samples = reader.samples();
+    let mut samples = FlacSampleReader::open(input_file)?;
+    let mut position: u64 = 0;
     let mut output_wavs: Vec<PathBuf> = vec![];
 
     for duration in durations {
-        if !cli.force && fs::exists(&duration.file).context("Can't check existence of file")? {
-            if !promt_overwrite(&duration.file).context("Promt failed")? {
-                continue;
+        if !cli.force
+            && fs::exists(&duration.file).context("Can't check existence of file")?
+            && !promt_overwrite(&duration.file).context("Promt failed")?
+        {
+            continue;
+        }
+
+        let skip = duration
+            .start_samples
+            .checked_sub(position)
+            .context("Tracks overlap")?;
+
+        // AccurateRip CRCs need every sample, tracks that are left out are
+        // only read for them
+        match accuraterip.as_deref_mut() {
+            None if skip > 0 => {
+                samples.seek(duration.start_samples / audio_spec.channels as u64)?;
+            }
+            None => (),
+            Some(ar) => {
+                for _ in 0..skip {
+                    let sample = samples.next().context("Failed to get next sample")??;
+                    ar.update(sample);
+                }
             }
         }
 
-        let mut output_wav = WavWriter::create(&duration.file, audio_spec)
-            .context("Failed to create output WAV file")?;
+        position = duration.start_samples + duration.duration_samples;
+
+        let mut output_wav = AudioWriter::create(
+            &duration.file,
+            audio_spec,
+            args.output_format,
+            args.compression_level,
+            duration.duration_samples / audio_spec.channels as u64,
+            &duration.tags,
+        )?;
 
         if !cli.silent {
-            println!(
+            progress!(
+                cli,
                 "Writing {} ...",
                 &duration
                     .file
@@ -114,34 +648,59 @@ pub fn flac_split_samples(
             );
         }
 
-        let pb = create_sample_progress(duration.duration_samples, &cli)?;
+        let pb = create_sample_progress(duration.duration_samples, cli)?;
+        let mut hasher = verifier.map(OutputVerifier::hasher);
+
+        for _ in 0..duration.duration_samples {
+            let sample = samples.next().context("Failed to get next sample")??;
 
-        for _ in 0..duration.duration_samples as u64 {
-            output_wav.write_sample(samples.next().context("Failed to get next sample")??)?;
+            if let Some(ref mut h) = hasher {
+                h.update_sample(&sample)?;
+            }
 
-            match pb {
-                Some(ref v) => v.inc(1),
-                None => (),
+            if let Some(ar) = accuraterip.as_deref_mut() {
+                ar.update(sample.to_i32().context("Sample is not an integer")?);
+            }
+
+            output_wav.write_sample(sample)?;
+
+            if let Some(ref v) = pb {
+                v.inc(1)
             }
         }
 
-        output_wav
-            .finalize()
-            .context("Failed to update the WAVE header")?;
+        output_wav.finalize()?;
+
+        if let (Some(v), Some(h)) = (verifier, hasher) {
+            v.check(
+                &duration.file,
+                audio_spec,
+                vec![track_segment(input_file, duration, h)?],
+            )?;
+        }
 
         output_wavs.push(duration.file.clone());
     }
 
+    // The last track of the image may go on after the last output file
+    if let Some(ar) = accuraterip {
+        for _ in 0..ar.remaining() {
+            let sample = samples.next().context("Failed to get next sample")??;
+            ar.update(sample.to_i32().context("Sample is not an integer")?);
+        }
+    }
+
     Ok(output_wavs)
 }
 
 pub fn wav_copy_samples<S>(
-    from_file: &PathBuf,
-    to_file: &mut WavWriter<BufWriter<File>>,
+    from_file: &Path,
+    to_file: &mut AudioWriter,
+    hasher: &mut Option<StreamHasher>,
     cli: &Cli,
 ) -> Result<u64, anyhow::Error>
 where
-    S: Sample,
+    S: Sample + ToPrimitive + ToBytes,
 {
     let mut reader = WavReader::open(from_file)?;
     let mut samples_written: u64 = 0;
@@ -149,12 +708,17 @@ where
     let samples = reader.samples::<S>();
 
     for sample in samples {
-        to_file.write_sample(sample?)?;
+        let sample = sample?;
+
+        if let Some(h) = hasher {
+            h.update_sample(&sample)?;
+        }
+
+        to_file.write_sample(sample)?;
         samples_written += 1;
 
-        match pb {
-            Some(ref v) => v.set_position(samples_written),
-            None => (),
+        if let Some(ref v) = pb {
+            v.set_position(samples_written)
         }
     }
 
@@ -162,8 +726,9 @@ where
 }
 
 pub fn flac_copy_samples(
-    from_file: &PathBuf,
-    to_file: &mut WavWriter<BufWriter<File>>,
+    from_file: &Path,
+    to_file: &mut AudioWriter,
+    hasher: &mut Option<StreamHasher>,
     cli: &Cli,
 ) -> Result<u64, anyhow::Error> {
     let mut reader = FlacReader::open(from_file)?;
@@ -177,164 +742,40 @@ pub fn flac_copy_samples(
     let samples = reader.samples();
 
     for sample in samples {
-        to_file.write_sample(sample?)?;
-        samples_written += 1;
+        let sample = sample?;
 
-        match pb {
-            Some(ref v) => v.set_position(samples_written),
-            None => (),
+        if let Some(h) = hasher {
+            h.update_sample(&sample)?;
         }
-    }
-
-    Ok(reader
-        .streaminfo()
-        .samples
-        .context("Failed to get samples total number")?)
-}
 
-pub fn wav_hash_samples<S>(
-    from_file: &PathBuf,
-    hasher: &mut Hasher,
-    cli: &Cli,
-) -> Result<(), anyhow::Error>
-where
-    S: Sample + ToBytes,
-{
-    let mut reader = WavReader::open(from_file)?;
-    let mut samples_readed: u64 = 0;
-    let pb = create_sample_progress(reader.len().into(), cli)?;
-
-    let mut bytes;
-    let samples = reader.samples::<S>();
-    for sample in samples {
-        bytes = sample?.to_be_bytes();
-        hasher.update(bytes.as_ref());
-        samples_readed += 1;
+        to_file.write_sample(sample)?;
+        samples_written += 1;
 
-        match pb {
-            Some(ref v) => v.set_position(samples_readed),
-            None => (),
+        if let Some(ref v) = pb {
+            v.set_position(samples_written)
         }
     }
 
-    Ok(())
-}
-
-pub fn flac_hash_samples(
-    from_file: &PathBuf,
-    hasher: &mut Hasher,
-    cli: &Cli,
-) -> Result<(), anyhow::Error> {
-    let mut reader = FlacReader::open(from_file)?;
-    let samples_count = reader
+    reader
         .streaminfo()
         .samples
-        .context("Failed to get samples total number")?
-        * reader.streaminfo().channels as u64;
-    let mut samples_readed: u64 = 0;
-    let pb = create_sample_progress(samples_count, cli)?;
-
-    let mut bytes;
-    let samples = reader.samples();
-    for sample in samples {
-        bytes = sample?.to_be_bytes();
-        hasher.update(bytes.as_ref());
-        samples_readed += 1;
-
-        match pb {
-            Some(ref v) => v.set_position(samples_readed),
-            None => (),
-        }
-    }
-
-    Ok(())
-}
-
-pub fn verify_samples(
-    sample_format: SampleFormat,
-    input: &Vec<PathBuf>,
-    output: &PathBuf,
-    cli: &Cli,
-) -> Result<(), anyhow::Error> {
-    if !cli.silent {
-        println!("Verifying ...");
-    }
-
-    let mut input_hasher = Hasher::new();
-    for file in input {
-        if !cli.silent {
-            println!(
-                "Reading {} ...",
-                &file
-                    .file_name()
-                    .context("Failed to get file name")?
-                    .to_str()
-                    .context("to_str failed")?
-            );
-        }
-
-        hash_samples(file, &sample_format, &mut input_hasher, cli)?;
-    }
-
-    let input_hash = input_hasher.finalize();
-
-    if !cli.silent {
-        println!(
-            "Reading {} ...",
-            &output
-                .file_name()
-                .context("Failed to get file name")?
-                .to_str()
-                .context("to_str failed")?
-        );
-    }
-
-    let mut output_hasher = Hasher::new();
-    hash_samples(output, &sample_format, &mut output_hasher, cli)?;
-
-    let output_hash = output_hasher.finalize();
-
-    if input_hash != output_hash {
-        bail!("Verify FAILED: Samples mismatch");
-    } else {
-        if !cli.totally_silent {
-            println!("Verify OK");
-        }
-    }
-
-    Ok(())
+        .context("Failed to get samples total number")
 }
 
-pub fn hash_samples(
-    file: &PathBuf,
-    sample_format: &SampleFormat,
-    hasher: &mut Hasher,
-    cli: &Cli,
-) -> Result<(), anyhow::Error> {
-    match file
-        .extension()
-        .context("Failed to get file extension")?
-        .to_str()
-        .context("to_str failed")?
-        .to_lowercase()
-        .as_ref()
-    {
-        "wav" => match sample_format {
-            SampleFormat::Float => {
-                wav_hash_samples::<f32>(file, hasher, cli).context("Failed to hash samples")?
-            }
-            SampleFormat::Int => {
-                wav_hash_samples::<i32>(file, hasher, cli).context("Failed to hash samples")?
-            }
+pub fn format_spec(spec: &WavSpec) -> String {
+    format!(
+        "{} Hz, {} bit {}, {} channels",
+        spec.sample_rate,
+        spec.bits_per_sample,
+        match spec.sample_format {
+            SampleFormat::Int => "int",
+            SampleFormat::Float => "float",
         },
-        "flac" => flac_hash_samples(file, hasher, cli).context("Failed to hash samples")?,
-        _ => bail!("Unsupported format"),
-    };
-
-    Ok(())
+        spec.channels
+    )
 }
 
-pub fn get_wav_info(file: &PathBuf) -> Result<AudioInfo, anyhow::Error> {
+pub fn get_wav_info(file: &Path) -> Result<AudioInfo, anyhow::Error> {
     let reader = WavReader::open(file)?;
     Ok(AudioInfo {
         spec: reader.spec(),
@@ -342,7 +783,7 @@ pub fn get_wav_info(file: &PathBuf) -> Result<AudioInfo, anyhow::Error> {
     })
 }
 
-pub fn get_flac_info(file: &PathBuf) -> Result<AudioInfo, anyhow::Error> {
+pub fn get_flac_info(file: &Path) -> Result<AudioInfo, anyhow::Error> {
     let reader = FlacReader::open(file)?;
     let spec = WavSpec {
         channels: reader.streaminfo().channels as u16,
@@ -361,7 +802,7 @@ pub fn get_flac_info(file: &PathBuf) -> Result<AudioInfo, anyhow::Error> {
     })
 }
 
-pub fn get_audio_info(file: &PathBuf) -> Result<AudioInfo, anyhow::Error> {
+pub fn get_audio_info(file: &Path) -> Result<AudioInfo, anyhow::Error> {
     match file
         .extension()
         .context("Failed to get file extension")?
@@ -370,10 +811,8 @@ pub fn get_audio_info(file: &PathBuf) -> Result<AudioInfo, anyhow::Error> {
         .to_lowercase()
         .as_ref()
     {
-        "wav" => Ok(get_wav_info(&file).context("Failed to get information about the WAVE file")?),
-        "flac" => {
-            Ok(get_flac_info(&file).context("Failed to get information about the FLAC file")?)
-        }
+        "wav" => Ok(get_wav_info(file).context("Failed to get information about the WAVE file")?),
+        "flac" => Ok(get_flac_info(file).context("Failed to get information about the FLAC file")?),
         _ => bail!("Unsupported format"),
     }
 }
@@ -0,0 +1,226 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use serde_json::json;
+
+use crate::{
+    cli::Cli,
+    cue::{read_cue_text, CueSheet},
+    report::{Failure, Report},
+    utils::{natural_cmp, progress},
+};
+
+/// What happened to one album of a recursive split.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AlbumStatus {
+    Done,
+    Failed(String),
+    Skipped(String),
+}
+
+impl AlbumStatus {
+    fn name(&self) -> &'static str {
+        match self {
+            AlbumStatus::Done => "done",
+            AlbumStatus::Failed(_) => "failed",
+            AlbumStatus::Skipped(_) => "skipped",
+        }
+    }
+}
+
+/// Splits every CUE sheet below `input` whose audio files exist, into the
+/// folder of the CUE sheet or the same folder below `output_dir`. A failed
+/// album doesn't stop the others. Albums that were split get a marker file
+/// listing their tracks, so a re-run skips them unless `resplit` is set.
+pub fn split_recursive<F>(
+    input: &Path,
+    output_dir: &Option<PathBuf>,
+    dry_run: bool,
+    resplit: bool,
+    cli: &Cli,
+    report: &mut Report,
+    split_album: F,
+) -> Result<Vec<PathBuf>, anyhow::Error>
+where
+    F: Fn(&Path, &Option<PathBuf>, &mut Report) -> Result<Vec<PathBuf>, anyhow::Error>,
+{
+    if !input.is_dir() {
+        bail!("{} is not a directory", input.display());
+    }
+
+    let cues = find_cue_sheets(input)?;
+    let mut albums: Vec<(PathBuf, AlbumStatus)> = vec![];
+    let mut outputs = vec![];
+
+    for (k, cue) in cues.iter().enumerate() {
+        let cue_dir = cue.parent().context("Failed to get parent dir")?;
+        let album_dir = match output_dir {
+            Some(d) => Some(d.join(cue_dir.strip_prefix(input)?)),
+            None => None,
+        };
+        let marker = done_marker(cue, album_dir.as_deref().unwrap_or(cue_dir))?;
+
+        let skip = match missing_audio(cue, cli) {
+            Some(file) => Some(format!("{} not found", file)),
+            None if !resplit && marker.exists() => Some("already split".to_string()),
+            None => None,
+        };
+
+        if let Some(reason) = skip {
+            albums.push((cue.clone(), AlbumStatus::Skipped(reason)));
+            continue;
+        }
+
+        if !cli.silent && !cli.totally_silent {
+            progress!(
+                cli,
+                "Splitting {} ({}/{})",
+                cue.strip_prefix(input)?.display(),
+                k + 1,
+                cues.len()
+            );
+        }
+
+        let result = split_album(cue, &album_dir, report).and_then(|tracks| {
+            if !dry_run {
+                let list: Vec<String> = tracks.iter().map(|t| t.display().to_string()).collect();
+                fs::write(&marker, list.join("\n"))
+                    .with_context(|| format!("Failed to write {}", marker.display()))?;
+            }
+            Ok(tracks)
+        });
+
+        match result {
+            Ok(tracks) => {
+                outputs.extend(tracks);
+                albums.push((cue.clone(), AlbumStatus::Done));
+            }
+            Err(e) => {
+                if !cli.totally_silent {
+                    progress!(cli, "Error: {:#}", e);
+                }
+                albums.push((cue.clone(), AlbumStatus::Failed(format!("{:#}", e))));
+            }
+        }
+    }
+
+    print_summary(input, &albums, cli, report);
+
+    let failed = albums
+        .iter()
+        .filter(|(_, s)| matches!(s, AlbumStatus::Failed(_)))
+        .count();
+
+    if failed > 0 {
+        bail!(Failure::Batch(format!(
+            "{} of {} albums failed",
+            failed,
+            albums.len()
+        )));
+    }
+
+    Ok(outputs)
+}
+
+/// CUE files below `dir` in natural order. A multiple file CUE sheet next to
+/// the CUE sheet it was split from is left out.
+fn find_cue_sheets(dir: &Path) -> Result<Vec<PathBuf>, anyhow::Error> {
+    let mut entries = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read {}", dir.display()))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<PathBuf>, _>>()
+        .with_context(|| format!("Failed to read {}", dir.display()))?;
+    entries.sort_by(|a, b| natural_cmp(a, b));
+
+    let mut cues = vec![];
+
+    for entry in entries {
+        if entry.is_dir() {
+            cues.extend(find_cue_sheets(&entry)?);
+            continue;
+        }
+
+        let is_cue = entry
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case("cue"));
+        let split_from = entry
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .and_then(|s| s.strip_suffix("_multiple"))
+            .map(|s| entry.with_file_name(format!("{}.cue", s)));
+
+        if is_cue && !split_from.is_some_and(|s| s.exists()) {
+            cues.push(entry);
+        }
+    }
+
+    Ok(cues)
+}
+
+/// First FILE of the CUE sheet that doesn't exist. A CUE sheet that can't be
+/// read is not skipped, splitting it reports the error.
+fn missing_audio(cue: &Path, cli: &Cli) -> Option<String> {
+    let (text, _) = read_cue_text(cue, cli.cue_encoding).ok()?;
+    let cue_sheet = CueSheet::try_from(text.as_ref()).ok()?;
+    let cue_dir = cue.parent()?;
+
+    cue_sheet
+        .cue
+        .files
+        .into_iter()
+        .find(|f| !cue_dir.join(f).exists())
+}
+
+/// Hidden file in the output folder of an album that was split.
+fn done_marker(cue: &Path, album_dir: &Path) -> Result<PathBuf, anyhow::Error> {
+    let name = cue
+        .file_name()
+        .context("Failed to get file name")?
+        .to_str()
+        .context("to_str failed")?;
+
+    Ok(album_dir.join(format!(".{}.wfcue-done", name)))
+}
+
+fn print_summary(input: &Path, albums: &[(PathBuf, AlbumStatus)], cli: &Cli, report: &mut Report) {
+    let count = |name: &str| albums.iter().filter(|(_, s)| s.name() == name).count();
+    let relative = |cue: &PathBuf| cue.strip_prefix(input).unwrap_or(cue).display().to_string();
+
+    if !cli.totally_silent {
+        progress!(
+            cli,
+            "Albums: {} split, {} failed, {} skipped",
+            count("done"),
+            count("failed"),
+            count("skipped")
+        );
+
+        for (cue, status) in albums {
+            match status {
+                AlbumStatus::Done => progress!(cli, "  OK       {}", relative(cue)),
+                AlbumStatus::Failed(e) => progress!(cli, "  FAILED   {}: {}", relative(cue), e),
+                AlbumStatus::Skipped(r) => progress!(cli, "  SKIPPED  {}: {}", relative(cue), r),
+            }
+        }
+    }
+
+    let albums = albums
+        .iter()
+        .map(|(cue, status)| {
+            let message = match status {
+                AlbumStatus::Done => None,
+                AlbumStatus::Failed(m) | AlbumStatus::Skipped(m) => Some(m),
+            };
+            json!({
+                "cue": cue.to_string_lossy(),
+                "status": status.name(),
+                "message": message,
+            })
+        })
+        .collect();
+    report.results.push(("albums", albums));
+}
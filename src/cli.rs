@@ -1,6 +1,7 @@
 use std::path::PathBuf;
 
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use encoding_rs::Encoding;
 
 #[derive(Parser)]
 #[command(author="John White", version, about="Merge/Split WAV,FLAC files and create CUE sheet", long_about = None, arg_required_else_help = true)]
@@ -14,54 +15,339 @@ pub struct Cli {
     /// Do not print anything of any kind, including warnings or errors
     #[arg(long, short, default_value = "false")]
     pub totally_silent: bool,
+    /// Print progress to stderr and a JSON result document to stdout
+    #[arg(long, default_value = "false")]
+    pub json: bool,
+    /// Encoding of CUE files without a BOM, such as windows-1251,
+    /// windows-1252, shift_jis or utf-16le, detected by default
+    #[arg(long, value_parser = parse_encoding)]
+    pub cue_encoding: Option<&'static Encoding>,
     #[command(subcommand)]
     pub command: Commands,
 }
 
 #[derive(Subcommand)]
 pub enum Commands {
-    /// Merge WAV,FLAC files into single WAV file and create CUE sheet
-    Merge {
-        /// Create CUE sheet
-        #[arg(long, short, default_value = "false")]
-        cue: bool,
-        /// Set CUE album name
-        #[arg(long, short)]
-        title: Option<String>,
-        /// Set CUE artist
-        #[arg(long, short)]
-        performer: Option<String>,
-        /// Add REM comments to CUE
-        #[arg(long, short)]
-        rem: Option<Vec<String>>,
-        /// Make sure input files samples matches output file samples
-        #[arg(long, short, default_value = "false")]
-        verify: bool,
-        /// Input files
+    /// Merge WAV,FLAC files into single WAV,FLAC file and create CUE sheet
+    Merge(MergeArgs),
+    /// Split WAV,FLAC file into separate tracks using CUE sheet
+    Split(SplitArgs),
+    /// Print MusicBrainz, freedb, AccurateRip and CUETools disc IDs of a CD
+    /// image
+    DiscId {
+        /// Path to input CUE sheet or FLAC file with an embedded cuesheet
+        #[arg(long, short, required = true)]
+        input: PathBuf,
+    },
+    /// Compare the samples of existing files: split tracks with their image or
+    /// the files of a multiple file CUE sheet with the single file
+    Verify {
+        /// Tracks to check: audio files, directories of audio files or a CUE
+        /// sheet
         #[arg(long, short, required = true, value_delimiter = ',', num_args = 1..)]
         input: Vec<PathBuf>,
-        /// Output WAV file
+        /// Image the tracks were split from or merged into: audio file, CUE
+        /// sheet or FLAC file with an embedded cuesheet
+        #[arg(long, required = true)]
+        image: PathBuf,
+    },
+    /// Print the album metadata, audio spec and track layout of a CUE sheet
+    /// or audio file
+    Info {
+        /// Path to input CUE sheet, FLAC file with an embedded cuesheet or
+        /// audio file
         #[arg(long, short, required = true)]
-        output: PathBuf,
+        input: PathBuf,
     },
-    /// Split WAV,FLAC file into separate tracks using CUE sheet
-    Split {
-        /// Create multiple file CUE sheet
-        #[arg(long, short, default_value = "false")]
-        cue: bool,
-        /// Path to input CUE sheet
+    /// Check a CUE sheet for problems a split would fail on or split wrong,
+    /// split runs the same checks first
+    Lint {
+        /// Path to input CUE sheet or FLAC file with an embedded cuesheet
         #[arg(long, short, required = true)]
         input: PathBuf,
-        /// Output directory for splitted tracks
-        #[arg(long, short)]
-        output_dir: Option<PathBuf>,
-        /// Make sure output files samples matches input file samples
-        #[arg(long, short, default_value = "false")]
-        verify: bool,
-        /// File name format for splitted tracks
-        #[arg(long, short)]
-        format: Option<String>,
     },
     /// Print examples
     Examples {},
 }
+
+impl Commands {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Commands::Merge(_) => "merge",
+            Commands::Split(_) => "split",
+            Commands::DiscId { .. } => "disc-id",
+            Commands::Verify { .. } => "verify",
+            Commands::Info { .. } => "info",
+            Commands::Lint { .. } => "lint",
+            Commands::Examples {} => "examples",
+        }
+    }
+}
+
+#[derive(Args, Clone)]
+pub struct MergeArgs {
+    /// Create CUE sheet
+    #[arg(long, short, default_value = "false")]
+    pub cue: bool,
+    /// Set CUE album name, taken from the input tags by default
+    #[arg(long, short)]
+    pub title: Option<String>,
+    /// Set CUE artist, taken from the input tags by default
+    #[arg(long, short)]
+    pub performer: Option<String>,
+    /// Add REM comments to CUE
+    #[arg(long, short)]
+    pub rem: Option<Vec<String>>,
+    /// Make sure input files samples matches output file samples
+    #[arg(long, short, default_value = "false")]
+    pub verify: bool,
+    /// Input files
+    #[arg(long, short, required = true, value_delimiter = ',', num_args = 1..)]
+    pub input: Vec<PathBuf>,
+    /// Output WAV,FLAC file
+    #[arg(long, short, required = true)]
+    pub output: PathBuf,
+    /// Output file format, taken from the output file extension by default
+    #[arg(long)]
+    pub output_format: Option<OutputFormat>,
+    /// FLAC compression level
+    #[arg(long, default_value = "5", value_parser = clap::value_parser!(u8).range(0..=8))]
+    pub compression_level: u8,
+    #[command(flatten)]
+    pub convert: ConvertArgs,
+    /// Rounding of CUE track positions that fall between two CD frames
+    #[arg(long, default_value = "nearest")]
+    pub msf_rounding: MsfRounding,
+    #[command(flatten)]
+    pub cue_format: CueFormatArgs,
+    /// Order of the input files
+    #[arg(long, default_value = "none")]
+    pub sort: InputSort,
+    /// Print the tracks and output files without writing anything, fails
+    /// on problems such as missing performers or tracks past the end
+    #[arg(long, default_value = "false")]
+    pub dry_run: bool,
+}
+
+#[derive(Args, Clone)]
+pub struct SplitArgs {
+    /// Create multiple file CUE sheet
+    #[arg(long, short, default_value = "false")]
+    pub cue: bool,
+    /// Path to input CUE sheet or FLAC file with an embedded cuesheet, a
+    /// folder with --recursive
+    #[arg(long, short, required = true)]
+    pub input: PathBuf,
+    /// Split every CUE sheet below the input folder whose audio files
+    /// exist, each into its own folder or the same folder below
+    /// --output-dir, and skip albums that were split before
+    #[arg(long, short, default_value = "false")]
+    pub recursive: bool,
+    /// Split the albums --recursive skips because they were split before
+    #[arg(long, default_value = "false")]
+    pub resplit: bool,
+    /// Output directory for splitted tracks, created if missing
+    #[arg(long, short)]
+    pub output_dir: Option<PathBuf>,
+    /// Make sure output files samples matches input file samples
+    #[arg(long, short, default_value = "false")]
+    pub verify: bool,
+    /// File name format for splitted tracks, "%track% %artist% - %title%"
+    /// by default. Fields: track, title, artist, album, albumartist, date,
+    /// genre, isrc, discnumber, totaldiscs, totaltracks and rem:KEY.
+    /// %track:3% pads to 3 digits, %artist|albumartist|'Unknown'% falls
+    /// back, [%discnumber%-] is left out if a field in it is missing, / makes
+    /// folders such as %albumartist%/%album%/CD%discnumber%/%title% and %%
+    /// is a literal %
+    #[arg(long, short)]
+    pub format: Option<String>,
+    /// Output format for splitted tracks
+    #[arg(long, default_value = "wav")]
+    pub output_format: OutputFormat,
+    /// FLAC compression level
+    #[arg(long, default_value = "5", value_parser = clap::value_parser!(u8).range(0..=8))]
+    pub compression_level: u8,
+    /// Where to put the track pregaps (INDEX 00 to INDEX 01)
+    #[arg(long, default_value = "append")]
+    pub gaps: GapsMode,
+    /// Also write an id3 chunk with the tags to WAV tracks
+    #[arg(long, default_value = "false")]
+    pub id3: bool,
+    #[command(flatten)]
+    pub accuraterip: AccurateRipArgs,
+    /// Tracks to split such as 3,5-7, all by default. 0 is the audio
+    /// before track 1, the multiple file CUE sheet only has these tracks
+    #[arg(long, value_delimiter = ',', num_args = 1.., value_parser = parse_track_range)]
+    pub tracks: Vec<TrackRange>,
+    /// Tracks written at the same time, each read from its start in the
+    /// image. --accuraterip writes one track at a time
+    #[arg(long, short, default_value = "1", value_parser = clap::value_parser!(u16).range(1..))]
+    pub jobs: u16,
+    #[command(flatten)]
+    pub cue_format: CueFormatArgs,
+    /// Print the tracks and output files without writing anything, fails
+    /// on problems such as missing performers or tracks past the end
+    #[arg(long, default_value = "false")]
+    pub dry_run: bool,
+}
+
+#[derive(Args, Clone, Default)]
+pub struct ConvertArgs {
+    /// Convert input files which audio spec differs from the output audio spec
+    #[arg(long, default_value = "false")]
+    pub convert: bool,
+    /// Output sample rate, taken from the first input file by default
+    #[arg(long)]
+    pub sample_rate: Option<u32>,
+    /// Output bits per sample, taken from the first input file by default
+    #[arg(long)]
+    pub bits_per_sample: Option<u16>,
+    /// Output channels, taken from the first input file by default
+    #[arg(long)]
+    pub channels: Option<u16>,
+    /// Output sample format, taken from the first input file by default
+    #[arg(long)]
+    pub sample_format: Option<OutputSampleFormat>,
+}
+
+#[derive(Args, Clone, Default)]
+pub struct AccurateRipArgs {
+    /// Compute AccurateRip v1 and v2 CRCs of the tracks, needs CD audio
+    #[arg(long, default_value = "false")]
+    pub accuraterip: bool,
+    /// Compare the CRCs with AccurateRip .bin response files, implies
+    /// --accuraterip
+    #[arg(long, value_delimiter = ',', num_args = 1..)]
+    pub accuraterip_bin: Vec<PathBuf>,
+}
+
+impl AccurateRipArgs {
+    pub fn enabled(&self) -> bool {
+        self.accuraterip || !self.accuraterip_bin.is_empty()
+    }
+}
+
+/// Layout of the written CUE sheets, a dialect with single details changed.
+#[derive(Args, Clone, Default)]
+pub struct CueFormatArgs {
+    /// Write CUE sheets the way this program or player expects them
+    #[arg(long, default_value = "wfcue")]
+    pub cue_dialect: CueDialect,
+    /// CUE sheet line endings, taken from the dialect by default
+    #[arg(long)]
+    pub cue_line_endings: Option<LineEndings>,
+    /// Start CUE sheets with a UTF-8 BOM, taken from the dialect by default
+    #[arg(long)]
+    pub cue_bom: Option<bool>,
+    /// Type of the FILE entries, taken from the dialect by default
+    #[arg(long)]
+    pub cue_file_type: Option<CueFileType>,
+    /// Write the sample exact REM DURATION of every track, taken from the
+    /// dialect by default
+    #[arg(long)]
+    pub cue_rem_duration: Option<bool>,
+}
+
+#[derive(Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum CueDialect {
+    /// LF, every string quoted, track REM lines after INDEX, REM DURATION
+    #[default]
+    Wfcue,
+    /// Exact Audio Copy: CRLF, UTF-8 BOM, track REM lines before INDEX
+    Eac,
+    /// foobar2000: CRLF, track REM lines before INDEX
+    Foobar2000,
+    /// X Lossless Decoder: LF, track REM lines before INDEX
+    Xld,
+    /// CDRWIN spec: LF, strings quoted only if they have spaces, no
+    /// non-standard REM DURATION
+    Strict,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum LineEndings {
+    Lf,
+    Crlf,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum CueFileType {
+    Wave,
+    Binary,
+}
+
+/// Track numbers `first` to `last`, from `3` or `5-7`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TrackRange {
+    pub first: usize,
+    pub last: usize,
+}
+
+impl TrackRange {
+    pub fn contains(&self, track: usize) -> bool {
+        (self.first..=self.last).contains(&track)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum OutputSampleFormat {
+    Int,
+    Float,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    Wav,
+    Flac,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum MsfRounding {
+    Nearest,
+    Down,
+    Up,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum InputSort {
+    /// File names with numbers compared by value, 2 before 10
+    Natural,
+    /// Disc and track number tags, files without them last
+    Tracknumber,
+    /// As given
+    None,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum GapsMode {
+    /// Append to the previous track, audio before track 1 goes to a hidden
+    /// track file 00
+    Append,
+    /// Prepend to the track
+    Prepend,
+    /// Leave out of the split tracks
+    Discard,
+}
+
+fn parse_encoding(label: &str) -> Result<&'static Encoding, String> {
+    Encoding::for_label(label.as_bytes()).ok_or(format!("Unknown encoding: {}", label))
+}
+
+fn parse_track_range(value: &str) -> Result<TrackRange, String> {
+    let number = |n: &str| {
+        n.trim()
+            .parse::<usize>()
+            .map_err(|_| format!("Invalid track number: {}", n))
+    };
+
+    let (first, last) = match value.split_once('-') {
+        Some((first, last)) => (number(first)?, number(last)?),
+        None => (number(value)?, number(value)?),
+    };
+
+    if first > last {
+        return Err(format!("Track range {} goes backwards", value));
+    }
+
+    Ok(TrackRange { first, last })
+}
@@ -1,37 +1,93 @@
-use std::{fs, path::PathBuf};
+use std::{
+    cmp::Ordering,
+    fs,
+    path::{Path, PathBuf},
+};
 
 use anyhow::{bail, Context};
-use cue_rw::CUEFile;
-use hound::{SampleFormat, WavWriter};
+use encoding_rs::UTF_8;
+use hound::{SampleFormat, WavSpec};
+use serde_json::json;
 
 use crate::{
+    accuraterip::{self, AccurateRip},
     audio::{
-        flac_copy_samples, flac_split_samples, get_audio_info, verify_samples, wav_copy_samples,
-        wav_split_samples, Duration,
+        flac_copy_samples, flac_split_samples, flac_split_samples_parallel, format_spec,
+        get_audio_info, get_output_format, wav_copy_samples, wav_split_samples,
+        wav_split_samples_parallel, AudioWriter, Duration,
+    },
+    cli::{Cli, GapsMode, InputSort, MergeArgs, MsfRounding, SplitArgs},
+    convert::{check_input_specs, ConvertedSample, SpecConverter},
+    cue::{
+        album_performer, file_track_bounds, file_tracks, has_embedded_cuesheet, index_to_samples,
+        merge_create_cue, merge_cue_path, read_cue_text, rem_value, split_create_cue,
+        split_cue_path, track_disc_numbers, CueIndex, CueSheet,
     },
-    cli::Cli,
-    cue::{cue_msf_to_samples, merge_create_cue, split_create_cue},
-    utils::{promt_output_in_input, promt_overwrite},
+    disc_id::{print_disc_ids, Toc},
+    lint::{lint_cue_sheet, report_issues, Severity},
+    plan::{LengthSource, Plan, PlannedTrack},
+    report::{FileEntry, Report},
+    tags::Tags,
+    template::{unique_file_name, Template, TemplateFields, DEFAULT_FORMAT},
+    track_msf::TrackMSF,
+    utils::{natural_cmp, progress, promt_output_in_input, promt_overwrite, warn},
+    verify::{verify_tracks, OutputVerifier, SampleSource, Segment},
 };
 
 pub fn merge(
-    cue: bool,
-    title: &Option<String>,
-    performer: &Option<String>,
-    rem: &Option<Vec<String>>,
-    verify: bool,
-    input: &Vec<PathBuf>,
-    output: &PathBuf,
+    args: &MergeArgs,
     cli: &Cli,
+    report: &mut Report,
 ) -> Result<Vec<PathBuf>, anyhow::Error> {
-    if !cli.totally_silent && input.contains(output) {
-        if !promt_output_in_input(output).context("Promt failed")? {
-            return Ok(vec![]);
+    let &MergeArgs {
+        cue,
+        verify,
+        ref input,
+        ref output,
+        output_format,
+        compression_level,
+        ref convert,
+        sort,
+        dry_run,
+        ref performer,
+        ..
+    } = args;
+    if !cli.totally_silent
+        && !dry_run
+        && input.contains(output)
+        && !promt_output_in_input(output).context("Promt failed")?
+    {
+        return Ok(vec![]);
+    }
+
+    let (input, tags) = sort_inputs(input, sort)?;
+    let input = &input;
+
+    // Input tags only go into the CUE sheet
+    let tags = match tags {
+        Some(tags) => tags,
+        None if cue => read_input_tags(input)?,
+        None => vec![Tags::default(); input.len()],
+    };
+
+    if !cli.silent {
+        progress!(cli, "Input order:");
+        for (i, file) in input.iter().enumerate() {
+            progress!(
+                cli,
+                "  {:>2}. {}",
+                i + 1,
+                file.file_name()
+                    .context("Failed to get file name")?
+                    .to_str()
+                    .context("to_str failed")?
+            );
         }
     }
 
     if !cli.silent {
-        println!(
+        progress!(
+            cli,
             "Reading properties of the audio data from {}",
             &input[0]
                 .file_name()
@@ -42,7 +98,8 @@ pub fn merge(
     }
 
     if !cli.silent {
-        println!(
+        progress!(
+            cli,
             "Output file {}",
             output
                 .file_name()
@@ -53,20 +110,107 @@ pub fn merge(
     }
 
     let audio_info = get_audio_info(&input[0])?;
+    let spec = convert.target_spec(audio_info.spec)?;
+    let output_format = get_output_format(output, &output_format)?;
     let mut durations: Vec<Duration> = vec![];
 
-    if !cli.force && fs::exists(output).context("Can't check existence of file")? {
-        if !promt_overwrite(output).context("Promt failed")? {
-            return Ok(vec![]);
+    // A dry run lists the problems of the plan instead of failing on them
+    let mut problems = vec![];
+
+    match check_input_specs(input, spec, convert.enabled()) {
+        Err(e) if dry_run => problems.push(e.to_string()),
+        result => result?,
+    }
+
+    let mut lengths: Vec<u64> = vec![];
+    for file in input.iter() {
+        let info = get_audio_info(file)?;
+        let frames = info.total_samples / info.spec.channels as u64;
+        report
+            .inputs
+            .push(FileEntry::audio(file, frames, info.spec.sample_rate));
+
+        // Files that don't match without --convert are already a problem
+        let length = if info.spec == spec || !convert.enabled() {
+            frames
+        } else {
+            match SpecConverter::new(info.spec, spec) {
+                Ok(converter) => converter.output_frames(frames),
+                Err(e) if dry_run => {
+                    problems.push(format!("{}: {:#}", file.display(), e));
+                    frames
+                }
+                Err(e) => return Err(e.context(format!("Can't convert {}", file.display()))),
+            }
+        };
+        lengths.push(length);
+    }
+    let expected_samples: u64 = lengths.iter().sum();
+
+    if dry_run {
+        let mut plan = Plan::default();
+        let mut start = 0;
+
+        plan.problems.extend(problems);
+        if input.contains(output) {
+            plan.problems
+                .push(format!("{} is also an input file", output.display()));
+        }
+
+        let mut durations: Vec<Duration> = vec![];
+        for (k, ((file, length), tags)) in input.iter().zip(&lengths).zip(&tags).enumerate() {
+            plan.tracks.push(PlannedTrack {
+                number: k + 1,
+                file: file.clone(),
+                start,
+                length: *length,
+                sample_rate: spec.sample_rate,
+                source: LengthSource::Header,
+            });
+            durations.push(Duration {
+                file: file.clone(),
+                start_samples: start,
+                duration_samples: *length,
+                tags: tags.clone(),
+            });
+            start += length;
+        }
+
+        plan.outputs.push(output.clone());
+        if cue {
+            if album_performer(performer, &durations).is_none() {
+                plan.problems.push(
+                    "No album PERFORMER in the input tags, set one with --performer".to_string(),
+                );
+            }
+            plan.outputs.push(merge_cue_path(output)?);
         }
+
+        return plan.finish(cli, report);
     }
 
-    let mut output_wav =
-        WavWriter::create(output, audio_info.spec).context("Failed to create output WAV file")?;
+    if !cli.force
+        && fs::exists(output).context("Can't check existence of file")?
+        && !promt_overwrite(output).context("Promt failed")?
+    {
+        return Ok(vec![]);
+    }
 
-    for file in input.iter() {
+    let mut output_wav = AudioWriter::create(
+        output,
+        spec,
+        output_format,
+        compression_level,
+        expected_samples,
+        &Tags::default(),
+    )?;
+    let verifier = verify.then(OutputVerifier::new);
+    let mut segments: Vec<Segment> = vec![];
+
+    for (file, tags) in input.iter().zip(&tags) {
         if !cli.silent {
-            println!(
+            progress!(
+                cli,
                 "Merging {} ...",
                 &file
                     .file_name()
@@ -76,6 +220,10 @@ pub fn merge(
             );
         }
 
+        let file_info = get_audio_info(file)?;
+        let file_spec = file_info.spec;
+        let mut hasher = verifier.as_ref().map(OutputVerifier::hasher);
+
         let duration_samples = match file
             .extension()
             .context("Failed to get file extension")?
@@ -84,173 +232,935 @@ pub fn merge(
             .to_lowercase()
             .as_ref()
         {
-            "wav" => match audio_info.spec.sample_format {
-                SampleFormat::Float => wav_copy_samples::<f32>(&file, &mut output_wav, cli)
-                    .context("Failed to copy samples")?,
-                SampleFormat::Int => wav_copy_samples::<i32>(&file, &mut output_wav, cli)
-                    .context("Failed to copy samples")?,
+            _ if file_spec != spec => SpecConverter::new(file_spec, spec)?
+                .convert_file(file, Some(cli), |s| {
+                    match s {
+                        ConvertedSample::Int(v) => {
+                            output_wav.write_sample(v)?;
+                            if let Some(ref mut h) = hasher {
+                                h.update_sample(&v)?;
+                            }
+                        }
+                        ConvertedSample::Float(v) => {
+                            output_wav.write_sample(v)?;
+                            if let Some(ref mut h) = hasher {
+                                h.update_sample(&v)?;
+                            }
+                        }
+                    }
+                    Ok(())
+                })
+                .context("Failed to convert samples")?,
+            "wav" => match spec.sample_format {
+                SampleFormat::Float => {
+                    wav_copy_samples::<f32>(file, &mut output_wav, &mut hasher, cli)
+                        .context("Failed to copy samples")?
+                }
+                SampleFormat::Int => {
+                    wav_copy_samples::<i32>(file, &mut output_wav, &mut hasher, cli)
+                        .context("Failed to copy samples")?
+                }
             },
-            "flac" => {
-                flac_copy_samples(&file, &mut output_wav, cli).context("Failed to copy samples")?
-            }
+            "flac" => flac_copy_samples(file, &mut output_wav, &mut hasher, cli)
+                .context("Failed to copy samples")?,
             _ => bail!("Unsupported format"),
         };
 
-        let duration_seconds = duration_samples as f64 / audio_info.spec.sample_rate as f64;
         let duration = Duration {
             file: file.clone(),
-            duration_seconds,
+            start_samples: durations
+                .last()
+                .map(|d| d.start_samples + d.duration_samples)
+                .unwrap_or(0),
             duration_samples,
+            tags: tags.clone(),
         };
 
         durations.push(duration);
+
+        if let Some(hasher) = hasher {
+            segments.push(Segment {
+                name: file
+                    .file_name()
+                    .context("Failed to get file name")?
+                    .to_string_lossy()
+                    .to_string(),
+                source: if file_spec == spec {
+                    SampleSource::Range {
+                        file: file.clone(),
+                        start: 0,
+                        length: file_info.total_samples,
+                    }
+                } else {
+                    SampleSource::Converted {
+                        file: file.clone(),
+                        spec,
+                    }
+                },
+                hasher,
+            });
+        }
     }
 
-    output_wav
-        .finalize()
-        .context("Failed to update the WAVE header")?;
+    output_wav.finalize()?;
+    report.outputs.push(FileEntry::audio(
+        output,
+        durations.iter().map(|d| d.duration_samples).sum(),
+        spec.sample_rate,
+    ));
 
-    if verify {
-        verify_samples(audio_info.spec.sample_format, &input, output, cli)?;
+    if let Some(verifier) = verifier {
+        verifier.check(output, spec, segments)?;
+        verifier.finish(cli, report)?;
     }
 
     if cue {
-        merge_create_cue(title, performer, &rem, output, &durations, cli)?;
+        merge_create_cue(args, &durations, spec.sample_rate, cli, report)?;
     }
 
     Ok(vec![output.clone()])
 }
 
+/// Returns the sorted inputs and their tags if they had to be read to sort
+/// them.
+fn sort_inputs(
+    input: &[PathBuf],
+    sort: InputSort,
+) -> Result<(Vec<PathBuf>, Option<Vec<Tags>>), anyhow::Error> {
+    let mut input = input.to_vec();
+
+    match sort {
+        InputSort::Natural => input.sort_by(|a, b| natural_cmp(a, b)),
+        InputSort::Tracknumber => {
+            let mut tagged: Vec<(Tags, PathBuf)> =
+                read_input_tags(&input)?.into_iter().zip(input).collect();
+
+            let key = |t: &Tags| {
+                (
+                    t.track_number.is_none(),
+                    t.disc_number.unwrap_or(1),
+                    t.track_number.unwrap_or(0),
+                )
+            };
+            tagged
+                .sort_by(|(a, fa), (b, fb)| key(a).cmp(&key(b)).then_with(|| natural_cmp(fa, fb)));
+
+            let (tags, input) = tagged.into_iter().unzip();
+            return Ok((input, Some(tags)));
+        }
+        InputSort::None => (),
+    }
+
+    Ok((input, None))
+}
+
+fn read_input_tags(input: &[PathBuf]) -> Result<Vec<Tags>, anyhow::Error> {
+    input
+        .iter()
+        .map(|f| Tags::read(f).with_context(|| format!("Failed to read tags of {}", f.display())))
+        .collect()
+}
+
+/// Splits the image of the CUE sheet `args.input`, `args.recursive` is left
+/// to the caller.
 pub fn split(
-    cue: bool,
-    input: &PathBuf,
-    output_dir: &Option<PathBuf>,
-    verify: bool,
-    format: &Option<String>,
+    args: &SplitArgs,
     cli: &Cli,
+    report: &mut Report,
 ) -> Result<Vec<PathBuf>, anyhow::Error> {
-    let cue_text = fs::read_to_string(input).context("Failed to read CUE file")?;
-    let cue_file = CUEFile::try_from(cue_text.as_ref()).context("Failed to parse CUE sheet")?;
+    let &SplitArgs {
+        cue,
+        ref input,
+        ref output_dir,
+        verify,
+        ref format,
+        output_format,
+        gaps,
+        id3,
+        ref accuraterip,
+        ref tracks,
+        jobs,
+        dry_run,
+        ..
+    } = args;
+
+    let template = Template::try_from(format.as_deref().unwrap_or(DEFAULT_FORMAT))?;
+    let issues = lint_cue_sheet(input, cli.cue_encoding)?;
+
+    // A dry run lists the errors as problems of the plan
+    if dry_run && issues.iter().any(|i| i.severity == Severity::Error) {
+        let mut plan = Plan::default();
+        for issue in issues {
+            match issue.severity {
+                Severity::Error => plan.problems.push(issue.to_string()),
+                Severity::Warning => warn(cli, report, "lint", issue.to_string()),
+            }
+        }
+        report.inputs.push(FileEntry::new(input));
+        return plan.finish(cli, report);
+    }
 
-    let mut audio_file = input
-        .parent()
-        .context("Failed to get parent dir")?
-        .to_path_buf();
+    report_issues(&issues, cli, report)?;
 
-    audio_file.push(&cue_file.files[0]);
+    let cue_sheet = read_cue_sheet(input, cli)?;
+    let cue_file = &cue_sheet.cue;
 
-    if !cli.silent {
-        println!(
-            "Reading properties of the audio data from {}",
-            &audio_file
-                .file_name()
-                .context("Failed to get file name")?
-                .to_str()
-                .context("to_str failed")?
+    if let Some(range) = tracks.iter().find(|r| r.last > cue_file.tracks.len()) {
+        bail!(
+            "Track {} is not in the CUE sheet, it has {} tracks",
+            range.last,
+            cue_file.tracks.len()
         );
     }
 
-    let audio_info = get_audio_info(&audio_file)?;
+    // Track number 0 is the hidden track before track 1
+    let selected = |track: usize| tracks.is_empty() || tracks.iter().any(|r| r.contains(track));
+
+    let input_dir = input.parent().context("Failed to get parent dir")?;
+    let output_dir = match output_dir {
+        Some(d) => d.clone(),
+        None => input_dir.to_path_buf(),
+    };
+
     let mut durations: Vec<Duration> = vec![];
+    let mut output_wavs: Vec<PathBuf> = vec![];
+    // INDEX points of every track as (number, source file, inter-channel
+    // sample), mapped into the split files for the multiple file CUE sheet
+    let mut points: Vec<Vec<(u8, usize, u64)>> = vec![vec![]; cue_file.tracks.len()];
+    let mut split_indices: Vec<Vec<CueIndex>> = vec![vec![]; cue_file.tracks.len()];
+    let mut pregaps: Vec<Option<TrackMSF>> = vec![None; cue_file.tracks.len()];
+    let mut pregap_samples: Vec<u64> = vec![0; cue_file.tracks.len()];
+    let mut verifier: Option<OutputVerifier> = None;
+    let non_empty = |s: &String| !s.is_empty();
+    let album_tags = Tags {
+        album: Some(cue_file.title.clone()).filter(non_empty),
+        album_artist: Some(cue_file.performer.clone()).filter(non_empty),
+        track_total: Some(cue_file.tracks.len()),
+        date: rem_value(&cue_file.comments, "DATE"),
+        genre: rem_value(&cue_file.comments, "GENRE"),
+        id3_chunk: id3,
+        ..Default::default()
+    };
+    let disc_numbers = track_disc_numbers(cue_file);
+    let mut crcs = accuraterip
+        .enabled()
+        .then(|| AccurateRip::new(cue_file.tracks.len()));
+    let mut plan = dry_run.then(Plan::default);
 
-    if !cli.silent {
-        println!("Reading track info from CUE file ...");
+    // AccurateRip CRCs need the samples of the image in order
+    if jobs > 1 && crcs.is_some() && !dry_run {
+        warn(
+            cli,
+            report,
+            "jobs",
+            "--accuraterip reads the image in one pass, the tracks are written one at a time"
+                .to_string(),
+        );
     }
 
-    let mut cue_tracks_iter = cue_file.tracks.iter().peekable();
-    let mut track_num = 0;
+    // Lowercase paths of the track files below the output directory, for
+    // unique names
+    let mut file_names: Vec<String> = vec![];
+
+    report.inputs.push(FileEntry::new(input));
+
+    for (file_id, file) in cue_file.files.iter().enumerate() {
+        let (tracks_in_file, tail_track) = file_tracks(&cue_sheet, file_id);
+
+        if tracks_in_file.is_empty() && tail_track.is_none() {
+            continue;
+        }
+
+        let audio_file = input_dir.join(file);
 
-    loop {
-        let Some(track) = cue_tracks_iter.next() else {
-            break;
+        if !cli.silent {
+            progress!(
+                cli,
+                "Reading properties of the audio data from {}",
+                &audio_file
+                    .file_name()
+                    .context("Failed to get file name")?
+                    .to_str()
+                    .context("to_str failed")?
+            );
+        }
+
+        let audio_info = get_audio_info(&audio_file)
+            .with_context(|| format!("Failed to read {}", audio_file.display()))?;
+        let channels = audio_info.spec.channels as u64;
+        let sample_rate = audio_info.spec.sample_rate;
+        let length = audio_info.total_samples / channels;
+
+        // A FLAC file with an embedded cuesheet is the input itself
+        let entry = FileEntry::audio(&audio_file, length, sample_rate);
+        match report.inputs.iter_mut().find(|e| e.path == audio_file) {
+            Some(input) => *input = entry,
+            None => report.inputs.push(entry),
+        }
+
+        if !cli.silent {
+            progress!(cli, "Reading track info from CUE file ...");
+        }
+
+        let tail_start = match tail_track {
+            Some(i) => Some(index_to_samples(&cue_sheet.indices[i], 0, sample_rate)?),
+            None => None,
         };
 
-        let track = &track.1;
-        track_num += 1;
+        let track_bounds = file_track_bounds(
+            &cue_sheet,
+            file_id,
+            &tracks_in_file,
+            sample_rate,
+            tail_start,
+            length,
+        )?;
+
+        let rem_tracks: Vec<usize> = track_bounds
+            .iter()
+            .filter(|b| b.source == LengthSource::RemDuration)
+            .map(|b| b.track)
+            .collect();
+
+        // (track, pregap start, INDEX 01, end) in inter-channel samples
+        let bounds: Vec<(usize, u64, u64, u64)> = track_bounds
+            .iter()
+            .map(|b| (b.track, b.gap_start, b.start, b.end))
+            .collect();
+
+        let overruns: Vec<String> = bounds
+            .iter()
+            .filter(|(_, _, start, end)| start > end || *end > length)
+            .map(|(i, ..)| {
+                format!(
+                    "Track {} ends after the end of {}",
+                    i + 1,
+                    audio_file.display()
+                )
+            })
+            .collect();
 
-        let rem_duration = track.comments.iter().find_map(|s| {
-            if s.starts_with("DURATION ") {
-                Some(s)
-            } else {
-                None
+        match (overruns.first(), plan.as_mut()) {
+            (None, _) => (),
+            (Some(_), Some(plan)) => {
+                plan.problems.extend(overruns);
+                continue;
             }
-        });
+            (Some(overrun), None) => bail!("{}", overrun),
+        }
 
-        let duration = match rem_duration {
-            Some(rem) => {
-                let split: Vec<&str> = rem.split(" ").collect();
-                if split.len() < 2 {
-                    bail!("Failed to parse REM DURATION")
-                }
-                let samples = split[1]
-                    .parse::<u64>()
-                    .context("Failed to parse REM DURATION")?;
-                samples * audio_info.spec.channels as u64
+        for (i, gap_start, start, _) in &bounds {
+            if *start > *gap_start && !points[*i].iter().any(|p| p.0 == 0) {
+                points[*i].push((0, file_id, *gap_start));
             }
-            None => {
-                // Fallback to MSF
-                let peek_track = cue_tracks_iter.peek();
-                let track_pos = cue_msf_to_samples(&track.indices, audio_info.spec.sample_rate)?;
-
-                match peek_track {
-                    Some(next_track) => {
-                        let next_track_pos =
-                            cue_msf_to_samples(&next_track.1.indices, audio_info.spec.sample_rate)?;
-                        let samples = next_track_pos - track_pos;
-                        samples * audio_info.spec.channels as u64
+            pregap_samples[*i] += start - gap_start;
+            points[*i].push((1, file_id, *start));
+
+            for index in cue_sheet.indices[*i]
+                .iter()
+                .filter(|x| x.number > 1 && x.file == file_id)
+            {
+                points[*i].push((index.number, file_id, index.to_samples(sample_rate)));
+            }
+        }
+
+        if let (Some(j), Some(tail_start)) = (tail_track, tail_start) {
+            points[j].push((0, file_id, tail_start));
+            pregap_samples[j] += length - tail_start;
+        }
+
+        // Output files as (track, start, end), track is None for the hidden
+        // track before track 1
+        let mut ranges: Vec<(Option<usize>, u64, u64)> = vec![];
+
+        for (k, (i, gap_start, start, end)) in bounds.iter().enumerate() {
+            let last = k + 1 == bounds.len();
+
+            let range = match gaps {
+                GapsMode::Append => {
+                    let range_start = match (k, *i) {
+                        (0, 0) if start > gap_start => {
+                            ranges.push((None, *gap_start, *start));
+                            *start
+                        }
+                        (0, _) => *gap_start,
+                        _ => *start,
+                    };
+                    let range_end = match (last, tail_start) {
+                        (true, Some(_)) => length,
+                        (true, None) => *end,
+                        (false, _) => bounds[k + 1].2,
+                    };
+                    (range_start, range_end)
+                }
+                GapsMode::Prepend => {
+                    if last && tail_start.is_some() {
+                        bail!(
+                            "Track {} pregap is in the previous FILE, it can't be prepended",
+                            i + 2
+                        );
                     }
-                    None => {
-                        // Last track
-                        let mut samples: u64 =
-                            cue_msf_to_samples(&track.indices, audio_info.spec.sample_rate)?;
-                        samples =
-                            (audio_info.total_samples / audio_info.spec.channels as u64) - samples;
-                        samples * audio_info.spec.channels as u64
+                    (*gap_start, *end)
+                }
+                GapsMode::Discard => (*start, *end),
+            };
+
+            ranges.push((Some(*i), range.0, range.1));
+        }
+
+        ranges.retain(|(track, ..)| selected(track.map(|i| i + 1).unwrap_or(0)));
+
+        let mut file_durations: Vec<Duration> = vec![];
+
+        for (track, start, end) in &ranges {
+            let mut tags = album_tags.clone();
+            match track {
+                Some(i) => {
+                    let track = &cue_file.tracks[*i].1;
+                    tags.title = Some(track.title.clone());
+                    tags.artist = track.performer.clone().or(tags.album_artist.clone());
+                    tags.track_number = Some(i + 1);
+                    tags.disc_number = disc_numbers[*i];
+                    tags.isrc = track.isrc.clone();
+                }
+                None => {
+                    tags.title = Some("Hidden Track".to_string());
+                    tags.disc_number = disc_numbers[0];
+                    tags.artist = cue_file.tracks[0]
+                        .1
+                        .performer
+                        .clone()
+                        .or(tags.album_artist.clone());
+                }
+            }
+
+            let track_num = tags.track_number.unwrap_or(0);
+
+            if tags.artist.is_none() {
+                let problem = format!("Track {} has no PERFORMER", track_num);
+                match plan.as_mut() {
+                    Some(plan) => plan.problems.push(problem),
+                    None => warn(cli, report, "missing_performer", problem),
+                }
+            }
+
+            let mut comments = match track {
+                Some(i) => cue_file.tracks[*i].1.comments.clone(),
+                None => vec![],
+            };
+            comments.extend(cue_file.comments.iter().cloned());
+
+            let name = Some(template.render(&TemplateFields {
+                tags: &tags,
+                comments,
+            }))
+            .filter(|n| !n.as_os_str().is_empty())
+            .unwrap_or(PathBuf::from(format!("{:02}", track_num)));
+            let output_filename =
+                unique_file_name(name, output_format.extension(), &mut file_names);
+
+            if let Some(ref mut plan) = plan {
+                plan.tracks.push(PlannedTrack {
+                    number: track_num,
+                    file: output_dir.join(&output_filename),
+                    start: *start,
+                    length: end - start,
+                    sample_rate,
+                    source: match track {
+                        _ if *end == length => LengthSource::FileEnd,
+                        Some(i) if rem_tracks.contains(i) => LengthSource::RemDuration,
+                        _ => LengthSource::Msf,
+                    },
+                });
+            }
+
+            file_durations.push(Duration {
+                file: output_dir.join(output_filename),
+                start_samples: start * channels,
+                duration_samples: (end - start) * channels,
+                tags,
+            });
+        }
+
+        // Map the INDEX points of this file into the split files
+        for (i, track_points) in points.iter().enumerate() {
+            for (number, _, position) in track_points.iter().filter(|p| p.1 == file_id) {
+                let output = ranges
+                    .iter()
+                    .position(|(_, start, end)| start <= position && position < end);
+
+                match output {
+                    Some(o) => split_indices[i].push(CueIndex {
+                        number: *number,
+                        file: durations.len() + o,
+                        position: TrackMSF::from_samples(
+                            position - ranges[o].1,
+                            sample_rate,
+                            MsfRounding::Nearest,
+                        ),
+                        samples: None,
+                    }),
+                    None if *number == 0 => {
+                        pregaps[i] = Some(TrackMSF::from_samples(
+                            pregap_samples[i],
+                            sample_rate,
+                            MsfRounding::Nearest,
+                        ))
                     }
+                    None => (),
                 }
             }
-        };
+        }
 
-        let mut output_file = PathBuf::new();
+        if let Some(ref mut plan) = plan {
+            plan.outputs
+                .extend(file_durations.iter().map(|d| d.file.clone()));
+            durations.extend(file_durations);
+            continue;
+        }
 
-        match output_dir {
-            Some(d) => output_file.push(d),
-            None => {
-                output_file.push(
-                    input
-                        .parent()
-                        .context("Failed to get input file parent dir")?,
-                );
+        if let Some(ref mut crcs) = crcs {
+            // AccurateRip tracks go from INDEX 01 to the next INDEX 01, audio
+            // before the first INDEX 01 of a file belongs to the previous track
+            let mut ar_ranges: Vec<(usize, u64, u64)> = vec![];
+            if let Some((i, _, start, _)) = bounds.first() {
+                if *i > 0 && *start > 0 {
+                    ar_ranges.push((i - 1, 0, *start));
+                }
             }
+            for (k, (i, _, start, _)) in bounds.iter().enumerate() {
+                let end = bounds.get(k + 1).map(|b| b.2).unwrap_or(length);
+                ar_ranges.push((*i, *start, end));
+            }
+
+            crcs.begin_file(audio_info.spec, ar_ranges)?;
+        }
+
+        if verify && verifier.is_none() {
+            verifier = Some(OutputVerifier::new());
+        }
+
+        for duration in &file_durations {
+            let dir = duration.file.parent().context("Failed to get parent dir")?;
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+
+        let file_outputs = split_audio_file(
+            &audio_file,
+            audio_info.spec,
+            &file_durations,
+            args,
+            verifier.as_ref(),
+            crcs.as_mut(),
+            cli,
+        )?;
+
+        report.outputs.extend(
+            file_durations
+                .iter()
+                .filter(|d| file_outputs.contains(&d.file))
+                .map(|d| FileEntry::audio(&d.file, d.duration_samples / channels, sample_rate)),
+        );
+
+        durations.extend(file_durations);
+        output_wavs.extend(file_outputs);
+    }
+
+    if let Some(mut plan) = plan {
+        if cue {
+            plan.outputs.push(split_cue_path(input)?);
         }
+        return plan.finish(cli, report);
+    }
+
+    if let Some(verifier) = verifier {
+        verifier.finish(cli, report)?;
+    }
+
+    if let Some(crcs) = crcs {
+        accuraterip::report(&crcs.finish(), &accuraterip.accuraterip_bin, cli, report)?;
+    }
+
+    if cue {
+        // Tracks that are left out are left out of the CUE sheet too
+        let keep: Vec<bool> = (1..=cue_file.tracks.len()).map(selected).collect();
+        let mut cue_file = cue_file.clone();
+        cue_file.tracks = retain_selected(cue_file.tracks, &keep);
 
-        let output_filename = match format {
-            Some(f) => {
-                f.replace("%track%", &format!("{:02}", track_num))
-                    .replace(
-                        "%artist%",
-                        &track.performer.clone().context("Failed to get performer")?,
-                    )
-                    .replace("%title%", &track.title)
-                    + ".wav"
+        let output_cue = split_create_cue(
+            &cue_file,
+            args,
+            &durations,
+            retain_selected(split_indices, &keep),
+            retain_selected((1..=keep.len()).collect(), &keep),
+            retain_selected(pregaps, &keep),
+            cli,
+        )?;
+        report
+            .outputs
+            .extend(output_cue.map(|f| FileEntry::new(&f)));
+    }
+
+    Ok(output_wavs)
+}
+
+fn retain_selected<T>(items: Vec<T>, keep: &[bool]) -> Vec<T> {
+    items
+        .into_iter()
+        .zip(keep)
+        .filter(|(_, keep)| **keep)
+        .map(|(item, _)| item)
+        .collect()
+}
+
+pub fn disc_id(
+    input: &Path,
+    cli: &Cli,
+    report: &mut Report,
+) -> Result<Vec<PathBuf>, anyhow::Error> {
+    let cue_sheet = read_cue_sheet(input, cli)?;
+    let input_dir = input.parent().context("Failed to get parent dir")?;
+
+    let toc = Toc::from_cue(&cue_sheet, input_dir)?;
+    print_disc_ids(&toc, cli, report);
+
+    Ok(vec![])
+}
+
+pub fn verify(
+    input: &[PathBuf],
+    image: &Path,
+    cli: &Cli,
+    report: &mut Report,
+) -> Result<Vec<PathBuf>, anyhow::Error> {
+    let image_files = audio_files(&[image.to_path_buf()], cli)?;
+    let image_paths = image_files
+        .iter()
+        .map(fs::canonicalize)
+        .collect::<Result<Vec<PathBuf>, _>>()
+        .context("Failed to find the image files")?;
+
+    // A directory of split tracks may also hold the image
+    let mut tracks = vec![];
+    for file in audio_files(input, cli)? {
+        if !image_paths.contains(&fs::canonicalize(&file).context("Failed to find track")?) {
+            tracks.push(file);
+        }
+    }
+
+    if tracks.is_empty() {
+        bail!("No tracks to verify");
+    }
+
+    for file in tracks.iter().chain(&image_files) {
+        let info = get_audio_info(file)?;
+        report.inputs.push(FileEntry::audio(
+            file,
+            info.total_samples / info.spec.channels as u64,
+            info.spec.sample_rate,
+        ));
+    }
+
+    verify_tracks(&tracks, &image_files, cli, report)?;
+
+    Ok(vec![])
+}
+
+/// Audio files of CUE sheets, directories (in natural order) and audio
+/// files.
+fn audio_files(input: &[PathBuf], cli: &Cli) -> Result<Vec<PathBuf>, anyhow::Error> {
+    let is_audio = |file: &PathBuf| {
+        file.extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case("wav") || e.eq_ignore_ascii_case("flac"))
+    };
+
+    let mut files = vec![];
+
+    for path in input {
+        if path.is_dir() {
+            let mut dir_files = fs::read_dir(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?
+                .map(|entry| entry.map(|e| e.path()))
+                .collect::<Result<Vec<PathBuf>, _>>()
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            dir_files.retain(|f| f.is_file() && is_audio(f));
+            dir_files.sort_by(|a, b| natural_cmp(a, b));
+            files.extend(dir_files);
+        } else if is_audio(path) {
+            files.push(path.clone());
+        } else {
+            let cue_sheet = read_cue_sheet(path, cli)?;
+            let cue_dir = path.parent().context("Failed to get parent dir")?;
+            files.extend(cue_sheet.cue.files.iter().map(|f| cue_dir.join(f)));
+        }
+    }
+
+    Ok(files)
+}
+
+pub fn info(input: &Path, cli: &Cli, report: &mut Report) -> Result<Vec<PathBuf>, anyhow::Error> {
+    let extension = input
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+
+    let cue_sheet = match extension.as_deref() {
+        Some("wav") => None,
+        Some("flac") if !has_embedded_cuesheet(input)? => None,
+        _ => Some(read_cue_sheet(input, cli)?),
+    };
+
+    report.inputs.push(FileEntry::new(input));
+
+    let msf = |samples: u64, sample_rate: u32| {
+        TrackMSF::from_samples(samples, sample_rate, MsfRounding::Nearest)
+    };
+    let print = !cli.totally_silent;
+
+    let Some(cue_sheet) = cue_sheet else {
+        let audio_info = get_audio_info(input)?;
+        let length = audio_info.total_samples / audio_info.spec.channels as u64;
+        let tags = Tags::read(input)
+            .with_context(|| format!("Failed to read tags of {}", input.display()))?
+            .vorbis_comments();
+
+        if print {
+            progress!(cli, "Audio:     {}", format_spec(&audio_info.spec));
+            progress!(
+                cli,
+                "Length:    {} ({} samples)",
+                msf(length, audio_info.spec.sample_rate),
+                length
+            );
+            for tag in &tags {
+                progress!(cli, "Tag:       {}", tag);
+            }
+        }
+
+        report.results.push((
+            "info",
+            json!({
+                "spec": format_spec(&audio_info.spec),
+                "samples": length,
+                "tags": tags,
+            }),
+        ));
+
+        return Ok(vec![]);
+    };
+
+    let cue_file = &cue_sheet.cue;
+    let input_dir = input.parent().context("Failed to get parent dir")?;
+
+    if print {
+        progress!(cli, "Title:     {}", cue_file.title);
+        progress!(cli, "Performer: {}", cue_file.performer);
+        for comment in &cue_file.comments {
+            progress!(cli, "REM        {}", comment);
+        }
+        progress!(cli, "Tracks:    {}", cue_file.tracks.len());
+    }
+
+    let mut files = vec![];
+
+    for (file_id, file) in cue_file.files.iter().enumerate() {
+        let (tracks, tail_track) = file_tracks(&cue_sheet, file_id);
+        let audio_file = input_dir.join(file);
+
+        if print {
+            progress!(cli, "FILE \"{}\"", file);
+        }
+
+        let audio_info = match get_audio_info(&audio_file) {
+            Ok(audio_info) => audio_info,
+            Err(e) => {
+                warn(
+                    cli,
+                    report,
+                    "missing_audio",
+                    format!("Failed to read {}: {}", audio_file.display(), e),
+                );
+                continue;
             }
-            None => format!(
-                "{:02} {} - {}.wav",
-                track_num,
-                track.performer.clone().unwrap_or("Artist".to_string()),
-                track.title
-            ),
         };
+        let sample_rate = audio_info.spec.sample_rate;
+        let length = audio_info.total_samples / audio_info.spec.channels as u64;
+
+        let tail_start = match tail_track {
+            Some(i) => Some(index_to_samples(&cue_sheet.indices[i], 0, sample_rate)?),
+            None => None,
+        };
+        let bounds = file_track_bounds(
+            &cue_sheet,
+            file_id,
+            &tracks,
+            sample_rate,
+            tail_start,
+            length,
+        )?;
+
+        if print {
+            progress!(
+                cli,
+                "  Audio:   {}, {} ({} samples)",
+                format_spec(&audio_info.spec),
+                msf(length, sample_rate),
+                length
+            );
+        }
+
+        let mut rows = vec![];
+
+        for b in &bounds {
+            let track = &cue_file.tracks[b.track].1;
+            let indices = cue_sheet.indices[b.track]
+                .iter()
+                .map(|x| {
+                    if x.file == file_id {
+                        format!("{:02} {}", x.number, x.position)
+                    } else {
+                        format!("{:02} {} (FILE {})", x.number, x.position, x.file + 1)
+                    }
+                })
+                .collect::<Vec<String>>()
+                .join(", ");
+            let overrun = b.end > length;
+
+            if print {
+                progress!(
+                    cli,
+                    "  {:02}  INDEX {}  start {} ({})  length {} ({}, {})  {} - {}{}",
+                    b.track + 1,
+                    indices,
+                    msf(b.start, sample_rate),
+                    b.start,
+                    msf(b.end.saturating_sub(b.start), sample_rate),
+                    b.end.saturating_sub(b.start),
+                    b.source.name(),
+                    track.performer.as_deref().unwrap_or(&cue_file.performer),
+                    track.title,
+                    if overrun {
+                        "  ends after the audio"
+                    } else {
+                        ""
+                    }
+                );
+            }
+
+            rows.push(json!({
+                "number": b.track + 1,
+                "title": track.title,
+                "performer": track.performer,
+                "indices": indices,
+                "start": b.start,
+                "length": b.end.saturating_sub(b.start),
+                "length_source": b.source.name(),
+                "overrun": overrun,
+            }));
+        }
+
+        let tracks_end = bounds.last().map(|b| b.end).unwrap_or(0);
+
+        if print {
+            let difference = match tracks_end.cmp(&length) {
+                Ordering::Greater => format!(", {} samples past the end", tracks_end - length),
+                Ordering::Less => match tail_track {
+                    Some(j) => format!(
+                        ", then {} samples of track {} pregap",
+                        length - tracks_end,
+                        j + 1
+                    ),
+                    None => format!(", {} samples after the last track", length - tracks_end),
+                },
+                Ordering::Equal => String::new(),
+            };
+            progress!(
+                cli,
+                "  Tracks end at {} ({} samples){}",
+                msf(tracks_end, sample_rate),
+                tracks_end,
+                difference
+            );
+        }
+
+        files.push(json!({
+            "path": audio_file.to_string_lossy(),
+            "spec": format_spec(&audio_info.spec),
+            "samples": length,
+            "tracks_end": tracks_end,
+            "tracks": rows,
+        }));
+    }
+
+    report.results.push((
+        "info",
+        json!({
+            "title": cue_file.title,
+            "performer": cue_file.performer,
+            "comments": cue_file.comments,
+            "files": files,
+        }),
+    ));
 
-        output_file.push(output_filename);
+    Ok(vec![])
+}
+
+pub fn lint(input: &Path, cli: &Cli, report: &mut Report) -> Result<Vec<PathBuf>, anyhow::Error> {
+    let issues = lint_cue_sheet(input, cli.cue_encoding)?;
+    report.inputs.push(FileEntry::new(input));
+
+    if issues.is_empty() && !cli.silent && !cli.totally_silent {
+        progress!(cli, "No problems found");
+    }
+
+    report_issues(&issues, cli, report)?;
+
+    Ok(vec![])
+}
+
+/// Reads a CUE sheet file or the cuesheet embedded in a FLAC file.
+fn read_cue_sheet(input: &Path, cli: &Cli) -> Result<CueSheet, anyhow::Error> {
+    let is_flac = input
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("flac"));
+
+    if is_flac {
+        return CueSheet::from_flac(input).context("Failed to read the embedded cuesheet");
+    }
+
+    let (cue_text, encoding) = read_cue_text(input, cli.cue_encoding)?;
 
-        durations.push(Duration {
-            file: output_file,
-            duration_samples: duration,
-            duration_seconds: duration as f64 / audio_info.spec.sample_rate as f64,
-        });
+    if encoding != UTF_8 && !cli.silent && !cli.totally_silent {
+        progress!(
+            cli,
+            "Reading {} as {}",
+            input
+                .file_name()
+                .context("Failed to get file name")?
+                .to_str()
+                .context("to_str failed")?,
+            encoding.name()
+        );
     }
 
+    CueSheet::try_from(cue_text.as_ref()).context("Failed to parse CUE sheet")
+}
+
+/// Tracks are written by up to `args.jobs` workers, each reading the image from
+/// the start of its track, unless the AccurateRip CRCs need all samples in
+/// order.
+fn split_audio_file(
+    audio_file: &Path,
+    spec: WavSpec,
+    durations: &[Duration],
+    args: &SplitArgs,
+    verifier: Option<&OutputVerifier>,
+    accuraterip: Option<&mut AccurateRip>,
+    cli: &Cli,
+) -> Result<Vec<PathBuf>, anyhow::Error> {
     let output_wavs = match audio_file
         .extension()
         .context("Failed to get file extension")?
@@ -259,38 +1169,59 @@ pub fn split(
         .to_lowercase()
         .as_ref()
     {
-        "wav" => match audio_info.spec.sample_format {
+        "wav" if args.jobs > 1 && accuraterip.is_none() => match spec.sample_format {
             SampleFormat::Float => {
-                wav_split_samples::<f32>(&audio_file, audio_info.spec, &durations, &cli)
+                wav_split_samples_parallel::<f32>(audio_file, spec, durations, args, verifier, cli)
                     .context("Failed to copy samples")?
             }
             SampleFormat::Int => {
-                wav_split_samples::<i32>(&audio_file, audio_info.spec, &durations, &cli)
+                wav_split_samples_parallel::<i32>(audio_file, spec, durations, args, verifier, cli)
                     .context("Failed to copy samples")?
             }
         },
-        "flac" => flac_split_samples(&audio_file, audio_info.spec, &durations, &cli)
+        "wav" => match spec.sample_format {
+            SampleFormat::Float => wav_split_samples::<f32>(
+                audio_file,
+                spec,
+                durations,
+                args,
+                verifier,
+                accuraterip,
+                cli,
+            )
+            .context("Failed to copy samples")?,
+            SampleFormat::Int => wav_split_samples::<i32>(
+                audio_file,
+                spec,
+                durations,
+                args,
+                verifier,
+                accuraterip,
+                cli,
+            )
             .context("Failed to copy samples")?,
+        },
+        "flac" if args.jobs > 1 && accuraterip.is_none() => {
+            flac_split_samples_parallel(audio_file, spec, durations, args, verifier, cli)
+                .context("Failed to copy samples")?
+        }
+        "flac" => flac_split_samples(
+            audio_file,
+            spec,
+            durations,
+            args,
+            verifier,
+            accuraterip,
+            cli,
+        )
+        .context("Failed to copy samples")?,
         _ => bail!("Unsupported format"),
     };
 
-    if verify {
-        verify_samples(
-            audio_info.spec.sample_format,
-            &output_wavs,
-            &audio_file,
-            cli,
-        )?;
-    }
-
-    if cue {
-        split_create_cue(&cue_file, input, &durations, cli)?;
-    }
-
     Ok(output_wavs)
 }
 
-pub fn examples() -> Vec<PathBuf> {
+pub fn examples(cli: &Cli, report: &mut Report) -> Vec<PathBuf> {
     let text = r#"Merge all wav files in the current working directory and create CUE sheet:
 
 wfcue merge --cue --title "Album" --performer "Artist" --rem "COMMENT wfcue" --verify --input *.wav --output "Artist - Album.wav"
@@ -307,9 +1238,103 @@ Split a single large audio file containing the entire album into the separate au
 
 wfcue split --cue --input "Artist - Album.cue" --verify --format "%track%. %artist% - %title%"
 
+Merge all wav files in the current working directory into a FLAC file with the highest compression level:
+
+wfcue merge --cue --title "Album" --performer "Artist" --verify --compression-level 8 --input *.wav --output "Artist - Album.flac"
+
+Split a single large audio file containing the entire album into the separate FLAC tracks:
+
+wfcue split --input "Artist - Album.cue" --verify --output-format flac --format "%track%. %artist% - %title%"
+
+Merge files with different sample rates, bit depths or channel counts into a 44.1 kHz 16 bit stereo file:
+
+wfcue merge --cue --convert --sample-rate 44100 --bits-per-sample 16 --channels 2 --input *.flac --output "Artist - Album.wav"
+
+Merge files in disc and track number order from their tags, the input list does not need to be sorted:
+
+wfcue merge --cue --sort tracknumber --input *.flac --output "Artist - Album.flac"
+
+Split an album with pregaps, prepending each pregap to its own track instead of appending it to the previous one:
+
+wfcue split --cue --input "Artist - Album.cue" --verify --gaps prepend
+
+Show the tracks, their lengths and the files a split would write without writing anything:
+
+wfcue split --input "Artist - Album.cue" --dry-run --format "%track%. %artist% - %title%"
+
+Split into WAV tracks tagged with both a RIFF INFO and an id3 chunk:
+
+wfcue split --input "Artist - Album.cue" --verify --id3
+
+Split a FLAC image using its embedded cuesheet:
+
+wfcue split --cue --input "Artist - Album.flac" --verify
+
+Split a CD image and check the AccurateRip CRCs of the tracks against a saved AccurateRip response:
+
+wfcue split --input "Artist - Album.cue" --verify --accuraterip-bin dBAR-012-0015c2a1-00c5f8d3-b60fc20c.bin
+
+Print the MusicBrainz, freedb, AccurateRip and CUETools disc IDs of a CD image:
+
+wfcue disc-id --input "Artist - Album.cue"
+
+Print the album metadata, the audio spec and the INDEX points, start and length of every track without splitting:
+
+wfcue info --input "Artist - Album.cue"
+
+Check a folder of split tracks against the image they were split from, sample by sample:
+
+wfcue verify --input tracks --image "Artist - Album.cue"
+
+Check a CUE sheet for broken INDEX points, tracks past the end of the audio, missing files and REM DURATION values that disagree with the MSF timing, split runs the same checks first:
+
+wfcue lint --input "Artist - Album.cue"
+
+Split a CUE sheet written in a legacy encoding, the encoding is detected without --cue-encoding:
+
+wfcue --cue-encoding windows-1251 split --input "Artist - Album.cue"
+
+Split a CD image and write the multiple file CUE sheet the way Exact Audio Copy does, with CRLF line endings and a UTF-8 BOM:
+
+wfcue split --cue --cue-dialect eac --input "Artist - Album.cue"
+
+Split a CD image into files named like 1-07 Artist - Title.flac, the disc number is left out when the CUE sheet has no REM DISCNUMBER:
+
+wfcue split --input "Artist - Album.cue" --output-format flac --format "[%discnumber%-]%track% %artist|albumartist|'Unknown'% - %title%"
+
+Split a CD image into artist and album folders with a folder per disc, the disc numbers come from REM DISCNUMBER lines of the tracks:
+
+wfcue split --input "Artist - Album.cue" --output-dir Music --format "%albumartist%/%date% - %album%/CD%discnumber%/%track% %title%"
+
+Split a CD image into files named like 07 Title (100% live).wav, %% is a % of the file name and a field without its closing % is an error:
+
+wfcue split --input "Artist - Album.cue" --format "%track% %title% (100%% live)"
+
+Split every album below a music folder into the same folders below another one, keep going past albums that fail and print a summary; a re-run skips the albums that were split unless --resplit is given:
+
+wfcue split --recursive --input Music --output-dir "Music (tracks)" --output-format flac
+
+Split a large WAV or FLAC image on 4 cores at once, each track is read from its own start in the image and the tracks are the same as without --jobs:
+
+wfcue split --input "Artist - Album.cue" --verify --output-format flac --jobs 4
+
+Split only tracks 3, 5, 6 and 7 of a CD image, the audio before them is skipped by seeking instead of being decoded, the multiple file CUE sheet has just these tracks:
+
+wfcue split --cue --input "Artist - Album.cue" --tracks 3,5-7
+
 Merge all wav files in the current working directory and create CUE sheet also overwrite existing files and use silent mode:
 
-wfcue --force --silent merge --cue --title "Album" --performer "Artist" --rem "COMMENT wfcue" --rem "COMPOSER test" --verify --input *.wav --output "Artist - Album.wav""#;
-    println!("{}", text);
+wfcue --force --silent merge --cue --title "Album" --performer "Artist" --rem "COMMENT wfcue" --rem "COMPOSER test" --verify --input *.wav --output "Artist - Album.wav"
+
+Split an album and print a JSON document with the output files, their hashes and the verify results, progress goes to stderr:
+
+wfcue --json split --input "Artist - Album.cue" --verify > result.json"#;
+
+    if cli.json {
+        report.results.push(("examples", json!(text)));
+    } else {
+        println!("{}", text);
+    }
+
     vec![]
 }
@@ -0,0 +1,417 @@
+use std::{
+    f64::consts::PI,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use claxon::FlacReader;
+use hound::{SampleFormat, WavReader, WavSpec};
+
+use crate::{
+    audio::{format_spec, get_audio_info},
+    cli::{Cli, ConvertArgs, OutputSampleFormat},
+    report::Failure,
+    utils::create_sample_progress,
+};
+
+/// Number of sinc zero crossings on each side of the resampling kernel.
+const SINC_ZERO_CROSSINGS: f64 = 16.0;
+/// Above this number of distinct phases the kernel is computed per sample.
+const MAX_KERNEL_PHASES: u64 = 4096;
+
+pub enum ConvertedSample {
+    Int(i32),
+    Float(f32),
+}
+
+impl ConvertArgs {
+    pub fn enabled(&self) -> bool {
+        self.convert
+            || self.sample_rate.is_some()
+            || self.bits_per_sample.is_some()
+            || self.channels.is_some()
+            || self.sample_format.is_some()
+    }
+
+    /// Output spec: the given `spec` (first input file) with the requested
+    /// fields replaced.
+    pub fn target_spec(&self, spec: WavSpec) -> Result<WavSpec, anyhow::Error> {
+        let mut target = spec;
+
+        if let Some(f) = self.sample_format {
+            target.sample_format = match f {
+                OutputSampleFormat::Int => SampleFormat::Int,
+                OutputSampleFormat::Float => SampleFormat::Float,
+            };
+
+            if target.sample_format != spec.sample_format {
+                target.bits_per_sample = match target.sample_format {
+                    SampleFormat::Float => 32,
+                    SampleFormat::Int => 24.min(spec.bits_per_sample),
+                };
+            }
+        }
+
+        if let Some(b) = self.bits_per_sample {
+            target.bits_per_sample = b;
+        }
+
+        if let Some(r) = self.sample_rate {
+            target.sample_rate = r;
+        }
+
+        if let Some(c) = self.channels {
+            target.channels = c;
+        }
+
+        match target.sample_format {
+            SampleFormat::Float if target.bits_per_sample != 32 => {
+                bail!("Float samples must be 32 bits per sample")
+            }
+            SampleFormat::Int if !(8..=32).contains(&target.bits_per_sample) => {
+                bail!("Integer samples must be 8 to 32 bits per sample")
+            }
+            _ => (),
+        }
+
+        if target.sample_rate == 0 {
+            bail!("Invalid sample rate 0");
+        }
+
+        if target.channels == 0 {
+            bail!("Invalid channel count 0");
+        }
+
+        Ok(target)
+    }
+}
+
+/// Fails with a list of the input files which audio spec differs from `target`.
+pub fn check_input_specs(
+    input: &[PathBuf],
+    target: WavSpec,
+    convert: bool,
+) -> Result<(), anyhow::Error> {
+    let mut mismatched = vec![];
+
+    for file in input {
+        let spec = get_audio_info(file)?.spec;
+        if spec != target {
+            mismatched.push(format!("  {} ({})", file.display(), format_spec(&spec)));
+        }
+    }
+
+    if !mismatched.is_empty() && !convert {
+        bail!(Failure::SpecMismatch(format!(
+            "Input files do not match the output audio spec ({}):\n{}\nUse --convert to convert them",
+            format_spec(&target),
+            mismatched.join("\n")
+        )));
+    }
+
+    Ok(())
+}
+
+pub struct SpecConverter {
+    from: WavSpec,
+    to: WavSpec,
+}
+
+impl SpecConverter {
+    pub fn new(from: WavSpec, to: WavSpec) -> Result<SpecConverter, anyhow::Error> {
+        if from.channels != to.channels && from.channels != 1 && to.channels != 1 {
+            bail!(
+                "Unsupported channel conversion from {} to {}",
+                from.channels,
+                to.channels
+            );
+        }
+
+        Ok(SpecConverter { from, to })
+    }
+
+    /// Number of inter-channel samples produced from `frames` input samples.
+    pub fn output_frames(&self, frames: u64) -> u64 {
+        let from = self.from.sample_rate as u64;
+        let to = self.to.sample_rate as u64;
+        (frames * to + from / 2) / from
+    }
+
+    /// Decodes `file`, converts it to the target spec and passes every output
+    /// sample to `sink`. Returns the number of inter-channel samples produced.
+    /// Progress is shown only when `cli` is set.
+    pub fn convert_file<F>(
+        &self,
+        file: &Path,
+        cli: Option<&Cli>,
+        sink: F,
+    ) -> Result<u64, anyhow::Error>
+    where
+        F: FnMut(ConvertedSample) -> Result<(), anyhow::Error>,
+    {
+        let info = get_audio_info(file)?;
+        let frames = info.total_samples / self.from.channels as u64;
+        let pb = match cli {
+            Some(cli) => create_sample_progress(info.total_samples, cli)?,
+            None => None,
+        };
+        let progress = || {
+            if let Some(ref v) = pb {
+                v.inc(1)
+            }
+        };
+        let scale = (1i64 << (self.from.bits_per_sample - 1)) as f64;
+
+        match file
+            .extension()
+            .context("Failed to get file extension")?
+            .to_str()
+            .context("to_str failed")?
+            .to_lowercase()
+            .as_ref()
+        {
+            "wav" => {
+                let mut reader = WavReader::open(file)?;
+                match self.from.sample_format {
+                    SampleFormat::Float => self.convert_samples(
+                        reader
+                            .samples::<f32>()
+                            .inspect(|_| progress())
+                            .map(|s| Ok(s? as f64)),
+                        frames,
+                        sink,
+                    ),
+                    SampleFormat::Int => self.convert_samples(
+                        reader
+                            .samples::<i32>()
+                            .inspect(|_| progress())
+                            .map(|s| Ok(s? as f64 / scale)),
+                        frames,
+                        sink,
+                    ),
+                }
+            }
+            "flac" => {
+                let mut reader = FlacReader::open(file)?;
+                self.convert_samples(
+                    reader
+                        .samples()
+                        .inspect(|_| progress())
+                        .map(|s| Ok(s? as f64 / scale)),
+                    frames,
+                    sink,
+                )
+            }
+            _ => bail!("Unsupported format"),
+        }
+    }
+
+    fn convert_samples<I, F>(
+        &self,
+        samples: I,
+        frames: u64,
+        mut sink: F,
+    ) -> Result<u64, anyhow::Error>
+    where
+        I: Iterator<Item = Result<f64, anyhow::Error>>,
+        F: FnMut(ConvertedSample) -> Result<(), anyhow::Error>,
+    {
+        let mut input = FrameReader {
+            samples,
+            from_channels: self.from.channels as usize,
+            to_channels: self.to.channels as usize,
+        };
+
+        let mut emit = |frame: &[f64]| -> Result<(), anyhow::Error> {
+            for v in frame {
+                sink(self.quantize(*v))?;
+            }
+            Ok(())
+        };
+
+        if self.from.sample_rate == self.to.sample_rate {
+            let mut written = 0;
+            while let Some(frame) = input.next_frame()? {
+                emit(&frame)?;
+                written += 1;
+            }
+            return Ok(written);
+        }
+
+        let resampler = Resampler::new(self.from.sample_rate, self.to.sample_rate);
+        let channels = self.to.channels as usize;
+        let out_frames = self.output_frames(frames);
+        let mut buffer: Vec<Vec<f64>> = vec![];
+        let mut buffer_start: i64 = 0;
+        let mut eof = false;
+        let mut frame = vec![0.0; channels];
+
+        for n in 0..out_frames {
+            let (position, phase) = resampler.position(n);
+            let first = position - resampler.half_taps + 1;
+            let last = position + resampler.half_taps;
+
+            while !eof && buffer_start + (buffer.len() as i64) <= last {
+                match input.next_frame()? {
+                    Some(f) => buffer.push(f),
+                    None => eof = true,
+                }
+            }
+
+            let drain = (first - buffer_start).clamp(0, buffer.len() as i64);
+            if drain > 4096 {
+                buffer.drain(..drain as usize);
+                buffer_start += drain;
+            }
+
+            let kernel = resampler.kernel(phase);
+            frame.iter_mut().for_each(|v| *v = 0.0);
+
+            for (k, weight) in kernel.iter().enumerate() {
+                let index = first + k as i64 - buffer_start;
+                if index < 0 || index >= buffer.len() as i64 {
+                    continue;
+                }
+
+                for (c, v) in frame.iter_mut().enumerate() {
+                    *v += buffer[index as usize][c] * weight;
+                }
+            }
+
+            emit(&frame)?;
+        }
+
+        Ok(out_frames)
+    }
+
+    fn quantize(&self, value: f64) -> ConvertedSample {
+        match self.to.sample_format {
+            SampleFormat::Float => ConvertedSample::Float(value as f32),
+            SampleFormat::Int => {
+                let scale = (1i64 << (self.to.bits_per_sample - 1)) as f64;
+                let v = (value * scale).round().clamp(-scale, scale - 1.0);
+                ConvertedSample::Int(v as i32)
+            }
+        }
+    }
+}
+
+/// Groups interleaved samples into frames and maps the channels: mono is
+/// copied to every output channel, multiple channels are averaged into mono.
+struct FrameReader<I> {
+    samples: I,
+    from_channels: usize,
+    to_channels: usize,
+}
+
+impl<I> FrameReader<I>
+where
+    I: Iterator<Item = Result<f64, anyhow::Error>>,
+{
+    fn next_frame(&mut self) -> Result<Option<Vec<f64>>, anyhow::Error> {
+        let mut frame = Vec::with_capacity(self.from_channels);
+
+        for c in 0..self.from_channels {
+            match self.samples.next() {
+                Some(s) => frame.push(s?),
+                None if c == 0 => return Ok(None),
+                None => bail!("Incomplete sample frame at the end of input"),
+            }
+        }
+
+        if self.from_channels == self.to_channels {
+            Ok(Some(frame))
+        } else if self.from_channels == 1 {
+            Ok(Some(vec![frame[0]; self.to_channels]))
+        } else {
+            let mean = frame.iter().sum::<f64>() / self.from_channels as f64;
+            Ok(Some(vec![mean]))
+        }
+    }
+}
+
+/// Blackman windowed sinc resampler. Output sample `n` sits at input position
+/// `n * from / to`, the kernel is precomputed for every distinct fractional
+/// phase of that ratio when there are not too many of them.
+struct Resampler {
+    step: u64,
+    phases: u64,
+    cutoff: f64,
+    half_taps: i64,
+    kernels: Vec<Vec<f64>>,
+}
+
+impl Resampler {
+    fn new(from: u32, to: u32) -> Resampler {
+        let gcd = gcd(from as u64, to as u64);
+        let step = from as u64 / gcd;
+        let phases = to as u64 / gcd;
+        let cutoff = (to as f64 / from as f64).min(1.0);
+        let half_taps = (SINC_ZERO_CROSSINGS / cutoff).ceil() as i64;
+
+        let mut resampler = Resampler {
+            step,
+            phases,
+            cutoff,
+            half_taps,
+            kernels: vec![],
+        };
+
+        if phases <= MAX_KERNEL_PHASES {
+            resampler.kernels = (0..phases)
+                .map(|p| resampler.compute_kernel(p as f64 / phases as f64))
+                .collect();
+        }
+
+        resampler
+    }
+
+    fn position(&self, n: u64) -> (i64, u64) {
+        let t = n * self.step;
+        ((t / self.phases) as i64, t % self.phases)
+    }
+
+    fn kernel(&self, phase: u64) -> std::borrow::Cow<'_, [f64]> {
+        match self.kernels.get(phase as usize) {
+            Some(k) => std::borrow::Cow::Borrowed(k),
+            None => std::borrow::Cow::Owned(self.compute_kernel(phase as f64 / self.phases as f64)),
+        }
+    }
+
+    /// Weights for the input samples `position - half_taps + 1 ..= position +
+    /// half_taps`, normalized to unity gain.
+    fn compute_kernel(&self, fraction: f64) -> Vec<f64> {
+        let width = self.half_taps as f64;
+        let mut kernel: Vec<f64> = (-self.half_taps + 1..=self.half_taps)
+            .map(|k| {
+                let x = k as f64 - fraction;
+                if x.abs() >= width {
+                    return 0.0;
+                }
+
+                let sinc = match x * self.cutoff {
+                    0.0 => 1.0,
+                    y => (PI * y).sin() / (PI * y),
+                };
+                let w = (x / width + 1.0) / 2.0;
+                let window = 0.42 - 0.5 * (2.0 * PI * w).cos() + 0.08 * (4.0 * PI * w).cos();
+
+                self.cutoff * sinc * window
+            })
+            .collect();
+
+        let sum: f64 = kernel.iter().sum();
+        if sum != 0.0 {
+            kernel.iter_mut().for_each(|k| *k /= sum);
+        }
+
+        kernel
+    }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    match b {
+        0 => a,
+        _ => gcd(b, a % b),
+    }
+}
@@ -1,34 +1,532 @@
-use std::{fs, path::PathBuf};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
 
 use anyhow::{bail, Context};
+use chardetng::EncodingDetector;
+use claxon::FlacReader;
 use cue_rw::{CUEFile, CUETimeStamp, CUETrack};
+use encoding_rs::{Encoding, UTF_8};
 
-use crate::{audio::Duration, cli::Cli, track_msf::TrackMSF, utils::promt_overwrite};
+use crate::{
+    audio::Duration,
+    cli::{
+        Cli, CueDialect, CueFileType, CueFormatArgs, LineEndings, MergeArgs, MsfRounding, SplitArgs,
+    },
+    flac::read_cuesheet_block,
+    plan::LengthSource,
+    report::{FileEntry, Report},
+    tags::Tags,
+    track_msf::TrackMSF,
+    utils::{progress, promt_overwrite, warn},
+};
+
+/// INDEX point of a CUE track. `file` is the FILE entry the point lies in,
+/// which for a pregap at the end of the previous file (EAC "gaps appended"
+/// layout) is not the FILE of the track itself.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CueIndex {
+    pub number: u8,
+    pub file: usize,
+    pub position: TrackMSF,
+    /// Sample exact position from a FLAC CUESHEET block, `position` is
+    /// rounded to CD frames
+    pub samples: Option<u64>,
+}
+
+impl CueIndex {
+    pub fn to_samples(self, sample_rate: u32) -> u64 {
+        self.samples
+            .unwrap_or_else(|| self.position.to_samples(sample_rate))
+    }
+}
+
+/// CUE sheet with the INDEX points of every track kept as `CueIndex`. cue_rw
+/// stores INDEX minutes as `u8`, rejects anything past 99 minutes and can't
+/// read a FILE line in the middle of a track, so FILE and INDEX lines are
+/// parsed here and cue_rw only gets the first FILE line.
+pub struct CueSheet {
+    pub cue: CUEFile,
+    pub indices: Vec<Vec<CueIndex>>,
+    /// TRACK numbers, they only differ from the position when some tracks
+    /// were left out of a split.
+    pub numbers: Vec<usize>,
+}
+
+impl TryFrom<&str> for CueSheet {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let value = value.strip_prefix('\u{feff}').unwrap_or(value);
+        let mut text = String::with_capacity(value.len());
+        let mut files: Vec<String> = vec![];
+        // (has content, FILE of the TRACK line, indices)
+        let mut tracks: Vec<(bool, usize, Vec<CueIndex>)> = vec![];
+
+        for line in value.lines() {
+            if !line.starts_with("  ") {
+                if let Some(file) = line.strip_prefix("FILE ") {
+                    let name = file
+                        .rsplit_once(' ')
+                        .map(|(name, _)| name)
+                        .with_context(|| format!("Invalid FILE line: {}", line))?;
+                    files.push(
+                        name.strip_prefix('"')
+                            .and_then(|n| n.strip_suffix('"'))
+                            .unwrap_or(name)
+                            .to_string(),
+                    );
+
+                    if files.len() > 1 {
+                        continue;
+                    }
+                }
+
+                text.push_str(line);
+                text.push('\n');
+                continue;
+            }
+
+            let trimmed = line.trim_start();
+            let mut words = trimmed.split(' ').filter(|w| !w.is_empty());
+            let file = files.len().saturating_sub(1);
+
+            match words.next() {
+                Some("TRACK") => tracks.push((false, file, vec![])),
+                Some("INDEX") => {
+                    let number = words
+                        .next()
+                        .and_then(|n| n.parse::<u8>().ok())
+                        .with_context(|| format!("Invalid INDEX line: {}", trimmed))?;
+                    let position = TrackMSF::try_from(
+                        words
+                            .next()
+                            .with_context(|| format!("Invalid INDEX line: {}", trimmed))?,
+                    )?;
+                    let track = tracks
+                        .last_mut()
+                        .with_context(|| format!("INDEX outside of TRACK: {}", trimmed))?;
+                    track.0 = true;
+                    track.2.push(CueIndex {
+                        number,
+                        file,
+                        position,
+                        samples: None,
+                    });
+
+                    text.push_str(&line[..line.len() - trimmed.len()]);
+                    text.push_str(&format!("INDEX {:02} 00:00:00\n", number));
+                    continue;
+                }
+                _ => {
+                    if let Some(track) = tracks.last_mut() {
+                        track.0 = true;
+                    }
+                }
+            }
+
+            text.push_str(line);
+            text.push('\n');
+        }
+
+        let mut cue = CUEFile::try_from(text.as_ref())?;
+
+        // cue_rw skips TRACK lines without any content
+        let tracks: Vec<(usize, Vec<CueIndex>)> = tracks
+            .into_iter()
+            .filter(|(content, _, _)| *content)
+            .map(|(_, file, indices)| (file, indices))
+            .collect();
+
+        if tracks.len() != cue.tracks.len() {
+            bail!("Failed to match INDEX entries with CUE tracks");
+        }
+
+        // A track belongs to the FILE of its INDEX 01
+        for ((file_id, _), (file, indices)) in cue.tracks.iter_mut().zip(&tracks) {
+            *file_id = indices
+                .iter()
+                .find(|i| i.number == 1)
+                .map(|i| i.file)
+                .unwrap_or(*file);
+        }
+
+        cue.files = files;
+
+        Ok(CueSheet {
+            numbers: (1..=cue.tracks.len()).collect(),
+            cue,
+            indices: tracks.into_iter().map(|(_, indices)| indices).collect(),
+        })
+    }
+}
+
+impl CueSheet {
+    /// CUE sheet embedded in a FLAC file as a CUESHEET Vorbis comment or
+    /// CUESHEET metadata block. The sample exact block offsets are used for
+    /// the INDEX points when both are there.
+    pub fn from_flac(file: &Path) -> Result<CueSheet, anyhow::Error> {
+        let reader = FlacReader::open(file)?;
+        let sample_rate = reader.streaminfo().sample_rate;
+        let tag = |key: &str| reader.get_tag(key).next().map(|v| v.to_string());
+        let block = read_cuesheet_block(file)?;
+
+        let mut cue_sheet = match (tag("CUESHEET"), &block) {
+            (Some(text), _) => CueSheet::try_from(text.as_ref())
+                .context("Failed to parse the CUESHEET Vorbis comment")?,
+            (None, Some(tracks)) => {
+                let mut text = format!(
+                    "TITLE \"{}\"\nPERFORMER \"{}\"\nFILE \"\" WAVE\n",
+                    tag("ALBUM").unwrap_or("Album".to_string()),
+                    tag("ALBUMARTIST")
+                        .or(tag("ARTIST"))
+                        .unwrap_or("Artist".to_string())
+                );
+                for track in tracks {
+                    text.push_str(&format!(
+                        "  TRACK {:02} AUDIO\n    TITLE \"Track {:02}\"\n",
+                        track.number, track.number
+                    ));
+                    for (number, offset) in &track.indices {
+                        let position = TrackMSF::from_samples(
+                            track.offset + offset,
+                            sample_rate,
+                            MsfRounding::Down,
+                        );
+                        text.push_str(&format!("    INDEX {:02} {}\n", number, position));
+                    }
+                }
+                CueSheet::try_from(text.as_ref())
+                    .context("Failed to read the FLAC CUESHEET block")?
+            }
+            (None, None) => bail!("{} has no embedded cuesheet", file.display()),
+        };
+
+        if cue_sheet.cue.files.len() != 1 {
+            bail!(
+                "Embedded cuesheet has {} FILE entries, expected 1",
+                cue_sheet.cue.files.len()
+            );
+        }
+
+        cue_sheet.cue.files = vec![file
+            .file_name()
+            .context("Failed to get file name")?
+            .to_str()
+            .context("to_str failed")?
+            .to_string()];
+
+        if let Some(tracks) = block {
+            if tracks.len() != cue_sheet.indices.len() {
+                bail!(
+                    "CUESHEET block has {} tracks, the CUESHEET comment {}",
+                    tracks.len(),
+                    cue_sheet.indices.len()
+                );
+            }
+
+            for (indices, track) in cue_sheet.indices.iter_mut().zip(&tracks) {
+                for index in indices.iter_mut() {
+                    if let Some((_, offset)) = track.indices.iter().find(|i| i.0 == index.number) {
+                        index.samples = Some(track.offset + offset);
+                    }
+                }
+            }
+        }
+
+        Ok(cue_sheet)
+    }
+}
+
+/// Text of a CUE file and its encoding. A BOM decides the encoding, then
+/// `encoding`, then UTF-8 if the file is valid UTF-8. Anything else is
+/// guessed from the bytes, legacy cues are mostly Windows-1251,
+/// Windows-1252 or Shift_JIS.
+pub fn read_cue_text(
+    input: &Path,
+    encoding: Option<&'static Encoding>,
+) -> Result<(String, &'static Encoding), anyhow::Error> {
+    let bytes = fs::read(input).context("Failed to read CUE file")?;
+
+    let (encoding, bom_length) = match Encoding::for_bom(&bytes) {
+        Some(bom) => bom,
+        None => (encoding.unwrap_or_else(|| detect_encoding(&bytes)), 0),
+    };
+
+    // Invalid sequences become U+FFFD
+    let (text, _) = encoding.decode_without_bom_handling(&bytes[bom_length..]);
+
+    Ok((text.into_owned(), encoding))
+}
+
+fn detect_encoding(bytes: &[u8]) -> &'static Encoding {
+    if std::str::from_utf8(bytes).is_ok() {
+        return UTF_8;
+    }
+
+    let mut detector = EncodingDetector::new();
+    detector.feed(bytes, true);
+    detector.guess(None, true)
+}
+
+/// Whether a FLAC file has a CUESHEET Vorbis comment or metadata block.
+pub fn has_embedded_cuesheet(file: &Path) -> Result<bool, anyhow::Error> {
+    let reader = FlacReader::open(file)?;
+
+    Ok(reader.get_tag("CUESHEET").next().is_some() || read_cuesheet_block(file)?.is_some())
+}
+
+/// How a CUE sheet is written, the dialect presets of `CueFormatArgs` with
+/// its overrides applied.
+#[derive(Clone, Copy, PartialEq)]
+pub struct CueStyle {
+    pub crlf: bool,
+    pub bom: bool,
+    /// Quote strings only if they are empty or have spaces
+    pub minimal_quoting: bool,
+    pub file_type: CueFileType,
+    /// Track REM lines before the INDEX lines instead of after them
+    pub rem_before_index: bool,
+    pub rem_duration: bool,
+}
+
+impl From<&CueFormatArgs> for CueStyle {
+    fn from(args: &CueFormatArgs) -> CueStyle {
+        let mut style = match args.cue_dialect {
+            CueDialect::Wfcue => CueStyle::default(),
+            CueDialect::Eac => CueStyle {
+                crlf: true,
+                bom: true,
+                rem_before_index: true,
+                rem_duration: false,
+                ..CueStyle::default()
+            },
+            CueDialect::Foobar2000 => CueStyle {
+                crlf: true,
+                rem_before_index: true,
+                rem_duration: false,
+                ..CueStyle::default()
+            },
+            CueDialect::Xld => CueStyle {
+                rem_before_index: true,
+                rem_duration: false,
+                ..CueStyle::default()
+            },
+            CueDialect::Strict => CueStyle {
+                minimal_quoting: true,
+                rem_before_index: true,
+                rem_duration: false,
+                ..CueStyle::default()
+            },
+        };
+
+        if let Some(line_endings) = args.cue_line_endings {
+            style.crlf = line_endings == LineEndings::Crlf;
+        }
+        style.bom = args.cue_bom.unwrap_or(style.bom);
+        style.file_type = args.cue_file_type.unwrap_or(style.file_type);
+        style.rem_duration = args.cue_rem_duration.unwrap_or(style.rem_duration);
+
+        style
+    }
+}
+
+impl Default for CueStyle {
+    fn default() -> CueStyle {
+        CueStyle {
+            crlf: false,
+            bom: false,
+            minimal_quoting: false,
+            file_type: CueFileType::Wave,
+            rem_before_index: false,
+            rem_duration: true,
+        }
+    }
+}
+
+impl CueSheet {
+    /// Same layout as cue_rw, except that a FILE line is written before the
+    /// first INDEX point that lies in it, which may be inside a track.
+    pub fn format(&self, style: &CueStyle) -> String {
+        let cue = &self.cue;
+        let quote = |value: &str| {
+            if style.minimal_quoting && !value.is_empty() && !value.contains(char::is_whitespace) {
+                value.to_string()
+            } else {
+                format!(r#""{}""#, value)
+            }
+        };
+        let file_type = match style.file_type {
+            CueFileType::Wave => "WAVE",
+            CueFileType::Binary => "BINARY",
+        };
+
+        let mut lines: Vec<String> = cue.comments.iter().map(|c| format!("REM {}", c)).collect();
+
+        lines.push(format!("TITLE {}", quote(&cue.title)));
+        lines.push(format!("PERFORMER {}", quote(&cue.performer)));
+
+        if let Some(catalog) = &cue.catalog {
+            lines.push(format!("CATALOG {}", catalog));
+        }
+
+        if let Some(text_file) = &cue.text_file {
+            lines.push(format!("CDTEXTFILE {}", quote(text_file)));
+        }
+
+        if let Some(songwriter) = &cue.songwriter {
+            lines.push(format!("SONGWRITER {}", quote(songwriter)));
+        }
+
+        let mut next_file = 0;
+        let mut push_files = |lines: &mut Vec<String>, file: usize| {
+            while next_file <= file && next_file < cue.files.len() {
+                lines.push(format!(
+                    "FILE {} {}",
+                    quote(&cue.files[next_file]),
+                    file_type
+                ));
+                next_file += 1;
+            }
+        };
+
+        for (i, ((file, track), indices)) in cue.tracks.iter().zip(&self.indices).enumerate() {
+            push_files(&mut lines, indices.first().map(|i| i.file).unwrap_or(*file));
+
+            let number = self.numbers.get(i).copied().unwrap_or(i + 1);
+            lines.push(format!("  TRACK {:02} AUDIO", number));
+
+            if let Some(flags) = &track.flags {
+                lines.push(format!("    FLAGS {}", flags));
+            }
+
+            lines.push(format!("    TITLE {}", quote(&track.title)));
+
+            if let Some(performer) = &track.performer {
+                lines.push(format!("    PERFORMER {}", quote(performer)));
+            }
+
+            if let Some(pregap) = &track.pre_gap {
+                lines.push(format!("    PREGAP {}", pregap));
+            }
+
+            if let Some(isrc) = &track.isrc {
+                lines.push(format!("    ISRC {}", isrc));
+            }
+
+            let comments: Vec<String> = track
+                .comments
+                .iter()
+                .filter(|c| style.rem_duration || !c.starts_with("DURATION "))
+                .map(|c| format!("    REM {}", c))
+                .collect();
+
+            if style.rem_before_index {
+                lines.extend(comments.iter().cloned());
+            }
+
+            for index in indices {
+                push_files(&mut lines, index.file);
+                lines.push(format!("    INDEX {:02} {}", index.number, index.position));
+            }
+
+            if !style.rem_before_index {
+                lines.extend(comments);
+            }
+        }
+
+        push_files(&mut lines, cue.files.len());
+
+        let text = lines.join(if style.crlf { "\r\n" } else { "\n" });
+
+        if style.bom {
+            format!("\u{feff}{}", text)
+        } else {
+            text
+        }
+    }
+}
+
+impl fmt::Display for CueSheet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.format(&CueStyle::default()))
+    }
+}
 
 pub fn merge_create_cue(
-    title: &Option<String>,
-    performer: &Option<String>,
-    rem: &Option<Vec<String>>,
-    output: &PathBuf,
-    durations: &Vec<Duration>,
+    args: &MergeArgs,
+    durations: &[Duration],
+    sample_rate: u32,
     cli: &Cli,
+    report: &mut Report,
 ) -> Result<(), anyhow::Error> {
+    let &MergeArgs {
+        ref title,
+        ref performer,
+        ref rem,
+        ref output,
+        msf_rounding,
+        ref cue_format,
+        ..
+    } = args;
+    let style = &CueStyle::from(cue_format);
+
     if !cli.silent && !cli.totally_silent {
-        println!("Creating CUE file ...");
+        progress!(cli, "Creating CUE file ...");
     }
 
     let mut cue = CUEFile::new();
+    let mut indices = vec![];
 
-    cue.title = title.clone().unwrap_or("Album".to_string());
-    cue.performer = performer.clone().unwrap_or("Artist".to_string());
+    // Album fields from the input tags unless given on the command line
+    let first_tag =
+        |field: fn(&Tags) -> &Option<String>| durations.iter().find_map(|d| field(&d.tags).clone());
 
-    match rem {
-        Some(comments) => {
-            comments.iter().for_each(|c| {
-                cue.comments.push(c.clone());
-            });
+    cue.title = match title.clone().or(first_tag(|t| &t.album)) {
+        Some(t) => cue_text(&t),
+        None => {
+            warn(
+                cli,
+                report,
+                "missing_title",
+                "No album TITLE, using \"Album\"".to_string(),
+            );
+            "Album".to_string()
+        }
+    };
+    cue.performer = match album_performer(performer, durations) {
+        Some(p) => cue_text(&p),
+        None => {
+            warn(
+                cli,
+                report,
+                "missing_performer",
+                "No album PERFORMER, using \"Artist\"".to_string(),
+            );
+            "Artist".to_string()
+        }
+    };
+
+    if let Some(comments) = rem {
+        comments.iter().for_each(|c| {
+            cue.comments.push(c.clone());
+        });
+    }
+
+    for (key, value) in [
+        ("GENRE", first_tag(|t| &t.genre)),
+        ("DATE", first_tag(|t| &t.date)),
+    ] {
+        if let Some(value) = value {
+            if rem_value(&cue.comments, key).is_none() {
+                cue.comments.push(if value.contains(' ') {
+                    format!(r#"{} "{}""#, key, cue_text(&value))
+                } else {
+                    format!("{} {}", key, value)
+                });
+            }
         }
-        None => (),
     }
 
     cue.files.push(
@@ -40,6 +538,80 @@ pub fn merge_create_cue(
             .to_string(),
     );
 
+    let output_cue = merge_cue_path(output)?;
+
+    for duration in durations {
+        let mut track = CUETrack::new();
+        track.title = match &duration.tags.title {
+            Some(title) => cue_text(title),
+            None => duration
+                .file
+                .file_stem()
+                .context("Failed to get file name")?
+                .to_str()
+                .context("to_str failed")?
+                .to_string(),
+        };
+        track.performer = Some(
+            duration
+                .tags
+                .artist
+                .as_ref()
+                .map(|a| cue_text(a))
+                .unwrap_or(cue.performer.clone()),
+        );
+        track.isrc = duration
+            .tags
+            .isrc
+            .clone()
+            .filter(|isrc| isrc.len() == 12 && isrc.chars().all(|c| c.is_ascii_alphanumeric()));
+        track
+            .comments
+            .push(format!("DURATION {}", duration.duration_samples));
+        cue.tracks.push((0, track));
+        indices.push(vec![CueIndex {
+            number: 1,
+            file: 0,
+            position: TrackMSF::from_samples(duration.start_samples, sample_rate, msf_rounding),
+            samples: None,
+        }]);
+    }
+
+    let cue_sheet = CueSheet {
+        numbers: (1..=cue.tracks.len()).collect(),
+        cue,
+        indices,
+    };
+
+    if !cli.force
+        && fs::exists(&output_cue).context("Can't check existence of file")?
+        && !promt_overwrite(&output_cue).context("Promt failed")?
+    {
+        return Ok(());
+    }
+
+    fs::write(&output_cue, cue_sheet.format(style)).context("Failed to write CUE file")?;
+    report.outputs.push(FileEntry::new(&output_cue));
+
+    Ok(())
+}
+
+/// The album performer is the one given on the command line, the album artist
+/// or the artist shared by all inputs.
+pub fn album_performer(performer: &Option<String>, durations: &[Duration]) -> Option<String> {
+    let common_artist = durations
+        .first()
+        .and_then(|d| d.tags.artist.clone())
+        .filter(|a| durations.iter().all(|d| d.tags.artist.as_ref() == Some(a)));
+
+    performer
+        .clone()
+        .or(durations.iter().find_map(|d| d.tags.album_artist.clone()))
+        .or(common_artist)
+}
+
+/// CUE sheet written next to the merged file.
+pub fn merge_cue_path(output: &Path) -> Result<PathBuf, anyhow::Error> {
     let mut output_cue: PathBuf = PathBuf::new();
 
     output_cue.push(
@@ -55,52 +627,51 @@ pub fn merge_create_cue(
             .context("Failed to get file name")?
             .to_str()
             .context("to_str failed")?
-            .to_string()
     ));
 
-    let mut last_duration: f64 = 0.0;
-    for duration in durations {
-        let mut track = CUETrack::new();
-        let track_duration = TrackMSF::new(last_duration).to_string();
-        track.title = duration
-            .file
+    Ok(output_cue)
+}
+
+/// Multiple file CUE sheet written next to the input CUE sheet.
+pub fn split_cue_path(input: &Path) -> Result<PathBuf, anyhow::Error> {
+    let mut output_cue: PathBuf = PathBuf::new();
+
+    output_cue.push(
+        input
+            .parent()
+            .context("Failed to get input file parent dir")?,
+    );
+
+    output_cue.push(format!(
+        "{}_multiple.cue",
+        input
             .file_stem()
             .context("Failed to get file name")?
             .to_str()
             .context("to_str failed")?
-            .to_string();
-        track.performer = Some(cue.performer.clone());
-        track.indices.push((
-            1,
-            CUETimeStamp::try_from(track_duration.as_ref())
-                .context("Failed to convert TrackMSF to CUETimeStamp")?,
-        ));
-        track
-            .comments
-            .push(format!("DURATION {}", duration.duration_samples));
-        cue.tracks.push((0, track));
-        last_duration += duration.duration_seconds;
-    }
-
-    if !cli.force && fs::exists(&output_cue).context("Can't check existence of file")? {
-        if !promt_overwrite(&output_cue).context("Promt failed")? {
-            return Ok(());
-        }
-    }
-
-    fs::write(&output_cue, cue.to_string()).context("Failed to write CUE file")?;
+    ));
 
-    Ok(())
+    Ok(output_cue)
 }
 
+/// `indices` are the INDEX points of every track in the split files,
+/// `numbers` their TRACK numbers in the input and `pregaps` the lengths of the
+/// pregaps that were not written to any file. Returns the path of the CUE
+/// sheet unless an existing one was kept.
 pub fn split_create_cue(
     cue_file: &CUEFile,
-    input: &PathBuf,
-    durations: &Vec<Duration>,
+    args: &SplitArgs,
+    durations: &[Duration],
+    indices: Vec<Vec<CueIndex>>,
+    numbers: Vec<usize>,
+    pregaps: Vec<Option<TrackMSF>>,
     cli: &Cli,
-) -> Result<(), anyhow::Error> {
+) -> Result<Option<PathBuf>, anyhow::Error> {
+    let input = &args.input;
+    let style = &CueStyle::from(&args.cue_format);
+
     if !cli.silent && !cli.totally_silent {
-        println!("Creating CUE file ...");
+        progress!(cli, "Creating CUE file ...");
     }
 
     let mut cue_multiple = CUEFile::new();
@@ -109,73 +680,235 @@ pub fn split_create_cue(
     cue_multiple.performer = cue_file.performer.clone();
     cue_multiple.comments = cue_file.comments.clone();
 
-    let mut output_cue: PathBuf = PathBuf::new();
+    let output_cue = split_cue_path(input)?;
 
-    output_cue.push(
-        input
-            .parent()
-            .context("Failed to get input file parent dir")?,
-    );
+    let cue_dir = output_cue.parent().context("Failed to get parent dir")?;
 
-    output_cue.push(format!(
-        "{}_multiple.cue",
-        input
-            .file_stem()
-            .context("Failed to get file name")?
-            .to_str()
-            .context("to_str failed")?
-            .to_string()
-    ));
+    // Tracks in folders next to the CUE sheet keep their relative path
+    for duration in durations {
+        let file = match duration.file.strip_prefix(cue_dir) {
+            Ok(file) => file,
+            Err(_) => Path::new(
+                duration
+                    .file
+                    .file_name()
+                    .context("Failed to get file name")?,
+            ),
+        };
+        cue_multiple
+            .files
+            .push(file.to_str().context("to_str failed")?.to_string());
+    }
 
-    let mut cue_file_tracks = cue_file.tracks.iter();
+    for ((cue_file_track, track_indices), pregap) in
+        cue_file.tracks.iter().zip(&indices).zip(pregaps)
+    {
+        let file_id = track_indices
+            .iter()
+            .find(|i| i.number == 1)
+            .context("Can`t find track INDEX 01")?
+            .file;
 
-    for (i, duration) in durations.iter().enumerate() {
-        let cue_file_next_track = cue_file_tracks
-            .next()
-            .context("Failed to get input cue next track")?;
+        let mut track = CUETrack::new();
+        track.title = cue_file_track.1.title.clone();
+        track.performer = cue_file_track.1.performer.clone();
+        track.comments = cue_file_track.1.comments.clone();
 
-        cue_multiple.files.push(
-            duration
-                .file
-                .file_name()
-                .context("Failed to get file name")?
-                .to_str()
-                .context("to_str failed")?
-                .to_string(),
-        );
+        if let Some(pregap) = pregap {
+            track.pre_gap = Some(
+                CUETimeStamp::try_from(pregap.to_string().as_ref())
+                    .context("Failed to convert TrackMSF to CUETimeStamp")?,
+            );
+        }
 
-        let mut track = CUETrack::new();
-        track.title = cue_file_next_track.1.title.clone();
-        track.performer = cue_file_next_track.1.performer.clone();
-        track.indices.push((1, CUETimeStamp::new(0, 0, 0)));
-        track.comments = cue_file_next_track.1.comments.clone();
-        cue_multiple.tracks.push((i, track));
+        cue_multiple.tracks.push((file_id, track));
     }
 
-    if !cli.force && fs::exists(&output_cue).context("Can't check existence of file")? {
-        if !promt_overwrite(&output_cue).context("Promt failed")? {
-            return Ok(());
-        }
+    let cue_sheet = CueSheet {
+        cue: cue_multiple,
+        indices,
+        numbers,
+    };
+
+    if !cli.force
+        && fs::exists(&output_cue).context("Can't check existence of file")?
+        && !promt_overwrite(&output_cue).context("Promt failed")?
+    {
+        return Ok(None);
     }
 
-    fs::write(&output_cue, cue_multiple.to_string()).context("Failed to write CUE file")?;
+    fs::write(&output_cue, cue_sheet.format(style)).context("Failed to write CUE file")?;
 
-    Ok(())
+    Ok(Some(output_cue))
 }
 
-pub fn cue_msf_to_samples(
-    indices: &Vec<(u8, CUETimeStamp)>,
-    sample_rate: u32,
-) -> Result<u64, anyhow::Error> {
-    let cue_ts = indices
-        .iter()
-        .find_map(|t| if t.0 == 1 { Some(t) } else { None });
+pub fn cue_msf_to_samples(indices: &[CueIndex], sample_rate: u32) -> Result<u64, anyhow::Error> {
+    let cue_ts = indices.iter().find(|t| t.number == 1);
 
     match cue_ts {
-        Some(ts) => {
-            let duration = TryInto::<TrackMSF>::try_into(ts.1.to_string().as_ref())?;
-            Ok((duration.to_duration_seconds() * sample_rate as f64) as u64)
-        }
+        Some(ts) => Ok(ts.to_samples(sample_rate)),
         None => bail!("Can`t find track INDEX 01"),
     }
 }
+
+/// Track of one FILE of a CUE sheet, positions in inter-channel samples of
+/// that file.
+pub struct TrackBounds {
+    pub track: usize,
+    /// INDEX 00, or INDEX 01 if the pregap is not in this file
+    pub gap_start: u64,
+    /// INDEX 01
+    pub start: u64,
+    /// Next pregap unless the track has a REM DURATION
+    pub end: u64,
+    pub source: LengthSource,
+}
+
+/// Tracks with their INDEX 01 in `file_id`, and the track after them if its
+/// pregap is at the end of this file.
+pub fn file_tracks(cue_sheet: &CueSheet, file_id: usize) -> (Vec<usize>, Option<usize>) {
+    let cue_file = &cue_sheet.cue;
+    let tracks: Vec<usize> = (0..cue_file.tracks.len())
+        .filter(|i| cue_file.tracks[*i].0 == file_id)
+        .collect();
+
+    let tail_track = tracks
+        .last()
+        .map(|i| i + 1)
+        .filter(|i| *i < cue_file.tracks.len())
+        .filter(|i| {
+            cue_sheet.indices[*i]
+                .iter()
+                .any(|x| x.number == 0 && x.file == file_id)
+        });
+
+    (tracks, tail_track)
+}
+
+/// Bounds of `tracks` in `file_id`, `tail_start` is the pregap of the next
+/// FILE at the end of this one. Ends are not checked against `length`.
+pub fn file_track_bounds(
+    cue_sheet: &CueSheet,
+    file_id: usize,
+    tracks: &[usize],
+    sample_rate: u32,
+    tail_start: Option<u64>,
+    length: u64,
+) -> Result<Vec<TrackBounds>, anyhow::Error> {
+    let cue_file = &cue_sheet.cue;
+    let mut bounds: Vec<(usize, u64, u64, Option<u64>)> = vec![];
+
+    for (k, i) in tracks.iter().enumerate() {
+        let track = &cue_file.tracks[*i].1;
+        let indices = &cue_sheet.indices[*i];
+        let track_num = i + 1;
+
+        let rem_duration = match track.comments.iter().find(|s| s.starts_with("DURATION ")) {
+            Some(rem) => {
+                let split: Vec<&str> = rem.split(" ").collect();
+                if split.len() < 2 {
+                    bail!("Failed to parse REM DURATION")
+                }
+                Some(
+                    split[1]
+                        .parse::<u64>()
+                        .context("Failed to parse REM DURATION")?,
+                )
+            }
+            None => None,
+        };
+
+        // REM DURATION is sample exact, the next track starts right after
+        let previous_end = bounds.last().and_then(|b| b.3);
+        let (gap_start, start) = match previous_end {
+            Some(end) => (end, end),
+            None => {
+                let start = cue_msf_to_samples(indices, sample_rate)?;
+                let gap_start = match k {
+                    0 => 0,
+                    _ if indices.iter().any(|x| x.number == 0 && x.file == file_id) => {
+                        index_to_samples(indices, 0, sample_rate)?
+                    }
+                    _ => start,
+                };
+                (gap_start, start)
+            }
+        };
+
+        if gap_start > start {
+            bail!("Track {} INDEX 00 is after INDEX 01", track_num);
+        }
+
+        if let Some(previous) = bounds.last() {
+            if previous.2 > gap_start {
+                bail!("Track {} starts before track {}", track_num, i);
+            }
+        }
+
+        bounds.push((*i, gap_start, start, rem_duration.map(|d| start + d)));
+    }
+
+    Ok((0..bounds.len())
+        .map(|k| {
+            let (track, gap_start, start, end) = bounds[k];
+            let (end, source) = match (end, bounds.get(k + 1), tail_start) {
+                (Some(end), ..) => (end, LengthSource::RemDuration),
+                (None, Some(next), _) => (next.1, LengthSource::Msf),
+                (None, None, Some(tail_start)) => (tail_start, LengthSource::Msf),
+                (None, None, None) => (length, LengthSource::FileEnd),
+            };
+            TrackBounds {
+                track,
+                gap_start,
+                start,
+                end,
+                source,
+            }
+        })
+        .collect())
+}
+
+pub fn index_to_samples(
+    indices: &[CueIndex],
+    number: u8,
+    sample_rate: u32,
+) -> Result<u64, anyhow::Error> {
+    match indices.iter().find(|x| x.number == number) {
+        Some(index) => Ok(index.to_samples(sample_rate)),
+        None => bail!("Can`t find track INDEX {:02}", number),
+    }
+}
+
+/// CUE strings are quoted without escapes, double quotes become single ones.
+fn cue_text(value: &str) -> String {
+    value.replace('"', "'")
+}
+
+/// Disc number of every track of a CUE sheet that spans discs: the `REM
+/// DISCNUMBER` of the track, of the track before it or of the album.
+pub fn track_disc_numbers(cue_file: &CUEFile) -> Vec<Option<usize>> {
+    let mut disc_number = rem_value(&cue_file.comments, "DISCNUMBER").and_then(|n| n.parse().ok());
+
+    cue_file
+        .tracks
+        .iter()
+        .map(|(_, track)| {
+            if let Some(n) = rem_value(&track.comments, "DISCNUMBER").and_then(|n| n.parse().ok()) {
+                disc_number = Some(n);
+            }
+            disc_number
+        })
+        .collect()
+}
+
+/// Value of a `REM KEY value` comment without quotes.
+pub fn rem_value(comments: &[String], key: &str) -> Option<String> {
+    comments.iter().find_map(|c| {
+        let value = c.strip_prefix(key)?.strip_prefix(' ')?.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .unwrap_or(value);
+        Some(value.to_string())
+    })
+}
@@ -0,0 +1,223 @@
+use std::path::Path;
+
+use anyhow::{bail, Context};
+use serde_json::json;
+use sha1::{Digest, Sha1};
+
+use crate::{
+    audio::get_audio_info,
+    cli::{Cli, MsfRounding},
+    cue::CueSheet,
+    report::Report,
+    track_msf::TrackMSF,
+    utils::progress,
+};
+
+/// CD frames before the first track, added to the offsets of the disc IDs
+/// that count from the start of the disc.
+const LEAD_IN: u64 = 150;
+
+/// Table of contents of a CD image, track offsets and lead-out position in CD
+/// frames from the start of the first audio file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Toc {
+    pub offsets: Vec<u64>,
+    pub leadout: u64,
+}
+
+/// Disc IDs of an image, as used by the catalogs and rip databases.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscIds {
+    pub musicbrainz: String,
+    pub freedb: u32,
+    pub accuraterip: (u32, u32),
+    pub cuetools: String,
+}
+
+impl Toc {
+    /// Track offsets are the INDEX 01 points, files of a multiple file CUE
+    /// sheet follow each other.
+    pub fn from_cue(cue_sheet: &CueSheet, input_dir: &Path) -> Result<Toc, anyhow::Error> {
+        let cue_file = &cue_sheet.cue;
+
+        if cue_file.tracks.is_empty() || cue_file.tracks.len() > 99 {
+            bail!(
+                "A CD has 1 to 99 tracks, the CUE sheet has {}",
+                cue_file.tracks.len()
+            );
+        }
+
+        let mut file_starts: Vec<u64> = vec![];
+        let mut leadout = 0;
+
+        for file in &cue_file.files {
+            let audio_file = input_dir.join(file);
+            let audio_info = get_audio_info(&audio_file)
+                .with_context(|| format!("Failed to read {}", audio_file.display()))?;
+            let length = audio_info.total_samples / audio_info.spec.channels as u64;
+
+            file_starts.push(leadout);
+            leadout +=
+                TrackMSF::from_samples(length, audio_info.spec.sample_rate, MsfRounding::Down)
+                    .frames();
+        }
+
+        let offsets = cue_sheet
+            .indices
+            .iter()
+            .enumerate()
+            .map(
+                |(i, indices)| match indices.iter().find(|x| x.number == 1) {
+                    Some(index) => Ok(file_starts[index.file] + index.position.frames()),
+                    None => bail!("Track {} has no INDEX 01", i + 1),
+                },
+            )
+            .collect::<Result<Vec<u64>, anyhow::Error>>()?;
+
+        if offsets.windows(2).any(|w| w[0] >= w[1]) || offsets.last() >= Some(&leadout) {
+            bail!("Track offsets are not in order or past the end of the audio");
+        }
+
+        Ok(Toc { offsets, leadout })
+    }
+
+    pub fn disc_ids(&self) -> DiscIds {
+        DiscIds {
+            musicbrainz: self.musicbrainz_id(),
+            freedb: self.freedb_id(),
+            accuraterip: self.accuraterip_ids(),
+            cuetools: self.cuetools_id(),
+        }
+    }
+
+    /// SHA-1 of the first and last track number, the lead-out and 99 track
+    /// offsets in hex, in MusicBrainz base64.
+    pub fn musicbrainz_id(&self) -> String {
+        let mut text = format!(
+            "{:02X}{:02X}{:08X}",
+            1,
+            self.offsets.len(),
+            self.leadout + LEAD_IN
+        );
+        for i in 0..99 {
+            let offset = self.offsets.get(i).map(|o| o + LEAD_IN).unwrap_or(0);
+            text.push_str(&format!("{:08X}", offset));
+        }
+
+        musicbrainz_base64(&Sha1::digest(text.as_bytes()))
+    }
+
+    /// Digit sum of the track start seconds, disc length in seconds and
+    /// track count.
+    pub fn freedb_id(&self) -> u32 {
+        let digit_sum = |mut n: u64| {
+            let mut sum = 0;
+            while n > 0 {
+                sum += n % 10;
+                n /= 10;
+            }
+            sum
+        };
+
+        let seconds = |frames: u64| (frames + LEAD_IN) / 75;
+        let n: u64 = self.offsets.iter().map(|o| digit_sum(seconds(*o))).sum();
+        let t = seconds(self.leadout) - seconds(self.offsets[0]);
+
+        ((n % 0xff) << 24 | t << 8 | self.offsets.len() as u64) as u32
+    }
+
+    /// Sum of the offsets and sum of the offsets times the track number, both
+    /// with the lead-out as the track after the last.
+    pub fn accuraterip_ids(&self) -> (u32, u32) {
+        let mut id1: u32 = 0;
+        let mut id2: u32 = 0;
+
+        for (i, offset) in self.offsets.iter().chain([&self.leadout]).enumerate() {
+            id1 = id1.wrapping_add(*offset as u32);
+            id2 = id2.wrapping_add((*offset as u32).max(1).wrapping_mul(i as u32 + 1));
+        }
+
+        (id1, id2)
+    }
+
+    /// AccurateRip response file name for this disc.
+    pub fn accuraterip_file_name(&self) -> String {
+        let (id1, id2) = self.accuraterip_ids();
+        format!(
+            "dBAR-{:03}-{:08x}-{:08x}-{:08x}.bin",
+            self.offsets.len(),
+            id1,
+            id2,
+            self.freedb_id()
+        )
+    }
+
+    /// CUETools database TOC ID, SHA-1 of the track offsets and lead-out
+    /// relative to track 1, in MusicBrainz base64.
+    pub fn cuetools_id(&self) -> String {
+        let first = self.offsets[0];
+        let mut text = String::new();
+        for offset in self.offsets.iter().skip(1).chain([&self.leadout]) {
+            text.push_str(&format!("{:08X}", offset - first));
+        }
+        while text.len() < 800 {
+            text.push('0');
+        }
+
+        musicbrainz_base64(&Sha1::digest(text.as_bytes()))
+    }
+}
+
+pub fn print_disc_ids(toc: &Toc, cli: &Cli, report: &mut Report) {
+    let ids = toc.disc_ids();
+
+    report.results.push((
+        "disc_ids",
+        json!({
+            "musicbrainz": ids.musicbrainz,
+            "freedb": format!("{:08x}", ids.freedb),
+            "accuraterip": format!("{:08x}-{:08x}", ids.accuraterip.0, ids.accuraterip.1),
+            "accuraterip_file": toc.accuraterip_file_name(),
+            "cuetools": ids.cuetools,
+        }),
+    ));
+
+    if cli.totally_silent {
+        return;
+    }
+    progress!(cli, "MusicBrainz: {}", ids.musicbrainz);
+    progress!(cli, "freedb:      {:08x}", ids.freedb);
+    progress!(
+        cli,
+        "AccurateRip: {:08x}-{:08x} ({})",
+        ids.accuraterip.0,
+        ids.accuraterip.1,
+        toc.accuraterip_file_name()
+    );
+    progress!(cli, "CUETools:    {}", ids.cuetools);
+}
+
+/// Base64 with `.`, `_` and `-` in place of `+`, `/` and `=`.
+fn musicbrainz_base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789._";
+
+    let mut text = String::new();
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let bits = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+
+        for k in 0..4 {
+            if k <= chunk.len() {
+                text.push(ALPHABET[(bits >> (18 - 6 * k) & 0x3f) as usize] as char);
+            } else {
+                text.push('-');
+            }
+        }
+    }
+
+    text
+}
@@ -0,0 +1,1223 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use claxon::{frame::FrameReader, input::BufferedReader, Block};
+use hound::{SampleFormat, WavSpec};
+use md5::{Digest, Md5};
+
+const BLOCK_SIZE: usize = 4096;
+const SEEK_POINT_INTERVAL_SECONDS: u64 = 10;
+const SEEK_POINT_PLACEHOLDER: u64 = u64::MAX;
+const STREAMINFO_LEN: u32 = 34;
+const SEEK_POINT_LEN: u32 = 18;
+/// Bytes searched for the next frame by a seek in a file without SEEKTABLE.
+const SCAN_BUFFER_LEN: u64 = 1 << 20;
+/// A seek without SEEKTABLE decodes from a frame at most this many bytes
+/// before the wanted one.
+const SCAN_PRECISION: u64 = 1 << 16;
+const VENDOR: &str = concat!("wfcue ", env!("CARGO_PKG_VERSION"));
+
+pub const MAX_COMPRESSION_LEVEL: u8 = 8;
+
+struct EncoderParams {
+    max_fixed_order: usize,
+    max_lpc_order: usize,
+    max_partition_order: u32,
+    stereo_decorrelation: bool,
+}
+
+impl EncoderParams {
+    fn from_level(level: u8) -> Self {
+        let (max_fixed_order, max_lpc_order, max_partition_order, stereo_decorrelation) =
+            match level {
+                0 => (2, 0, 3, false),
+                1 => (4, 0, 3, true),
+                2 => (4, 0, 4, true),
+                3 => (4, 6, 4, true),
+                4 => (4, 8, 4, true),
+                5 => (4, 8, 5, true),
+                6 => (4, 8, 6, true),
+                7 => (4, 12, 6, true),
+                _ => (4, 12, 8, true),
+            };
+
+        EncoderParams {
+            max_fixed_order,
+            max_lpc_order,
+            max_partition_order,
+            stereo_decorrelation,
+        }
+    }
+}
+
+struct FrameInfo {
+    sample: u64,
+    offset: u64,
+    samples: u16,
+}
+
+/// FLAC encoder with a `WavWriter`-like interface: interleaved samples in,
+/// fixed-size frames out. STREAMINFO (total samples, frame sizes, MD5) and
+/// the SEEKTABLE are rewritten in place on `finalize`.
+pub struct FlacWriter {
+    writer: BufWriter<File>,
+    spec: WavSpec,
+    params: EncoderParams,
+    buffer: Vec<Vec<i64>>,
+    channel: usize,
+    md5: Md5,
+    md5_bytes: Vec<u8>,
+    frames: Vec<FrameInfo>,
+    frames_len: u64,
+    total_samples: u64,
+    min_frame_size: u32,
+    max_frame_size: u32,
+    streaminfo_pos: u64,
+    seektable_pos: u64,
+    seek_points: usize,
+}
+
+impl FlacWriter {
+    /// `expected_samples` is the number of inter-channel samples the caller
+    /// is going to write, it is only used to reserve room for the seek table.
+    pub fn create(
+        file: &Path,
+        spec: WavSpec,
+        compression_level: u8,
+        expected_samples: u64,
+        comments: &[String],
+    ) -> Result<FlacWriter, anyhow::Error> {
+        if spec.sample_format != SampleFormat::Int {
+            bail!("FLAC output supports integer samples only");
+        }
+
+        if !(4..=24).contains(&spec.bits_per_sample) {
+            bail!(
+                "FLAC output supports 4 to 24 bits per sample, got {}",
+                spec.bits_per_sample
+            );
+        }
+
+        if spec.channels == 0 || spec.channels > 8 {
+            bail!(
+                "FLAC output supports 1 to 8 channels, got {}",
+                spec.channels
+            );
+        }
+
+        if spec.sample_rate == 0 || spec.sample_rate >= 1 << 20 {
+            bail!("Unsupported FLAC sample rate {}", spec.sample_rate);
+        }
+
+        if compression_level > MAX_COMPRESSION_LEVEL {
+            bail!(
+                "FLAC compression level must be between 0 and {}",
+                MAX_COMPRESSION_LEVEL
+            );
+        }
+
+        let seek_interval = spec.sample_rate as u64 * SEEK_POINT_INTERVAL_SECONDS;
+        let seek_points = (expected_samples.div_ceil(seek_interval) as usize).max(1);
+
+        let mut writer = BufWriter::new(File::create(file)?);
+
+        writer.write_all(b"fLaC")?;
+
+        write_metadata_header(&mut writer, false, 0, STREAMINFO_LEN)?;
+        let streaminfo_pos = writer.stream_position()?;
+        writer.write_all(&[0; STREAMINFO_LEN as usize])?;
+
+        write_metadata_header(&mut writer, false, 3, SEEK_POINT_LEN * seek_points as u32)?;
+        let seektable_pos = writer.stream_position()?;
+        for _ in 0..seek_points {
+            write_seek_point(&mut writer, SEEK_POINT_PLACEHOLDER, 0, 0)?;
+        }
+
+        let vorbis_comment_len =
+            4 + VENDOR.len() as u32 + 4 + comments.iter().map(|c| 4 + c.len() as u32).sum::<u32>();
+        write_metadata_header(&mut writer, true, 4, vorbis_comment_len)?;
+        writer.write_all(&(VENDOR.len() as u32).to_le_bytes())?;
+        writer.write_all(VENDOR.as_bytes())?;
+        writer.write_all(&(comments.len() as u32).to_le_bytes())?;
+        for comment in comments {
+            writer.write_all(&(comment.len() as u32).to_le_bytes())?;
+            writer.write_all(comment.as_bytes())?;
+        }
+
+        Ok(FlacWriter {
+            writer,
+            spec,
+            params: EncoderParams::from_level(compression_level),
+            buffer: vec![Vec::with_capacity(BLOCK_SIZE); spec.channels as usize],
+            channel: 0,
+            md5: Md5::new(),
+            md5_bytes: Vec::with_capacity(BLOCK_SIZE * 3 * spec.channels as usize),
+            frames: vec![],
+            frames_len: 0,
+            total_samples: 0,
+            min_frame_size: u32::MAX,
+            max_frame_size: 0,
+            streaminfo_pos,
+            seektable_pos,
+            seek_points,
+        })
+    }
+
+    pub fn write_sample(&mut self, sample: i32) -> Result<(), anyhow::Error> {
+        let bits = self.spec.bits_per_sample as u32;
+        let min = -(1i64 << (bits - 1));
+        let max = (1i64 << (bits - 1)) - 1;
+        let sample = sample as i64;
+
+        if sample < min || sample > max {
+            bail!("Sample {} does not fit into {} bits", sample, bits);
+        }
+
+        let byte_width = bits.div_ceil(8) as usize;
+        self.md5_bytes
+            .extend_from_slice(&(sample as i32).to_le_bytes()[..byte_width]);
+
+        self.buffer[self.channel].push(sample);
+        self.channel += 1;
+
+        if self.channel == self.spec.channels as usize {
+            self.channel = 0;
+
+            if self.buffer[0].len() == BLOCK_SIZE {
+                self.write_frame()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn finalize(mut self) -> Result<(), anyhow::Error> {
+        if self.channel != 0 {
+            bail!("Number of samples written is not a multiple of the channel count");
+        }
+
+        if !self.buffer[0].is_empty() {
+            self.write_frame()?;
+        }
+
+        let md5sum = self.md5.finalize_reset();
+
+        if self.frames.is_empty() {
+            self.min_frame_size = 0;
+        }
+
+        let mut streaminfo = BitWriter::new();
+        streaminfo.write(BLOCK_SIZE as u64, 16);
+        streaminfo.write(BLOCK_SIZE as u64, 16);
+        streaminfo.write(self.min_frame_size as u64, 24);
+        streaminfo.write(self.max_frame_size as u64, 24);
+        streaminfo.write(self.spec.sample_rate as u64, 20);
+        streaminfo.write(self.spec.channels as u64 - 1, 3);
+        streaminfo.write(self.spec.bits_per_sample as u64 - 1, 5);
+        streaminfo.write(self.total_samples >> 32, 4);
+        streaminfo.write(self.total_samples & 0xFFFFFFFF, 32);
+
+        self.writer.seek(SeekFrom::Start(self.streaminfo_pos))?;
+        self.writer.write_all(&streaminfo.bytes)?;
+        self.writer.write_all(&md5sum)?;
+
+        self.writer.seek(SeekFrom::Start(self.seektable_pos))?;
+        for (sample, offset, samples) in self.seek_table() {
+            write_seek_point(&mut self.writer, sample, offset, samples)?;
+        }
+
+        self.writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Picks the first frame at or after every seek interval, the interval is
+    /// widened when the stream turned out longer than the reserved table.
+    fn seek_table(&self) -> Vec<(u64, u64, u16)> {
+        let mut points = vec![];
+        let min_interval = self.spec.sample_rate as u64 * SEEK_POINT_INTERVAL_SECONDS;
+        let interval = min_interval.max(self.total_samples.div_ceil(self.seek_points as u64));
+        let mut target = 0;
+
+        for frame in &self.frames {
+            if points.len() == self.seek_points {
+                break;
+            }
+
+            if frame.sample + frame.samples as u64 > target {
+                points.push((frame.sample, frame.offset, frame.samples));
+                while target < frame.sample + frame.samples as u64 {
+                    target += interval;
+                }
+            }
+        }
+
+        while points.len() < self.seek_points {
+            points.push((SEEK_POINT_PLACEHOLDER, 0, 0));
+        }
+
+        points
+    }
+
+    fn write_frame(&mut self) -> Result<(), anyhow::Error> {
+        let block_size = self.buffer[0].len();
+        let bits = self.spec.bits_per_sample as u32;
+        let (channel_assignment, subframes) = self.encode_subframes(bits);
+
+        let mut frame = BitWriter::new();
+        frame.write(0b11111111111110, 14);
+        frame.write(0, 1);
+        frame.write(0, 1);
+        frame.write(0b0111, 4);
+        frame.write(sample_rate_code(self.spec.sample_rate), 4);
+        frame.write(channel_assignment, 4);
+        frame.write(sample_size_code(bits), 3);
+        frame.write(0, 1);
+        frame.write_utf8(self.frames.len() as u64);
+        frame.write(block_size as u64 - 1, 16);
+        let crc = crc8(&frame.bytes);
+        frame.write(crc as u64, 8);
+
+        for subframe in &subframes {
+            subframe.write(&mut frame);
+        }
+
+        frame.align();
+        let crc = crc16(&frame.bytes);
+        frame.write(crc as u64, 16);
+
+        self.writer.write_all(&frame.bytes)?;
+
+        let frame_size = frame.bytes.len() as u32;
+        self.min_frame_size = self.min_frame_size.min(frame_size);
+        self.max_frame_size = self.max_frame_size.max(frame_size);
+        self.frames.push(FrameInfo {
+            sample: self.total_samples,
+            offset: self.frames_len,
+            samples: block_size as u16,
+        });
+        self.frames_len += frame_size as u64;
+        self.total_samples += block_size as u64;
+
+        self.md5.update(&self.md5_bytes);
+        self.md5_bytes.clear();
+        self.buffer.iter_mut().for_each(|c| c.clear());
+
+        Ok(())
+    }
+
+    fn encode_subframes(&self, bits: u32) -> (u64, Vec<Subframe>) {
+        if self.spec.channels != 2 || !self.params.stereo_decorrelation {
+            let subframes = self
+                .buffer
+                .iter()
+                .map(|c| Subframe::encode(c, bits, &self.params))
+                .collect();
+            return (self.spec.channels as u64 - 1, subframes);
+        }
+
+        let left = &self.buffer[0];
+        let right = &self.buffer[1];
+        let mid: Vec<i64> = left.iter().zip(right).map(|(l, r)| (l + r) >> 1).collect();
+        let side: Vec<i64> = left.iter().zip(right).map(|(l, r)| l - r).collect();
+
+        let left = Subframe::encode(left, bits, &self.params);
+        let right = Subframe::encode(right, bits, &self.params);
+        let mid = Subframe::encode(&mid, bits, &self.params);
+        let side = Subframe::encode(&side, bits + 1, &self.params);
+
+        let candidates = [
+            (0b0001, left.bits + right.bits),
+            (0b1000, left.bits + side.bits),
+            (0b1001, side.bits + right.bits),
+            (0b1010, mid.bits + side.bits),
+        ];
+
+        let (assignment, _) = candidates
+            .iter()
+            .min_by_key(|(_, bits)| *bits)
+            .copied()
+            .unwrap_or(candidates[0]);
+
+        let subframes = match assignment {
+            0b1000 => vec![left, side],
+            0b1001 => vec![side, right],
+            0b1010 => vec![mid, side],
+            _ => vec![left, right],
+        };
+
+        (assignment, subframes)
+    }
+}
+
+enum Predictor {
+    Constant,
+    Verbatim,
+    Fixed,
+    Lpc {
+        coefficients: Vec<i64>,
+        precision: u32,
+        shift: u32,
+    },
+}
+
+struct Subframe {
+    predictor: Predictor,
+    bits_per_sample: u32,
+    warm_up: Vec<i64>,
+    residual: Vec<i64>,
+    partition_order: u32,
+    rice_params: Vec<u32>,
+    bits: u64,
+}
+
+impl Subframe {
+    fn encode(samples: &[i64], bits_per_sample: u32, params: &EncoderParams) -> Subframe {
+        let block_size = samples.len();
+
+        if samples.iter().all(|s| *s == samples[0]) {
+            return Subframe {
+                predictor: Predictor::Constant,
+                bits_per_sample,
+                warm_up: vec![samples[0]],
+                residual: vec![],
+                partition_order: 0,
+                rice_params: vec![],
+                bits: 8 + bits_per_sample as u64,
+            };
+        }
+
+        let mut best = Subframe {
+            predictor: Predictor::Verbatim,
+            bits_per_sample,
+            warm_up: samples.to_vec(),
+            residual: vec![],
+            partition_order: 0,
+            rice_params: vec![],
+            bits: 8 + bits_per_sample as u64 * block_size as u64,
+        };
+
+        for order in 0..=params.max_fixed_order.min(block_size - 1) {
+            let residual = fixed_residual(samples, order);
+            let Some((partition_order, rice_params, residual_bits)) =
+                rice_partitioning(&residual, order, block_size, params.max_partition_order)
+            else {
+                continue;
+            };
+
+            let bits = 8 + order as u64 * bits_per_sample as u64 + residual_bits;
+            if bits < best.bits {
+                best = Subframe {
+                    predictor: Predictor::Fixed,
+                    bits_per_sample,
+                    warm_up: samples[..order].to_vec(),
+                    residual,
+                    partition_order,
+                    rice_params,
+                    bits,
+                };
+            }
+        }
+
+        let max_lpc_order = params.max_lpc_order.min(block_size - 1);
+        if max_lpc_order == 0 {
+            return best;
+        }
+
+        let precision = if bits_per_sample <= 16 { 12 } else { 15 };
+
+        for (order, lpc) in lpc_coefficients(samples, max_lpc_order)
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (i + 1, c))
+        {
+            let Some((coefficients, shift)) = quantize_coefficients(lpc, precision) else {
+                continue;
+            };
+
+            let Some(residual) = lpc_residual(samples, &coefficients, shift) else {
+                continue;
+            };
+
+            let Some((partition_order, rice_params, residual_bits)) =
+                rice_partitioning(&residual, order, block_size, params.max_partition_order)
+            else {
+                continue;
+            };
+
+            let bits = 8
+                + order as u64 * bits_per_sample as u64
+                + 4
+                + 5
+                + order as u64 * precision as u64
+                + residual_bits;
+
+            if bits < best.bits {
+                best = Subframe {
+                    predictor: Predictor::Lpc {
+                        coefficients,
+                        precision,
+                        shift,
+                    },
+                    bits_per_sample,
+                    warm_up: samples[..order].to_vec(),
+                    residual,
+                    partition_order,
+                    rice_params,
+                    bits,
+                };
+            }
+        }
+
+        best
+    }
+
+    fn write(&self, out: &mut BitWriter) {
+        let bps = self.bits_per_sample;
+
+        match &self.predictor {
+            Predictor::Constant => {
+                out.write(0b000000 << 1, 8);
+                out.write_signed(self.warm_up[0], bps);
+            }
+            Predictor::Verbatim => {
+                out.write(0b000001 << 1, 8);
+                self.warm_up.iter().for_each(|s| out.write_signed(*s, bps));
+            }
+            Predictor::Fixed => {
+                out.write((0b001000 | self.warm_up.len() as u64) << 1, 8);
+                self.warm_up.iter().for_each(|s| out.write_signed(*s, bps));
+                self.write_residual(out);
+            }
+            Predictor::Lpc {
+                coefficients,
+                precision,
+                shift,
+            } => {
+                out.write((0b100000 | (coefficients.len() as u64 - 1)) << 1, 8);
+                self.warm_up.iter().for_each(|s| out.write_signed(*s, bps));
+                out.write(*precision as u64 - 1, 4);
+                out.write(*shift as u64, 5);
+                coefficients
+                    .iter()
+                    .for_each(|c| out.write_signed(*c, *precision));
+                self.write_residual(out);
+            }
+        }
+    }
+
+    fn write_residual(&self, out: &mut BitWriter) {
+        let param_bits = if self.rice_params.iter().any(|k| *k > 14) {
+            out.write(0b01, 2);
+            5
+        } else {
+            out.write(0b00, 2);
+            4
+        };
+        out.write(self.partition_order as u64, 4);
+
+        let block_size = self.residual.len() + self.warm_up.len();
+        let partition_len = block_size >> self.partition_order;
+        let mut start = 0;
+
+        for (i, k) in self.rice_params.iter().enumerate() {
+            let len = match i {
+                0 => partition_len - self.warm_up.len(),
+                _ => partition_len,
+            };
+
+            out.write(*k as u64, param_bits);
+            for r in &self.residual[start..start + len] {
+                out.write_rice(zigzag(*r), *k);
+            }
+
+            start += len;
+        }
+    }
+}
+
+fn fixed_residual(samples: &[i64], order: usize) -> Vec<i64> {
+    let s = samples;
+
+    (order..s.len())
+        .map(|i| match order {
+            0 => s[i],
+            1 => s[i] - s[i - 1],
+            2 => s[i] - 2 * s[i - 1] + s[i - 2],
+            3 => s[i] - 3 * s[i - 1] + 3 * s[i - 2] - s[i - 3],
+            _ => s[i] - 4 * s[i - 1] + 6 * s[i - 2] - 4 * s[i - 3] + s[i - 4],
+        })
+        .collect()
+}
+
+/// Tukey(0.5) windowed autocorrelation followed by Levinson-Durbin recursion,
+/// returns the predictor coefficients for every order up to `max_order`.
+fn lpc_coefficients(samples: &[i64], max_order: usize) -> Vec<Vec<f64>> {
+    let n = samples.len();
+    let taper = ((n as f64 * 0.25) as usize).max(1);
+    let windowed: Vec<f64> = samples
+        .iter()
+        .enumerate()
+        .map(|(i, s)| {
+            let w = if i < taper {
+                0.5 - 0.5 * (std::f64::consts::PI * i as f64 / taper as f64).cos()
+            } else if i >= n - taper {
+                0.5 - 0.5 * (std::f64::consts::PI * (n - 1 - i) as f64 / taper as f64).cos()
+            } else {
+                1.0
+            };
+            *s as f64 * w
+        })
+        .collect();
+
+    let autoc: Vec<f64> = (0..=max_order)
+        .map(|lag| {
+            windowed[lag..]
+                .iter()
+                .zip(&windowed)
+                .map(|(a, b)| a * b)
+                .sum()
+        })
+        .collect();
+
+    let mut result = vec![];
+    if autoc[0] == 0.0 {
+        return result;
+    }
+
+    let mut lpc = vec![0.0; max_order];
+    let mut error = autoc[0];
+
+    for i in 0..max_order {
+        let mut r = -autoc[i + 1];
+        for j in 0..i {
+            r -= lpc[j] * autoc[i - j];
+        }
+        r /= error;
+
+        lpc[i] = r;
+        for j in 0..i / 2 {
+            let tmp = lpc[j];
+            lpc[j] += r * lpc[i - 1 - j];
+            lpc[i - 1 - j] += r * tmp;
+        }
+        if i % 2 == 1 {
+            lpc[i / 2] += lpc[i / 2] * r;
+        }
+
+        error *= 1.0 - r * r;
+
+        result.push(lpc[..=i].iter().map(|c| -c).collect());
+
+        if error <= 0.0 {
+            break;
+        }
+    }
+
+    result
+}
+
+fn quantize_coefficients(lpc: &[f64], precision: u32) -> Option<(Vec<i64>, u32)> {
+    let cmax = lpc.iter().fold(0.0f64, |m, c| m.max(c.abs()));
+    if cmax <= 0.0 || !cmax.is_finite() {
+        return None;
+    }
+
+    let log2cmax = cmax.log2().floor() as i32;
+    let shift = (precision as i32 - 2 - log2cmax).min(15);
+    if shift < 0 {
+        return None;
+    }
+
+    let qmax = (1i64 << (precision - 1)) - 1;
+    let qmin = -(1i64 << (precision - 1));
+    let mut error = 0.0;
+    let coefficients = lpc
+        .iter()
+        .map(|c| {
+            error += c * (1i64 << shift) as f64;
+            let q = (error.round() as i64).clamp(qmin, qmax);
+            error -= q as f64;
+            q
+        })
+        .collect();
+
+    Some((coefficients, shift as u32))
+}
+
+fn lpc_residual(samples: &[i64], coefficients: &[i64], shift: u32) -> Option<Vec<i64>> {
+    let order = coefficients.len();
+    let mut residual = Vec::with_capacity(samples.len() - order);
+
+    for i in order..samples.len() {
+        let prediction: i64 = coefficients
+            .iter()
+            .enumerate()
+            .map(|(j, c)| c * samples[i - 1 - j])
+            .sum::<i64>()
+            >> shift;
+        let r = samples[i] - prediction;
+
+        if r < i32::MIN as i64 || r > i32::MAX as i64 {
+            return None;
+        }
+
+        residual.push(r);
+    }
+
+    Some(residual)
+}
+
+/// Finds the partition order and the Rice parameters that give the smallest
+/// residual, returns `None` when no partition order is valid for the block.
+fn rice_partitioning(
+    residual: &[i64],
+    predictor_order: usize,
+    block_size: usize,
+    max_partition_order: u32,
+) -> Option<(u32, Vec<u32>, u64)> {
+    let mut best: Option<(u32, Vec<u32>, u64)> = None;
+
+    for partition_order in 0..=max_partition_order {
+        let partitions = 1usize << partition_order;
+        if !block_size.is_multiple_of(partitions) || block_size / partitions <= predictor_order {
+            break;
+        }
+
+        let partition_len = block_size / partitions;
+        let mut start = 0;
+        let mut params = Vec::with_capacity(partitions);
+        let mut bits = 2 + 4;
+
+        for p in 0..partitions {
+            let len = match p {
+                0 => partition_len - predictor_order,
+                _ => partition_len,
+            };
+            let sum: u64 = residual[start..start + len]
+                .iter()
+                .map(|r| zigzag(*r))
+                .sum();
+            let (k, partition_bits) = rice_parameter(len as u64, sum);
+
+            params.push(k);
+            bits += partition_bits;
+            start += len;
+        }
+
+        bits += if params.iter().any(|k| *k > 14) {
+            5 * partitions as u64
+        } else {
+            4 * partitions as u64
+        };
+
+        if best.as_ref().is_none_or(|b| bits < b.2) {
+            best = Some((partition_order, params, bits));
+        }
+    }
+
+    best
+}
+
+fn rice_parameter(len: u64, sum: u64) -> (u32, u64) {
+    if len == 0 || sum == 0 {
+        return (0, len);
+    }
+
+    let mean = sum / len;
+    let estimate = match mean {
+        0 => 0,
+        m => 63 - m.leading_zeros(),
+    };
+
+    (estimate.saturating_sub(1).min(30)..=(estimate + 1).min(30))
+        .map(|k| (k, len * (k as u64 + 1) + (sum >> k)))
+        .min_by_key(|(_, bits)| *bits)
+        .unwrap_or((0, len + sum))
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn sample_rate_code(sample_rate: u32) -> u64 {
+    match sample_rate {
+        88200 => 0b0001,
+        176400 => 0b0010,
+        192000 => 0b0011,
+        8000 => 0b0100,
+        16000 => 0b0101,
+        22050 => 0b0110,
+        24000 => 0b0111,
+        32000 => 0b1000,
+        44100 => 0b1001,
+        48000 => 0b1010,
+        96000 => 0b1011,
+        _ => 0b0000,
+    }
+}
+
+fn sample_size_code(bits: u32) -> u64 {
+    match bits {
+        8 => 0b001,
+        12 => 0b010,
+        16 => 0b100,
+        20 => 0b101,
+        24 => 0b110,
+        _ => 0b000,
+    }
+}
+
+/// Track of a FLAC CUESHEET metadata block, offsets are inter-channel
+/// samples.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CueSheetTrack {
+    pub number: u8,
+    pub offset: u64,
+    /// INDEX points as (number, offset from the track offset)
+    pub indices: Vec<(u8, u64)>,
+}
+
+/// Tracks of the CUESHEET metadata block of a FLAC file, without the
+/// lead-out track. `None` if the file has no CUESHEET block.
+pub fn read_cuesheet_block(file: &Path) -> Result<Option<Vec<CueSheetTrack>>, anyhow::Error> {
+    let (blocks, _) = read_metadata_blocks(file, &[5])?;
+
+    blocks
+        .first()
+        .map(|(_, block)| parse_cuesheet_block(block))
+        .transpose()
+}
+
+/// FLAC metadata block as (type, data).
+type MetadataBlock = (u8, Vec<u8>);
+
+/// Metadata blocks of a FLAC file that are one of `block_types`, and the
+/// offset of the first frame.
+fn read_metadata_blocks(
+    file: &Path,
+    block_types: &[u8],
+) -> Result<(Vec<MetadataBlock>, u64), anyhow::Error> {
+    let mut reader = BufReader::new(File::open(file)?);
+
+    let mut marker = [0; 4];
+    reader.read_exact(&mut marker)?;
+    if &marker != b"fLaC" {
+        bail!("{} is not a FLAC file", file.display());
+    }
+
+    let mut blocks = vec![];
+
+    loop {
+        let mut header = [0; 4];
+        reader
+            .read_exact(&mut header)
+            .context("Failed to read FLAC metadata block header")?;
+        let last = header[0] & 0x80 != 0;
+        let block_type = header[0] & 0x7f;
+        let length = u32::from_be_bytes([0, header[1], header[2], header[3]]);
+
+        if block_types.contains(&block_type) {
+            let mut block = vec![0; length as usize];
+            reader
+                .read_exact(&mut block)
+                .context("Failed to read FLAC metadata block")?;
+            blocks.push((block_type, block));
+        } else {
+            reader.seek_relative(length as i64)?;
+        }
+
+        if last {
+            return Ok((blocks, reader.stream_position()?));
+        }
+    }
+}
+
+fn parse_cuesheet_block(block: &[u8]) -> Result<Vec<CueSheetTrack>, anyhow::Error> {
+    let truncated = || anyhow::anyhow!("Truncated FLAC CUESHEET block");
+    let u64_at = |p: usize| -> Result<u64, anyhow::Error> {
+        Ok(u64::from_be_bytes(
+            block
+                .get(p..p + 8)
+                .ok_or_else(truncated)?
+                .try_into()
+                .unwrap(),
+        ))
+    };
+    let u8_at = |p: usize| block.get(p).copied().ok_or_else(truncated);
+
+    // Catalog number, lead-in samples, CD flag and reserved bytes
+    let mut p = 128 + 8 + 1 + 258;
+    let count = u8_at(p)?;
+    p += 1;
+
+    let mut tracks = vec![];
+    for _ in 0..count {
+        let offset = u64_at(p)?;
+        let number = u8_at(p + 8)?;
+        // ISRC, track type and reserved bytes
+        let index_count = u8_at(p + 8 + 1 + 12 + 1 + 13)?;
+        p += 36;
+
+        let mut indices = vec![];
+        for _ in 0..index_count {
+            indices.push((u8_at(p + 8)?, u64_at(p)?));
+            p += 12;
+        }
+
+        // Lead-out is 170 on CDs and 255 otherwise
+        if number != 170 && number != 255 {
+            tracks.push(CueSheetTrack {
+                number,
+                offset,
+                indices,
+            });
+        }
+    }
+
+    Ok(tracks)
+}
+
+/// Interleaved samples of a FLAC file like `FlacReader::samples`, that can
+/// go on from any sample. The frame with the sample is found with the
+/// SEEKTABLE or, without one, by bisecting the file for frames, only frames
+/// from there on are decoded.
+pub struct FlacSampleReader {
+    file: PathBuf,
+    frames: FrameReader<BufferedReader<File>>,
+    block: Block,
+    /// Interleaved index of the next sample in `block`
+    position: usize,
+    audio_start: u64,
+    audio_end: u64,
+    /// Seek points as (sample, offset from the first frame)
+    seek_points: Vec<(u64, u64)>,
+    /// Block size of all frames but the last one, if the stream has one
+    fixed_block_size: Option<u64>,
+    /// Inter-channel samples, 0 if unknown
+    total_samples: u64,
+}
+
+impl FlacSampleReader {
+    pub fn open(file: &Path) -> Result<FlacSampleReader, anyhow::Error> {
+        let (blocks, audio_start) = read_metadata_blocks(file, &[0, 3])?;
+        let mut seek_points = vec![];
+        let mut fixed_block_size = None;
+        let mut total_samples = 0;
+
+        for (block_type, block) in blocks {
+            match block_type {
+                0 => {
+                    if block.len() < STREAMINFO_LEN as usize {
+                        bail!("Truncated FLAC STREAMINFO block");
+                    }
+                    let min_block_size = u16::from_be_bytes([block[0], block[1]]);
+                    let max_block_size = u16::from_be_bytes([block[2], block[3]]);
+                    if min_block_size == max_block_size {
+                        fixed_block_size = Some(max_block_size as u64);
+                    }
+                    total_samples =
+                        u64::from_be_bytes(block[10..18].try_into().unwrap()) & ((1 << 36) - 1);
+                }
+                _ => {
+                    for point in block.chunks_exact(SEEK_POINT_LEN as usize) {
+                        let sample = u64::from_be_bytes(point[0..8].try_into().unwrap());
+                        let offset = u64::from_be_bytes(point[8..16].try_into().unwrap());
+                        if sample != SEEK_POINT_PLACEHOLDER {
+                            seek_points.push((sample, offset));
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(FlacSampleReader {
+            file: file.to_path_buf(),
+            frames: open_frames(file, audio_start)?,
+            block: Block::empty(),
+            position: 0,
+            audio_start,
+            audio_end: file.metadata()?.len(),
+            seek_points,
+            fixed_block_size,
+            total_samples,
+        })
+    }
+
+    /// Goes on at inter-channel sample `sample`.
+    pub fn seek(&mut self, sample: u64) -> Result<(), anyhow::Error> {
+        let offset = match self.seek_points.iter().rev().find(|(s, _)| *s <= sample) {
+            Some((_, offset)) => self.audio_start + offset,
+            None => self.find_frame(sample)?,
+        };
+
+        self.frames = open_frames(&self.file, offset)?;
+
+        loop {
+            if !self.next_block()? {
+                bail!("Sample {} is after the end of the FLAC file", sample);
+            }
+
+            let time = self.block_time(&self.block);
+            let duration = self.block.duration() as u64;
+
+            if time > sample {
+                bail!("Failed to seek to sample {} of the FLAC file", sample);
+            }
+
+            if sample < time + duration {
+                self.position = ((sample - time) * self.block.channels() as u64) as usize;
+                return Ok(());
+            }
+        }
+    }
+
+    /// Offset of a frame at or before `sample`, the file is bisected until
+    /// that frame is known to within `SCAN_PRECISION` bytes.
+    fn find_frame(&self, sample: u64) -> Result<u64, anyhow::Error> {
+        let (mut low, mut high) = (self.audio_start, self.audio_end);
+
+        while high - low > SCAN_PRECISION {
+            let middle = low + (high - low) / 2;
+
+            match self.next_frame(middle)? {
+                Some((offset, time)) if offset < high && time <= sample => low = offset,
+                _ => high = middle,
+            }
+        }
+
+        Ok(low)
+    }
+
+    /// First frame at or after `offset` as (offset, first sample). A sync
+    /// code only starts a frame if the frame decodes with valid CRCs.
+    fn next_frame(&self, offset: u64) -> Result<Option<(u64, u64)>, anyhow::Error> {
+        let mut file = File::open(&self.file)?;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut buffer = vec![];
+        file.take(SCAN_BUFFER_LEN).read_to_end(&mut buffer)?;
+
+        for p in 0..buffer.len().saturating_sub(1) {
+            if buffer[p] != 0xff || buffer[p + 1] & 0xfe != 0xf8 {
+                continue;
+            }
+
+            let mut frames = FrameReader::new(Cursor::new(&buffer[p..]));
+            if let Ok(Some(block)) = frames.read_next_or_eof(vec![]) {
+                return Ok(Some((offset + p as u64, self.block_time(&block))));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn next_block(&mut self) -> Result<bool, anyhow::Error> {
+        let buffer = std::mem::replace(&mut self.block, Block::empty()).into_buffer();
+
+        match self.frames.read_next_or_eof(buffer)? {
+            Some(block) => {
+                self.block = block;
+                self.position = 0;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// claxon takes the frame number times the size of the frame, which is
+    /// wrong for the shorter last frame of a stream with a fixed block size.
+    fn block_time(&self, block: &Block) -> u64 {
+        let duration = block.duration() as u64;
+
+        match self.fixed_block_size {
+            Some(size) if duration < size && self.total_samples >= duration => {
+                self.total_samples - duration
+            }
+            _ => block.time(),
+        }
+    }
+}
+
+impl Iterator for FlacSampleReader {
+    type Item = Result<i32, anyhow::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.position >= self.block.len() as usize {
+            match self.next_block() {
+                Ok(true) => (),
+                Ok(false) => return None,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+
+        let channels = self.block.channels();
+        let k = self.position as u32;
+        self.position += 1;
+
+        Some(Ok(self.block.sample(k % channels, k / channels)))
+    }
+}
+
+fn open_frames(
+    file: &Path,
+    offset: u64,
+) -> Result<FrameReader<BufferedReader<File>>, anyhow::Error> {
+    let mut reader = File::open(file)?;
+    reader.seek(SeekFrom::Start(offset))?;
+
+    Ok(FrameReader::new(BufferedReader::new(reader)))
+}
+
+fn write_metadata_header(
+    writer: &mut impl Write,
+    last: bool,
+    block_type: u8,
+    length: u32,
+) -> Result<(), anyhow::Error> {
+    let header = ((last as u32) << 31) | ((block_type as u32) << 24) | length;
+    writer
+        .write_all(&header.to_be_bytes())
+        .context("Failed to write FLAC metadata block header")
+}
+
+fn write_seek_point(
+    writer: &mut impl Write,
+    sample: u64,
+    offset: u64,
+    samples: u16,
+) -> Result<(), anyhow::Error> {
+    writer.write_all(&sample.to_be_bytes())?;
+    writer.write_all(&offset.to_be_bytes())?;
+    writer.write_all(&samples.to_be_bytes())?;
+    Ok(())
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u64,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        BitWriter {
+            bytes: vec![],
+            acc: 0,
+            bits: 0,
+        }
+    }
+
+    fn write(&mut self, value: u64, bits: u32) {
+        debug_assert!(bits <= 32);
+        let mask = (1u64 << bits) - 1;
+        self.acc = (self.acc << bits) | (value & mask);
+        self.bits += bits;
+
+        while self.bits >= 8 {
+            self.bits -= 8;
+            self.bytes.push((self.acc >> self.bits) as u8);
+        }
+    }
+
+    fn write_signed(&mut self, value: i64, bits: u32) {
+        self.write(value as u64, bits);
+    }
+
+    fn write_rice(&mut self, value: u64, k: u32) {
+        let mut quotient = value >> k;
+
+        while quotient >= 32 {
+            self.write(0, 32);
+            quotient -= 32;
+        }
+
+        self.write(1, quotient as u32 + 1);
+        if k > 0 {
+            self.write(value, k);
+        }
+    }
+
+    fn write_utf8(&mut self, value: u64) {
+        if value < 0x80 {
+            self.write(value, 8);
+            return;
+        }
+
+        let mut continuation = 1;
+        while value >> (6 * continuation) >= 1 << (6 - continuation) {
+            continuation += 1;
+        }
+
+        let lead = (0xFF00u64 >> (continuation + 1)) & 0xFF;
+        self.write(lead | (value >> (6 * continuation)), 8);
+
+        for i in (0..continuation).rev() {
+            self.write(0x80 | ((value >> (6 * i)) & 0x3F), 8);
+        }
+    }
+
+    fn align(&mut self) {
+        if self.bits > 0 {
+            self.write(0, 8 - self.bits);
+        }
+    }
+}
+
+const CRC8_TABLE: [u8; 256] = crc8_table();
+const CRC16_TABLE: [u16; 256] = crc16_table();
+
+const fn crc8_table() -> [u8; 256] {
+    let mut table = [0u8; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u8;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+const fn crc16_table() -> [u16; 256] {
+    let mut table = [0u16; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u16) << 8;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8005
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+fn crc8(bytes: &[u8]) -> u8 {
+    bytes
+        .iter()
+        .fold(0u8, |crc, b| CRC8_TABLE[(crc ^ b) as usize])
+}
+
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0u16, |crc, b| {
+        (crc << 8) ^ CRC16_TABLE[((crc >> 8) as u8 ^ b) as usize]
+    })
+}
@@ -0,0 +1,435 @@
+use std::{fmt, path::Path};
+
+use anyhow::{bail, Context};
+use encoding_rs::Encoding;
+use serde_json::json;
+
+use crate::{
+    audio::get_audio_info,
+    cli::{Cli, MsfRounding},
+    cue::{file_track_bounds, file_tracks, index_to_samples, read_cue_text, CueSheet},
+    plan::LengthSource,
+    report::{Failure, Report},
+    track_msf::TrackMSF,
+    utils::{progress, warn},
+};
+
+/// Errors make a split fail, warnings are cut points a split would take as
+/// they are although they are probably not what was meant.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// Problem in a CUE sheet, `line` is 1-based and only known for problems
+/// found in the text of a CUE file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Issue {
+    pub severity: Severity,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl Issue {
+    fn error(line: Option<usize>, message: String) -> Issue {
+        Issue {
+            severity: Severity::Error,
+            line,
+            message,
+        }
+    }
+
+    fn warning(line: Option<usize>, message: String) -> Issue {
+        Issue {
+            severity: Severity::Warning,
+            line,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Checks a CUE file or the cuesheet embedded in a FLAC file. The text of a
+/// CUE file is checked first, the audio files only if it has no errors.
+pub fn lint_cue_sheet(
+    input: &Path,
+    encoding: Option<&'static Encoding>,
+) -> Result<Vec<Issue>, anyhow::Error> {
+    let input_dir = input.parent().context("Failed to get parent dir")?;
+    let is_flac = input
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("flac"));
+
+    let cue_sheet = if is_flac {
+        CueSheet::from_flac(input).context("Failed to read the embedded cuesheet")?
+    } else {
+        let (cue_text, _) = read_cue_text(input, encoding)?;
+        let issues = lint_text(&cue_text, input_dir);
+
+        if issues.iter().any(|i| i.severity == Severity::Error) {
+            return Ok(issues);
+        }
+
+        match CueSheet::try_from(cue_text.as_ref()) {
+            Ok(cue_sheet) => cue_sheet,
+            Err(e) => return Ok(vec![Issue::error(None, format!("{:#}", e))]),
+        }
+    };
+
+    let mut issues = vec![];
+
+    for (file_id, file) in cue_sheet.cue.files.iter().enumerate() {
+        let (tracks, tail_track) = file_tracks(&cue_sheet, file_id);
+        let audio_file = input_dir.join(file);
+
+        if tracks.is_empty() && tail_track.is_none() || !audio_file.exists() {
+            continue;
+        }
+
+        let audio_info = match get_audio_info(&audio_file) {
+            Ok(audio_info) => audio_info,
+            Err(e) => {
+                issues.push(Issue::error(None, format!("Can't read {}: {:#}", file, e)));
+                continue;
+            }
+        };
+        let sample_rate = audio_info.spec.sample_rate;
+        let length = audio_info.total_samples / audio_info.spec.channels as u64;
+        let msf = |samples: u64| TrackMSF::from_samples(samples, sample_rate, MsfRounding::Nearest);
+
+        let bounds = tail_track
+            .map(|i| index_to_samples(&cue_sheet.indices[i], 0, sample_rate))
+            .transpose()
+            .and_then(|tail_start| {
+                Ok((
+                    tail_start,
+                    file_track_bounds(
+                        &cue_sheet,
+                        file_id,
+                        &tracks,
+                        sample_rate,
+                        tail_start,
+                        length,
+                    )?,
+                ))
+            });
+        let (tail_start, bounds) = match bounds {
+            Ok(bounds) => bounds,
+            Err(e) => {
+                issues.push(Issue::error(None, format!("{:#}", e)));
+                continue;
+            }
+        };
+
+        // A CD frame, REM DURATION and the MSF timing of the next track may
+        // differ by the rounding of the INDEX point
+        let frame = (sample_rate as u64).div_ceil(75);
+
+        for (k, track) in bounds.iter().enumerate() {
+            let track_num = track.track + 1;
+
+            if track.start > track.end || track.end > length {
+                issues.push(Issue::error(
+                    None,
+                    format!(
+                        "Track {:02} ends {} samples after the end of {} ({})",
+                        track_num,
+                        track.end.saturating_sub(length),
+                        file,
+                        msf(length)
+                    ),
+                ));
+                continue;
+            }
+
+            if track.source != LengthSource::RemDuration {
+                continue;
+            }
+
+            // Where the MSF timing alone would end the track
+            let next = match bounds.get(k + 1) {
+                Some(next) => {
+                    let indices = &cue_sheet.indices[next.track];
+                    if indices.iter().any(|x| x.number == 0 && x.file == file_id) {
+                        Some(index_to_samples(indices, 0, sample_rate)?)
+                    } else {
+                        Some(index_to_samples(indices, 1, sample_rate)?)
+                    }
+                }
+                None => tail_start,
+            };
+
+            match next {
+                Some(next) if next.abs_diff(track.end) > frame => {
+                    issues.push(Issue::warning(
+                        None,
+                        format!(
+                            "Track {:02} REM DURATION ends at {} ({}), the MSF timing at {} ({})",
+                            track_num,
+                            msf(track.end),
+                            track.end,
+                            msf(next),
+                            next
+                        ),
+                    ));
+                }
+                _ => (),
+            }
+        }
+
+        // Unless the pregap of the next FILE starts in it, the rest of the
+        // audio belongs to no track
+        if let Some(last) = bounds.last().filter(|_| tail_start.is_none()) {
+            if last.end < length {
+                issues.push(Issue::warning(
+                    None,
+                    format!(
+                        "{} samples of {} after the last track are not in any track",
+                        length - last.end,
+                        file
+                    ),
+                ));
+            }
+        }
+    }
+
+    Ok(issues)
+}
+
+/// Checks that need the lines of the CUE file, these stop the CUE sheet
+/// parser or are lost by it.
+fn lint_text(cue_text: &str, input_dir: &std::path::Path) -> Vec<Issue> {
+    let cue_text = cue_text.strip_prefix('\u{feff}').unwrap_or(cue_text);
+    let mut issues = vec![];
+    let mut track_numbers: Vec<u32> = vec![];
+    // (number, line, INDEX numbers) of the current track
+    let mut track: Option<(u32, usize, Vec<u8>)> = None;
+    // Previous INDEX position in the current FILE, in frames
+    let mut previous: Option<(u64, String)> = None;
+    let mut has_file = false;
+
+    let end_track = |track: Option<(u32, usize, Vec<u8>)>, issues: &mut Vec<Issue>| {
+        if let Some((number, line, indices)) = track {
+            if !indices.contains(&1) {
+                issues.push(Issue::error(
+                    Some(line),
+                    format!("Track {:02} has no INDEX 01", number),
+                ));
+            }
+        }
+    };
+
+    for (n, line) in cue_text.lines().enumerate() {
+        let line_num = Some(n + 1);
+        let trimmed = line.trim();
+        let mut words = trimmed.split(' ').filter(|w| !w.is_empty());
+
+        match words.next() {
+            Some("FILE") => {
+                has_file = true;
+                previous = None;
+
+                let name = trimmed["FILE".len()..]
+                    .trim()
+                    .rsplit_once(' ')
+                    .map(|(name, _)| name.trim());
+                let name = name.map(|n| {
+                    n.strip_prefix('"')
+                        .and_then(|n| n.strip_suffix('"'))
+                        .unwrap_or(n)
+                });
+
+                match name {
+                    Some(name) if !input_dir.join(name).exists() => issues.push(Issue::error(
+                        line_num,
+                        format!("FILE \"{}\" not found", name),
+                    )),
+                    Some(_) => (),
+                    None => issues.push(Issue::error(
+                        line_num,
+                        format!("Invalid FILE line: {}", trimmed),
+                    )),
+                }
+            }
+            Some("TRACK") => {
+                end_track(track.take(), &mut issues);
+
+                let Some(number) = words.next().and_then(|n| n.parse::<u32>().ok()) else {
+                    issues.push(Issue::error(
+                        line_num,
+                        format!("Invalid TRACK line: {}", trimmed),
+                    ));
+                    continue;
+                };
+
+                if !has_file {
+                    issues.push(Issue::error(
+                        line_num,
+                        format!("Track {:02} is before the first FILE", number),
+                    ));
+                }
+
+                if track_numbers.contains(&number) {
+                    issues.push(Issue::error(
+                        line_num,
+                        format!("Track {:02} is listed twice", number),
+                    ));
+                } else if let Some(last) = track_numbers.iter().max().filter(|l| **l > number) {
+                    issues.push(Issue::error(
+                        line_num,
+                        format!("Track {:02} comes after track {:02}", number, last),
+                    ));
+                }
+
+                track_numbers.push(number);
+                track = Some((number, n + 1, vec![]));
+            }
+            Some("INDEX") => {
+                let (Some(number), Some(position)) = (
+                    words.next().and_then(|n| n.parse::<u8>().ok()),
+                    words.next(),
+                ) else {
+                    issues.push(Issue::error(
+                        line_num,
+                        format!("Invalid INDEX line: {}", trimmed),
+                    ));
+                    continue;
+                };
+
+                let Some((track_number, _, indices)) = track.as_mut() else {
+                    issues.push(Issue::error(
+                        line_num,
+                        format!("INDEX {:02} is outside of a TRACK", number),
+                    ));
+                    continue;
+                };
+
+                if let Some(last) = indices.last().filter(|l| **l >= number) {
+                    issues.push(Issue::error(
+                        line_num,
+                        format!(
+                            "INDEX {:02} of track {:02} comes after INDEX {:02}",
+                            number, track_number, last
+                        ),
+                    ));
+                }
+                indices.push(number);
+
+                let msf: Vec<Option<u64>> =
+                    position.split(':').map(|v| v.parse::<u64>().ok()).collect();
+                let frames = match msf[..] {
+                    [Some(_), Some(s), Some(f)] if s < 60 && f < 75 => {
+                        match TrackMSF::try_from(position) {
+                            Ok(msf) => msf.frames(),
+                            Err(_) => {
+                                issues.push(Issue::error(
+                                    line_num,
+                                    format!(
+                                        "INDEX {:02} {} of track {:02} is out of range",
+                                        number, position, track_number
+                                    ),
+                                ));
+                                continue;
+                            }
+                        }
+                    }
+                    [Some(_), Some(s), Some(_)] if s >= 60 => {
+                        issues.push(Issue::error(
+                            line_num,
+                            format!(
+                                "INDEX {:02} {} of track {:02} has {} seconds, the maximum is 59",
+                                number, position, track_number, s
+                            ),
+                        ));
+                        continue;
+                    }
+                    [Some(_), Some(_), Some(f)] => {
+                        issues.push(Issue::error(
+                            line_num,
+                            format!(
+                                "INDEX {:02} {} of track {:02} has {} frames, the maximum is 74",
+                                number, position, track_number, f
+                            ),
+                        ));
+                        continue;
+                    }
+                    _ => {
+                        issues.push(Issue::error(
+                            line_num,
+                            format!("Invalid INDEX position: {}", position),
+                        ));
+                        continue;
+                    }
+                };
+
+                if let Some((_, last)) = previous.as_ref().filter(|(p, _)| *p > frames) {
+                    issues.push(Issue::error(
+                        line_num,
+                        format!(
+                            "INDEX {:02} {} of track {:02} is before the previous INDEX at {}",
+                            number, position, track_number, last
+                        ),
+                    ));
+                }
+                previous = Some((frames, position.to_string()));
+            }
+            _ => (),
+        }
+    }
+
+    end_track(track, &mut issues);
+
+    issues
+}
+
+/// Prints the issues, warnings go to the report as `lint` warnings and fail
+/// on errors.
+pub fn report_issues(
+    issues: &[Issue],
+    cli: &Cli,
+    report: &mut Report,
+) -> Result<(), anyhow::Error> {
+    let errors = issues
+        .iter()
+        .filter(|i| i.severity == Severity::Error)
+        .count();
+
+    for issue in issues {
+        match issue.severity {
+            Severity::Warning => warn(cli, report, "lint", issue.to_string()),
+            Severity::Error if !cli.totally_silent => progress!(cli, "Error: {}", issue),
+            Severity::Error => (),
+        }
+    }
+
+    let json = issues
+        .iter()
+        .map(|issue| {
+            json!({
+                "severity": match issue.severity {
+                    Severity::Error => "error",
+                    Severity::Warning => "warning",
+                },
+                "line": issue.line,
+                "message": issue.message,
+            })
+        })
+        .collect();
+    report.results.push(("lint", json));
+
+    if errors > 0 {
+        bail!(Failure::Lint(format!("CUE sheet has {} error(s)", errors)));
+    }
+
+    Ok(())
+}
@@ -1,15 +1,31 @@
+use std::path::{Path, PathBuf};
+
 use anyhow::bail;
+use batch::split_recursive;
 use clap::Parser;
-use cli::{Cli, Commands};
-use commands::{examples, merge, split};
+use cli::{Cli, Commands, SplitArgs};
+use commands::{disc_id, examples, info, lint, merge, split, verify};
+use report::Report;
+use utils::progress;
 
+mod accuraterip;
 mod audio;
+mod batch;
 mod cli;
 mod commands;
+mod convert;
 mod cue;
+mod disc_id;
+mod flac;
+mod lint;
+mod plan;
+mod report;
+mod tags;
+mod template;
 mod tests;
 mod track_msf;
 mod utils;
+mod verify;
 
 fn main() -> Result<(), anyhow::Error> {
     let mut cli = Cli::parse_from(wild::args());
@@ -18,10 +34,18 @@ fn main() -> Result<(), anyhow::Error> {
         cli.silent = true;
     }
 
-    match process_command(&cli) {
+    let mut report = Report::default();
+    let result = process_command(&cli, &mut report);
+
+    if cli.json {
+        let document = report.to_json(cli.command.name(), result.as_ref().err());
+        println!("{}", serde_json::to_string_pretty(&document)?);
+    }
+
+    match result {
         Ok(_) => {
             if !&cli.totally_silent {
-                println!("Done.")
+                progress!(cli, "Done.")
             }
         }
         Err(e) => {
@@ -34,25 +58,41 @@ fn main() -> Result<(), anyhow::Error> {
     Ok(())
 }
 
-fn process_command(cli: &Cli) -> Result<(), anyhow::Error> {
+fn process_command(cli: &Cli, report: &mut Report) -> Result<(), anyhow::Error> {
     match &cli.command {
-        Commands::Merge {
-            cue,
-            title,
-            performer,
-            rem,
-            verify,
-            input,
-            output,
-        } => merge(*cue, &title, &performer, rem, *verify, input, output, &cli)?,
-        Commands::Split {
-            cue,
-            input,
-            output_dir,
-            verify,
-            format,
-        } => split(*cue, input, output_dir, *verify, format, &cli)?,
-        Commands::Examples {} => examples(),
+        Commands::Merge(args) => merge(args, cli, report)?,
+        Commands::Split(args) => {
+            let split_album = |input: &Path, output_dir: &Option<PathBuf>, report: &mut Report| {
+                split(
+                    &SplitArgs {
+                        input: input.to_path_buf(),
+                        output_dir: output_dir.clone(),
+                        ..args.clone()
+                    },
+                    cli,
+                    report,
+                )
+            };
+
+            if args.recursive {
+                split_recursive(
+                    &args.input,
+                    &args.output_dir,
+                    args.dry_run,
+                    args.resplit,
+                    cli,
+                    report,
+                    split_album,
+                )?
+            } else {
+                split(args, cli, report)?
+            }
+        }
+        Commands::DiscId { input } => disc_id(input, cli, report)?,
+        Commands::Verify { input, image } => verify(input, image, cli, report)?,
+        Commands::Info { input } => info(input, cli, report)?,
+        Commands::Lint { input } => lint(input, cli, report)?,
+        Commands::Examples {} => examples(cli, report),
     };
 
     Ok(())
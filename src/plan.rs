@@ -0,0 +1,161 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::{bail, Context};
+use serde_json::{json, Value};
+
+use crate::{
+    cli::{Cli, MsfRounding},
+    report::{Failure, Report},
+    track_msf::TrackMSF,
+    utils::progress,
+};
+
+/// Where the length of a planned track comes from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LengthSource {
+    /// Length of a merge input file
+    Header,
+    /// Sample exact `REM DURATION` of the track
+    RemDuration,
+    /// Next INDEX point of the CUE sheet
+    Msf,
+    /// End of the audio file
+    FileEnd,
+}
+
+/// One track of a merge or split, positions in inter-channel samples.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedTrack {
+    pub number: usize,
+    /// Input file of a merge, output file of a split
+    pub file: PathBuf,
+    pub start: u64,
+    pub length: u64,
+    pub sample_rate: u32,
+    pub source: LengthSource,
+}
+
+/// What a `--dry-run` merge or split would do, nothing is written.
+#[derive(Debug, Default)]
+pub struct Plan {
+    pub tracks: Vec<PlannedTrack>,
+    /// Files that would be written, in order
+    pub outputs: Vec<PathBuf>,
+    /// Problems the real run would fail on or paper over
+    pub problems: Vec<String>,
+}
+
+impl LengthSource {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LengthSource::Header => "file header",
+            LengthSource::RemDuration => "REM DURATION",
+            LengthSource::Msf => "MSF",
+            LengthSource::FileEnd => "end of file",
+        }
+    }
+}
+
+impl Plan {
+    /// Prints the plan and fails if it has problems, so the exit code tells
+    /// whether the real run would go through.
+    pub fn finish(mut self, cli: &Cli, report: &mut Report) -> Result<Vec<PathBuf>, anyhow::Error> {
+        for (k, output) in self.outputs.iter().enumerate() {
+            if self.outputs[..k].contains(output) {
+                self.problems
+                    .push(format!("{} would be written twice", output.display()));
+            }
+        }
+
+        if !cli.totally_silent {
+            self.print(cli)?;
+        }
+
+        let plan = self.to_json()?;
+        report.results.push(("plan", plan));
+
+        if !self.problems.is_empty() {
+            bail!(Failure::DryRun(format!(
+                "Dry run found {} problem(s)",
+                self.problems.len()
+            )));
+        }
+
+        Ok(vec![])
+    }
+
+    fn to_json(&self) -> Result<Value, anyhow::Error> {
+        let tracks: Vec<Value> = self
+            .tracks
+            .iter()
+            .map(|track| {
+                json!({
+                    "number": track.number,
+                    "file": track.file.to_string_lossy(),
+                    "start": track.start,
+                    "length": track.length,
+                    "length_source": track.source.name(),
+                })
+            })
+            .collect();
+
+        let mut outputs = vec![];
+        for output in &self.outputs {
+            outputs.push(json!({
+                "path": output.to_string_lossy(),
+                "overwrite": fs::exists(output).context("Can't check existence of file")?,
+            }));
+        }
+
+        Ok(json!({
+            "tracks": tracks,
+            "outputs": outputs,
+            "problems": self.problems,
+        }))
+    }
+
+    fn print(&self, cli: &Cli) -> Result<(), anyhow::Error> {
+        let msf = |samples: u64, sample_rate: u32| {
+            TrackMSF::from_samples(samples, sample_rate, MsfRounding::Nearest)
+        };
+
+        progress!(cli, "Dry run, nothing is written");
+        progress!(cli, "Tracks:");
+        for track in &self.tracks {
+            progress!(
+                cli,
+                "  {:02}  start {} ({})  length {} ({}, {})  {}",
+                track.number,
+                msf(track.start, track.sample_rate),
+                track.start,
+                msf(track.length, track.sample_rate),
+                track.length,
+                track.source.name(),
+                track
+                    .file
+                    .file_name()
+                    .context("Failed to get file name")?
+                    .to_str()
+                    .context("to_str failed")?
+            );
+        }
+
+        progress!(cli, "Output files:");
+        for output in &self.outputs {
+            if fs::exists(output).context("Can't check existence of file")? {
+                progress!(cli, "  {} (overwrite)", output.display());
+            } else {
+                progress!(cli, "  {}", output.display());
+            }
+        }
+
+        if !self.problems.is_empty() {
+            progress!(cli, "Problems:");
+            for problem in &self.problems {
+                progress!(cli, "  {}", problem);
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use serde_json::{json, Value};
+use thiserror::Error;
+
+/// Failures with a code of their own in the `--json` document, every other
+/// error is reported as `io_error` or `error`.
+#[derive(Error, Debug)]
+pub enum Failure {
+    #[error("{0}")]
+    SpecMismatch(String),
+    #[error("{0}")]
+    Verify(String),
+    #[error("{0}")]
+    AccurateRip(String),
+    #[error("{0}")]
+    DryRun(String),
+    #[error("{0}")]
+    Lint(String),
+    #[error("{0}")]
+    Batch(String),
+}
+
+impl Failure {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Failure::SpecMismatch(_) => "spec_mismatch",
+            Failure::Verify(_) => "verify_failed",
+            Failure::AccurateRip(_) => "accuraterip_not_found",
+            Failure::DryRun(_) => "dry_run_problems",
+            Failure::Lint(_) => "lint_errors",
+            Failure::Batch(_) => "batch_failed",
+        }
+    }
+}
+
+/// Input or output file of a command, `samples` are inter-channel samples.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileEntry {
+    pub path: PathBuf,
+    pub samples: Option<u64>,
+    pub sample_rate: Option<u32>,
+}
+
+/// Result of one verified segment, a track of a merged file or a split track.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerifyEntry {
+    pub name: String,
+    pub ok: bool,
+    pub message: String,
+    pub hash: String,
+}
+
+/// Everything a command did, collected while it runs and printed as the
+/// `--json` result document.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub inputs: Vec<FileEntry>,
+    pub outputs: Vec<FileEntry>,
+    pub verify: Vec<VerifyEntry>,
+    /// (code, message)
+    pub warnings: Vec<(&'static str, String)>,
+    /// Results of the command, such as disc IDs or CRCs
+    pub results: Vec<(&'static str, Value)>,
+}
+
+impl FileEntry {
+    pub fn new(path: &Path) -> FileEntry {
+        FileEntry {
+            path: path.to_path_buf(),
+            samples: None,
+            sample_rate: None,
+        }
+    }
+
+    pub fn audio(path: &Path, samples: u64, sample_rate: u32) -> FileEntry {
+        FileEntry {
+            path: path.to_path_buf(),
+            samples: Some(samples),
+            sample_rate: Some(sample_rate),
+        }
+    }
+}
+
+impl Report {
+    /// Files get the hash of the verified segment with the same file name,
+    /// the input files of a merge and the tracks of a split.
+    pub fn to_json(&self, command: &str, error: Option<&anyhow::Error>) -> Value {
+        let file = |entry: &FileEntry| {
+            let name = entry.path.file_name().map(|n| n.to_string_lossy());
+            let hash = self
+                .verify
+                .iter()
+                .find(|v| Some(v.name.as_str()) == name.as_deref())
+                .map(|v| v.hash.as_str());
+            let duration = entry
+                .samples
+                .zip(entry.sample_rate)
+                .map(|(samples, rate)| samples as f64 / rate as f64);
+
+            json!({
+                "path": entry.path.to_string_lossy(),
+                "samples": entry.samples,
+                "duration": duration,
+                "hash": hash,
+            })
+        };
+
+        let verify: Vec<Value> = self
+            .verify
+            .iter()
+            .map(|v| {
+                json!({
+                    "name": v.name,
+                    "ok": v.ok,
+                    "message": v.message,
+                    "hash": v.hash,
+                })
+            })
+            .collect();
+
+        let message = |code: &str, message: &str| json!({ "code": code, "message": message });
+
+        let errors: Vec<Value> = error
+            .map(|e| message(error_code(e), &format!("{:#}", e)))
+            .into_iter()
+            .collect();
+
+        let mut document = json!({
+            "command": command,
+            "success": error.is_none(),
+            "inputs": self.inputs.iter().map(file).collect::<Vec<Value>>(),
+            "outputs": self.outputs.iter().map(file).collect::<Vec<Value>>(),
+            "verify": verify,
+            "warnings": self.warnings.iter().map(|(c, m)| message(c, m)).collect::<Vec<Value>>(),
+            "errors": errors,
+        });
+        if let Value::Object(fields) = &mut document {
+            fields.extend(
+                self.results
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.clone())),
+            );
+        }
+
+        document
+    }
+}
+
+/// Code of the first `Failure` in the error chain.
+fn error_code(error: &anyhow::Error) -> &'static str {
+    for cause in error.chain() {
+        if let Some(failure) = cause.downcast_ref::<Failure>() {
+            return failure.code();
+        }
+    }
+
+    if error.chain().any(|c| c.is::<io::Error>()) {
+        "io_error"
+    } else {
+        "error"
+    }
+}
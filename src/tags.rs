@@ -0,0 +1,436 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufReader, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use anyhow::{bail, Context};
+use claxon::FlacReader;
+
+/// Metadata of a track, read from merge inputs and written to split tracks as
+/// Vorbis comments for FLAC or RIFF LIST/INFO (and optionally id3) chunks for
+/// WAV.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Tags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub track_number: Option<usize>,
+    pub track_total: Option<usize>,
+    pub disc_number: Option<usize>,
+    pub date: Option<String>,
+    pub genre: Option<String>,
+    pub isrc: Option<String>,
+    /// Also write an id3 chunk to WAV files
+    pub id3_chunk: bool,
+}
+
+impl Tags {
+    fn track(&self) -> Option<String> {
+        match (self.track_number, self.track_total) {
+            (Some(n), Some(t)) => Some(format!("{}/{}", n, t)),
+            (Some(n), None) => Some(n.to_string()),
+            _ => None,
+        }
+    }
+
+    /// `KEY=value` fields of a FLAC VORBIS_COMMENT block.
+    pub fn vorbis_comments(&self) -> Vec<String> {
+        [
+            ("TITLE", self.title.clone()),
+            ("ARTIST", self.artist.clone()),
+            ("ALBUM", self.album.clone()),
+            ("ALBUMARTIST", self.album_artist.clone()),
+            ("TRACKNUMBER", self.track_number.map(|n| n.to_string())),
+            ("TRACKTOTAL", self.track_total.map(|n| n.to_string())),
+            ("DISCNUMBER", self.disc_number.map(|n| n.to_string())),
+            ("DATE", self.date.clone()),
+            ("GENRE", self.genre.clone()),
+            ("ISRC", self.isrc.clone()),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| value.map(|v| format!("{}={}", key, v)))
+        .collect()
+    }
+
+    /// RIFF LIST chunk of type INFO with UTF-8 strings, empty if there are no
+    /// tags.
+    pub fn riff_info_chunk(&self) -> Vec<u8> {
+        let fields = [
+            (b"INAM", self.title.clone()),
+            (b"IART", self.artist.clone()),
+            (b"IPRD", self.album.clone()),
+            (b"ITRK", self.track()),
+            (b"ICRD", self.date.clone()),
+            (b"IGNR", self.genre.clone()),
+        ];
+
+        let mut data = b"INFO".to_vec();
+        for (id, value) in fields {
+            if let Some(value) = value {
+                let mut text = value.into_bytes();
+                text.push(0);
+                data.extend(riff_chunk(id, &text));
+            }
+        }
+
+        if data.len() > 4 {
+            riff_chunk(b"LIST", &data)
+        } else {
+            vec![]
+        }
+    }
+
+    /// RIFF `id3 ` chunk holding an ID3v2.4 tag with UTF-8 text frames.
+    pub fn id3_chunk(&self) -> Vec<u8> {
+        let fields = [
+            (b"TIT2", self.title.clone()),
+            (b"TPE1", self.artist.clone()),
+            (b"TALB", self.album.clone()),
+            (b"TPE2", self.album_artist.clone()),
+            (b"TRCK", self.track()),
+            (b"TPOS", self.disc_number.map(|n| n.to_string())),
+            (b"TDRC", self.date.clone()),
+            (b"TCON", self.genre.clone()),
+            (b"TSRC", self.isrc.clone()),
+        ];
+
+        let mut frames = vec![];
+        for (id, value) in fields {
+            if let Some(value) = value {
+                let text = value.into_bytes();
+                frames.extend_from_slice(id);
+                frames.extend(syncsafe(text.len() as u32 + 1));
+                frames.extend([0, 0, 3]);
+                frames.extend(text);
+            }
+        }
+
+        if frames.is_empty() {
+            return vec![];
+        }
+
+        let mut tag = b"ID3".to_vec();
+        tag.extend([4, 0, 0]);
+        tag.extend(syncsafe(frames.len() as u32));
+        tag.extend(frames);
+
+        riff_chunk(b"id3 ", &tag)
+    }
+
+    /// Reads Vorbis comments of a FLAC file or the id3 and LIST/INFO chunks of
+    /// a WAV file, id3 values win over INFO ones.
+    pub fn read(file: &Path) -> Result<Tags, anyhow::Error> {
+        match file
+            .extension()
+            .context("Failed to get file extension")?
+            .to_str()
+            .context("to_str failed")?
+            .to_lowercase()
+            .as_ref()
+        {
+            "flac" => {
+                let reader = FlacReader::open(file)?;
+                let mut tags = Tags::default();
+                for (key, value) in reader.tags() {
+                    tags.set(&key.to_uppercase(), value);
+                }
+                Ok(tags)
+            }
+            "wav" => {
+                let chunks = read_wav_tag_chunks(file)
+                    .with_context(|| format!("Failed to read {}", file.display()))?;
+                let mut info = Tags::default();
+                let mut id3 = Tags::default();
+
+                for (id, chunk) in &chunks {
+                    match id {
+                        b"LIST" if chunk.starts_with(b"INFO") => {
+                            for (id, value) in riff_chunks(&chunk[4..]) {
+                                let value = String::from_utf8_lossy(value);
+                                info.set_info(id, value.trim_end_matches('\0'));
+                            }
+                        }
+                        b"id3 " | b"ID3 " => id3 = read_id3(chunk).unwrap_or_default(),
+                        _ => (),
+                    }
+                }
+
+                Ok(id3.or(info))
+            }
+            _ => bail!("Unsupported format"),
+        }
+    }
+
+    /// Sets a field from a Vorbis comment key.
+    fn set(&mut self, key: &str, value: &str) {
+        let value = Some(value.trim().to_string()).filter(|v| !v.is_empty());
+        let field = match key {
+            "TITLE" => &mut self.title,
+            "ARTIST" => &mut self.artist,
+            "ALBUM" => &mut self.album,
+            "ALBUMARTIST" | "ALBUM ARTIST" => &mut self.album_artist,
+            "DATE" | "YEAR" => &mut self.date,
+            "GENRE" => &mut self.genre,
+            "ISRC" => &mut self.isrc,
+            "TRACKNUMBER" => {
+                self.track_number = value.and_then(|v| v.split('/').next()?.parse().ok());
+                return;
+            }
+            "DISCNUMBER" => {
+                self.disc_number = value.and_then(|v| v.split('/').next()?.parse().ok());
+                return;
+            }
+            _ => return,
+        };
+
+        if field.is_none() {
+            *field = value;
+        }
+    }
+
+    fn set_info(&mut self, id: &[u8; 4], value: &str) {
+        let key = match id {
+            b"INAM" => "TITLE",
+            b"IART" => "ARTIST",
+            b"IPRD" => "ALBUM",
+            b"ICRD" => "DATE",
+            b"IGNR" => "GENRE",
+            b"ITRK" | b"IPRT" => "TRACKNUMBER",
+            _ => return,
+        };
+        self.set(key, value);
+    }
+
+    /// Fields of `self`, missing ones taken from `other`.
+    fn or(self, other: Tags) -> Tags {
+        Tags {
+            title: self.title.or(other.title),
+            artist: self.artist.or(other.artist),
+            album: self.album.or(other.album),
+            album_artist: self.album_artist.or(other.album_artist),
+            track_number: self.track_number.or(other.track_number),
+            track_total: self.track_total.or(other.track_total),
+            disc_number: self.disc_number.or(other.disc_number),
+            date: self.date.or(other.date),
+            genre: self.genre.or(other.genre),
+            isrc: self.isrc.or(other.isrc),
+            id3_chunk: self.id3_chunk,
+        }
+    }
+}
+
+/// Text frames of an ID3v2.3 or ID3v2.4 tag, `None` for other versions and
+/// unsynchronised tags.
+fn read_id3(tag: &[u8]) -> Option<Tags> {
+    if tag.len() < 10 || &tag[..3] != b"ID3" {
+        return None;
+    }
+
+    let version = tag[3];
+    let flags = tag[5];
+    if !(3..=4).contains(&version) || flags & 0x80 != 0 {
+        return None;
+    }
+
+    let size = |b: &[u8]| match version {
+        4 => b.iter().fold(0usize, |s, x| s << 7 | (*x & 0x7f) as usize),
+        _ => b.iter().fold(0usize, |s, x| s << 8 | *x as usize),
+    };
+
+    let end = (10
+        + tag[6..10]
+            .iter()
+            .fold(0usize, |s, x| s << 7 | (*x & 0x7f) as usize))
+    .min(tag.len());
+    let mut p = 10;
+
+    if flags & 0x40 != 0 {
+        let extended = size(tag.get(10..14)?);
+        p += match version {
+            4 => extended,
+            _ => extended + 4,
+        };
+    }
+
+    let mut tags = Tags::default();
+
+    while p + 10 <= end && tag[p] != 0 {
+        let id = &tag[p..p + 4];
+        let length = size(&tag[p + 4..p + 8]);
+        let frame = tag.get(p + 10..p + 10 + length)?;
+        p += 10 + length;
+
+        let key = match id {
+            b"TIT2" => "TITLE",
+            b"TPE1" => "ARTIST",
+            b"TALB" => "ALBUM",
+            b"TPE2" => "ALBUMARTIST",
+            b"TRCK" => "TRACKNUMBER",
+            b"TPOS" => "DISCNUMBER",
+            b"TDRC" | b"TYER" => "DATE",
+            b"TCON" => "GENRE",
+            b"TSRC" => "ISRC",
+            _ => continue,
+        };
+
+        if let Some(text) = id3_text(frame) {
+            tags.set(key, &text);
+        }
+    }
+
+    Some(tags)
+}
+
+/// First string of a text frame.
+fn id3_text(frame: &[u8]) -> Option<String> {
+    let (encoding, data) = frame.split_first()?;
+
+    let utf16 = |data: &[u8], big_endian: bool| {
+        let units: Vec<u16> = data
+            .chunks_exact(2)
+            .map(|c| {
+                if big_endian {
+                    u16::from_be_bytes([c[0], c[1]])
+                } else {
+                    u16::from_le_bytes([c[0], c[1]])
+                }
+            })
+            .collect();
+        String::from_utf16_lossy(&units)
+    };
+
+    let text = match encoding {
+        0 => data.iter().map(|b| *b as char).collect(),
+        1 => match data {
+            [0xfe, 0xff, rest @ ..] => utf16(rest, true),
+            [0xff, 0xfe, rest @ ..] => utf16(rest, false),
+            _ => utf16(data, false),
+        },
+        2 => utf16(data, true),
+        3 => String::from_utf8_lossy(data).to_string(),
+        _ => return None,
+    };
+
+    text.split('\0').next().map(|t| t.to_string())
+}
+
+/// Chunks of a RIFF file or list as (id, data), stops at the first truncated
+/// chunk.
+fn riff_chunks(data: &[u8]) -> Vec<(&[u8; 4], &[u8])> {
+    let mut chunks = vec![];
+    let mut p = if data.starts_with(b"RIFF") { 12 } else { 0 };
+
+    while p + 8 <= data.len() {
+        let id: &[u8; 4] = data[p..p + 4].try_into().unwrap();
+        let length = u32::from_le_bytes(data[p + 4..p + 8].try_into().unwrap()) as usize;
+
+        match data.get(p + 8..p + 8 + length) {
+            Some(chunk) => chunks.push((id, chunk)),
+            None => break,
+        }
+
+        p += 8 + length + length % 2;
+    }
+
+    chunks
+}
+
+/// Id and data of a RIFF chunk.
+type RiffChunk = ([u8; 4], Vec<u8>);
+
+/// LIST and id3 chunks of a WAV file as (id, data), the headers of the other
+/// chunks are read to skip over them. Stops at the first truncated chunk.
+fn read_wav_tag_chunks(file: &Path) -> Result<Vec<RiffChunk>, anyhow::Error> {
+    let mut reader = BufReader::new(File::open(file)?);
+    let file_length = reader.get_ref().metadata()?.len();
+    let mut chunks = vec![];
+
+    if file_length < 12 {
+        return Ok(chunks);
+    }
+    let mut header = [0u8; 12];
+    reader.read_exact(&mut header)?;
+    if !header.starts_with(b"RIFF") {
+        return Ok(chunks);
+    }
+
+    let mut p: u64 = 12;
+    while p + 8 <= file_length {
+        let mut chunk_header = [0u8; 8];
+        reader.read_exact(&mut chunk_header)?;
+        let id: [u8; 4] = chunk_header[..4].try_into().unwrap();
+        let length = u32::from_le_bytes(chunk_header[4..].try_into().unwrap()) as u64;
+        let padded = length + length % 2;
+
+        if p + 8 + length > file_length {
+            break;
+        }
+
+        match &id {
+            b"LIST" | b"id3 " | b"ID3 " => {
+                let mut data = vec![0u8; length as usize];
+                reader.read_exact(&mut data)?;
+                chunks.push((id, data));
+                reader.seek_relative((padded - length) as i64)?;
+            }
+            _ => reader.seek_relative(padded as i64)?,
+        }
+
+        p += 8 + padded;
+    }
+
+    Ok(chunks)
+}
+
+/// Appends chunks to a finished WAV file and updates the RIFF size.
+pub fn append_wav_chunks(file: &Path, chunks: &[Vec<u8>]) -> Result<(), anyhow::Error> {
+    if chunks.iter().all(|c| c.is_empty()) {
+        return Ok(());
+    }
+
+    let mut writer = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(file)
+        .with_context(|| format!("Failed to open {} to write tags", file.display()))?;
+
+    // Chunks start at even offsets, an odd sized data chunk gets a pad byte
+    let mut length = writer.seek(SeekFrom::End(0))?;
+    if length % 2 == 1 {
+        writer.write_all(&[0])?;
+        length += 1;
+    }
+
+    for chunk in chunks {
+        writer.write_all(chunk)?;
+        length += chunk.len() as u64;
+    }
+
+    let riff_size = u32::try_from(length - 8).context("WAV file too large for tags")?;
+    writer.seek(SeekFrom::Start(4))?;
+    writer.write_all(&riff_size.to_le_bytes())?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+fn riff_chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = id.to_vec();
+    chunk.extend((data.len() as u32).to_le_bytes());
+    chunk.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        chunk.push(0);
+    }
+    chunk
+}
+
+fn syncsafe(value: u32) -> [u8; 4] {
+    [
+        (value >> 21 & 0x7f) as u8,
+        (value >> 14 & 0x7f) as u8,
+        (value >> 7 & 0x7f) as u8,
+        (value & 0x7f) as u8,
+    ]
+}
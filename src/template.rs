@@ -0,0 +1,303 @@
+use std::path::PathBuf;
+
+use anyhow::bail;
+
+use crate::{cue::rem_value, tags::Tags};
+
+/// File name of split tracks without `--format`.
+pub const DEFAULT_FORMAT: &str = "%track% %artist|'Artist'% - %title%";
+
+const FIELDS: [&str; 11] = [
+    "track",
+    "title",
+    "artist",
+    "album",
+    "albumartist",
+    "date",
+    "genre",
+    "isrc",
+    "discnumber",
+    "totaldiscs",
+    "totaltracks",
+];
+
+/// File name template of split tracks.
+///
+/// `%field%` is replaced by a field of the track, `%rem:KEY%` by a REM field
+/// of the track or the album. `%field:N%` pads numbers with zeros to N
+/// digits and cuts text after N characters, `%a|b|'text'%` takes the first
+/// field that is set or the quoted text. `[...]` is left out unless all
+/// fields in it are set, fields outside of one are left empty. `%%` is a
+/// `%` of the file name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Field {
+        alternatives: Vec<Source>,
+        width: Option<usize>,
+    },
+    Section(Vec<Part>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Source {
+    Field(String),
+    Rem(String),
+    Text(String),
+}
+
+/// Values of the template fields for one track, REM fields of the track come
+/// before the ones of the album.
+pub struct TemplateFields<'a> {
+    pub tags: &'a Tags,
+    pub comments: Vec<String>,
+}
+
+impl TryFrom<&str> for Template {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let mut chars = value.chars();
+        let parts = parse_parts(&mut chars, false)?;
+
+        Ok(Template { parts })
+    }
+}
+
+fn parse_parts(chars: &mut std::str::Chars, section: bool) -> Result<Vec<Part>, anyhow::Error> {
+    let mut parts = vec![];
+    let mut text = String::new();
+
+    let push_text = |parts: &mut Vec<Part>, text: &mut String| {
+        if !text.is_empty() {
+            parts.push(Part::Text(std::mem::take(text)));
+        }
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            '%' => {
+                let mut field = String::new();
+                loop {
+                    match chars.next() {
+                        Some('%') => break,
+                        Some(c) => field.push(c),
+                        None => bail!("Missing % after %{} in the file name format", field),
+                    }
+                }
+
+                if field.is_empty() {
+                    text.push('%');
+                } else {
+                    push_text(&mut parts, &mut text);
+                    parts.push(parse_field(&field)?);
+                }
+            }
+            '[' => {
+                push_text(&mut parts, &mut text);
+                parts.push(Part::Section(parse_parts(chars, true)?));
+            }
+            ']' if section => {
+                push_text(&mut parts, &mut text);
+                return Ok(parts);
+            }
+            ']' => bail!("Unexpected ] in the file name format"),
+            c => text.push(c),
+        }
+    }
+
+    if section {
+        bail!("Missing ] in the file name format");
+    }
+
+    push_text(&mut parts, &mut text);
+
+    Ok(parts)
+}
+
+fn parse_field(field: &str) -> Result<Part, anyhow::Error> {
+    let (field, width) = match field.rsplit_once(':') {
+        Some((f, w)) if !w.is_empty() && w.chars().all(|c| c.is_ascii_digit()) => {
+            (f, Some(w.parse::<usize>()?))
+        }
+        _ => (field, None),
+    };
+
+    let mut alternatives = vec![];
+
+    for alternative in field.split('|') {
+        let source = match alternative.strip_prefix("rem:") {
+            Some("") => bail!("Missing REM field name in %{}%", field),
+            Some(key) => Source::Rem(key.to_uppercase()),
+            None if alternative.len() >= 2
+                && alternative.starts_with('\'')
+                && alternative.ends_with('\'') =>
+            {
+                Source::Text(alternative[1..alternative.len() - 1].to_string())
+            }
+            None if FIELDS.contains(&alternative.to_lowercase().as_str()) => {
+                Source::Field(alternative.to_lowercase())
+            }
+            None => bail!(
+                "Unknown field %{}% in the file name format, known fields: {}, rem:KEY",
+                alternative,
+                FIELDS.join(", ")
+            ),
+        };
+        alternatives.push(source);
+    }
+
+    Ok(Part::Field {
+        alternatives,
+        width,
+    })
+}
+
+impl Template {
+    /// Path of a track below the output directory, without extension. `/`
+    /// in the format separates folders, field values can't add separators or
+    /// characters the platform doesn't allow in file names.
+    pub fn render(&self, fields: &TemplateFields) -> PathBuf {
+        let (name, _) = render_parts(&self.parts, fields);
+        let separators: &[char] = if cfg!(windows) { &['/', '\\'] } else { &['/'] };
+
+        name.split(separators)
+            .map(|c| sanitize_file_name(c, cfg!(windows)))
+            .filter(|c| !c.is_empty())
+            .map(|c| match c.as_str() {
+                "." | ".." => "_".to_string(),
+                _ => c,
+            })
+            .collect()
+    }
+}
+
+/// Text of the parts and whether all of their fields are set.
+fn render_parts(parts: &[Part], fields: &TemplateFields) -> (String, bool) {
+    let mut text = String::new();
+    let mut complete = true;
+
+    for part in parts {
+        match part {
+            Part::Text(t) => text.push_str(t),
+            Part::Field {
+                alternatives,
+                width,
+            } => match alternatives.iter().find_map(|s| fields.value(s, *width)) {
+                Some(value) => text.push_str(&sanitize_value(&value, cfg!(windows))),
+                None => complete = false,
+            },
+            Part::Section(section) => {
+                if let (section, true) = render_parts(section, fields) {
+                    text.push_str(&section);
+                }
+            }
+        }
+    }
+
+    (text, complete)
+}
+
+impl TemplateFields<'_> {
+    fn value(&self, source: &Source, width: Option<usize>) -> Option<String> {
+        let tags = self.tags;
+        let number = |n: Option<usize>| n.map(|n| format!("{:0w$}", n, w = width.unwrap_or(1)));
+        let rem_number = |key: &str| {
+            let value = rem_value(&self.comments, key)?;
+            match value.parse::<usize>() {
+                Ok(n) => number(Some(n)),
+                Err(_) => Some(value),
+            }
+        };
+
+        let value = match source {
+            Source::Field(field) => match field.as_str() {
+                "track" => {
+                    return tags
+                        .track_number
+                        .or(Some(0))
+                        .map(|n| format!("{:0w$}", n, w = width.unwrap_or(2)))
+                }
+                "discnumber" => return number(tags.disc_number).or(rem_number("DISCNUMBER")),
+                "totaldiscs" => return rem_number("TOTALDISCS"),
+                "totaltracks" => return number(tags.track_total),
+                "title" => tags.title.clone(),
+                "artist" => tags.artist.clone(),
+                "album" => tags.album.clone(),
+                "albumartist" => tags.album_artist.clone(),
+                "date" => tags.date.clone(),
+                "genre" => tags.genre.clone(),
+                "isrc" => tags.isrc.clone(),
+                _ => None,
+            },
+            Source::Rem(key) => rem_value(&self.comments, key),
+            Source::Text(text) => Some(text.clone()),
+        };
+
+        value.filter(|v| !v.is_empty()).map(|v| match width {
+            Some(width) => v.chars().take(width).collect(),
+            None => v,
+        })
+    }
+}
+
+/// Field values may not hold path separators, on Windows neither any other
+/// character that is not allowed in file names.
+fn sanitize_value(value: &str, windows: bool) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' => '_',
+            '<' | '>' | ':' | '"' | '|' | '?' | '*' if windows => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// Windows also doesn't allow names that end with a dot or space, or device
+/// names like `CON` and `COM1` with any extension.
+pub fn sanitize_file_name(name: &str, windows: bool) -> String {
+    let name = sanitize_value(name.trim(), windows);
+
+    if !windows {
+        return name;
+    }
+
+    let name = name.trim_end_matches(['.', ' ']).to_string();
+    let stem = name.split('.').next().unwrap_or_default().to_uppercase();
+    let reserved = ["CON", "PRN", "AUX", "NUL"].contains(&stem.as_str())
+        || (stem.len() == 4
+            && (stem.starts_with("COM") || stem.starts_with("LPT"))
+            && stem.ends_with(|c: char| c.is_ascii_digit() && c != '0'));
+
+    if reserved {
+        format!("_{}", name)
+    } else {
+        name
+    }
+}
+
+/// `path` with ` (2)`, ` (3)`, ... before the extension if it is already in
+/// `used`, compared without case for case-insensitive file systems.
+pub fn unique_file_name(path: PathBuf, extension: &str, used: &mut Vec<String>) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut file = path.with_file_name(format!("{}.{}", name, extension));
+    let mut n = 1;
+
+    while used.contains(&file.to_string_lossy().to_lowercase()) {
+        n += 1;
+        file = path.with_file_name(format!("{} ({}).{}", name, n, extension));
+    }
+
+    used.push(file.to_string_lossy().to_lowercase());
+    file
+}
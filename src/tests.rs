@@ -2,50 +2,1881 @@
 mod commands {
     use std::{
         fs::{self, File},
-        path::PathBuf,
+        path::{Path, PathBuf},
         process::Command,
     };
 
     use blake3::Hash;
-    use hound::{SampleFormat, WavSpec, WavWriter};
+    use claxon::FlacReader;
+    use encoding_rs::{SHIFT_JIS, UTF_16LE, WINDOWS_1251, WINDOWS_1252};
+    use hound::{SampleFormat, WavSpec, WavSpecEx, WavWriter};
+    use serde_json::{json, Value};
     use serial_test::serial;
 
-    use crate::*;
+    use crate::{
+        audio::get_audio_info,
+        batch::split_recursive,
+        cli::{
+            AccurateRipArgs, ConvertArgs, CueFormatArgs, GapsMode, InputSort, MergeArgs,
+            MsfRounding, OutputFormat, SplitArgs, TrackRange,
+        },
+        cue::{read_cue_text, CueSheet},
+        disc_id::Toc,
+        flac::{CueSheetTrack, FlacWriter},
+        lint::{lint_cue_sheet, Severity},
+        report::Report,
+        tags::{append_wav_chunks, Tags},
+        verify::{OutputVerifier, SampleSource, Segment},
+        *,
+    };
+
+    #[test]
+    #[serial]
+    fn test_wav_split_msf() {
+        test_split(false, true);
+    }
+
+    #[test]
+    #[serial]
+    fn test_flac_split_msf() {
+        test_split(true, true);
+    }
+
+    #[test]
+    #[serial]
+    fn test_wav_split() {
+        test_split(false, false);
+    }
+
+    #[test]
+    #[serial]
+    fn test_flac_split() {
+        test_split(true, false);
+    }
+
+    #[test]
+    #[serial]
+    fn test_wav_merge() {
+        test_merge(false, true);
+    }
+
+    #[test]
+    #[serial]
+    fn test_flac_merge() {
+        test_merge(true, true);
+    }
+
+    #[test]
+    #[serial]
+    fn test_wav_merge_split_flac_output() {
+        remove_tmp_files(true);
+        let test_dir = get_test_dir();
+
+        let mut output = test_dir.clone();
+        output.push("output.flac");
+
+        let mut output_cue = test_dir.clone();
+        output_cue.push("output.cue");
+
+        let cli = Cli {
+            totally_silent: false,
+            ..test_cli()
+        };
+
+        let input = create_test_wavs(false);
+
+        merge(
+            &MergeArgs {
+                cue: true,
+                title: Some("Album".to_string()),
+                performer: Some("Artist".to_string()),
+                verify: true,
+                compression_level: 8,
+                ..merge_args(&input, &output)
+            },
+            &cli,
+            &mut Report::default(),
+        )
+        .unwrap();
+
+        let reader = FlacReader::open(&output).unwrap();
+        assert_eq!(reader.streaminfo().samples, Some(88200 * 3 / 2));
+        assert_ne!(reader.streaminfo().md5sum, [0; 16]);
+
+        let split_output = split(
+            &SplitArgs {
+                output_dir: Some(test_dir),
+                verify: true,
+                output_format: OutputFormat::Flac,
+                compression_level: 0,
+                ..split_args(&output_cue)
+            },
+            &cli,
+            &mut Report::default(),
+        )
+        .unwrap();
+
+        assert_eq!(split_output.len(), 3);
+        assert!(split_output
+            .iter()
+            .all(|f| f.extension().unwrap() == "flac"));
+
+        remove_tmp_files(true);
+        remove_wavs(&split_output);
+    }
+
+    #[test]
+    #[serial]
+    fn test_wav_merge_convert() {
+        remove_tmp_files(false);
+        let test_dir = get_test_dir();
+
+        let mut output = test_dir.clone();
+        output.push("output.wav");
+
+        let cli = Cli {
+            totally_silent: false,
+            ..test_cli()
+        };
+
+        let mut input = create_test_wavs(false);
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: 22050,
+            bits_per_sample: 24,
+            sample_format: SampleFormat::Int,
+        };
+
+        let mut writer = WavWriter::create(&input[1], spec).unwrap();
+        for t in (0..22050).map(|x| x as f32 / 22050.0) {
+            let sample = (t * 440.0 * 2.0 * std::f32::consts::PI).sin();
+            writer.write_sample((sample * 8388607.0) as i32).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let err = merge(&merge_args(&input, &output), &cli, &mut Report::default()).unwrap_err();
+
+        assert!(err
+            .to_string()
+            .contains("2.wav (22050 Hz, 24 bit int, 1 channels)"));
+
+        let convert = ConvertArgs {
+            convert: true,
+            ..Default::default()
+        };
+
+        merge(
+            &MergeArgs {
+                verify: true,
+                convert: convert.clone(),
+                ..merge_args(&input, &output)
+            },
+            &cli,
+            &mut Report::default(),
+        )
+        .unwrap();
+
+        let reader = hound::WavReader::open(&output).unwrap();
+        assert_eq!(reader.spec(), get_audio_info(&input[0]).unwrap().spec);
+        assert_eq!(reader.duration(), 44100 * 2 + 44100);
+
+        // 5.1 can't be converted to stereo, the mismatch must still be reported first
+        let spec = WavSpec {
+            channels: 6,
+            sample_rate: 44100,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let mut writer = WavWriter::create(&input[1], spec).unwrap();
+        for _ in 0..44100 * 6 {
+            writer.write_sample(0i16).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let err = merge(&merge_args(&input, &output), &cli, &mut Report::default()).unwrap_err();
+
+        assert_eq!(
+            err.downcast_ref::<report::Failure>().map(|f| f.code()),
+            Some("spec_mismatch")
+        );
+        assert!(err
+            .to_string()
+            .contains("2.wav (44100 Hz, 16 bit int, 6 channels)"));
+
+        let err = merge(
+            &MergeArgs {
+                convert: convert.clone(),
+                dry_run: true,
+                ..merge_args(&input, &output)
+            },
+            &cli,
+            &mut Report::default(),
+        )
+        .unwrap_err();
+
+        assert_eq!(err.to_string(), "Dry run found 1 problem(s)");
+
+        input.push(output);
+        remove_wavs(&input);
+        remove_tmp_files(false);
+    }
+
+    #[test]
+    #[serial]
+    fn test_wav_split_multiple_files() {
+        remove_tmp_files(false);
+        let test_dir = get_test_dir();
+
+        let mut output_cue = test_dir.clone();
+        output_cue.push("output.cue");
+
+        let cli = Cli {
+            totally_silent: false,
+            ..test_cli()
+        };
+
+        let input = create_test_wavs(false);
+
+        let test_cue = r#"TITLE "Album"
+PERFORMER "Artist"
+FILE "1.wav" WAVE
+  TRACK 01 AUDIO
+    TITLE "A"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE "B"
+    INDEX 01 00:00:30
+FILE "2.wav" WAVE
+  TRACK 03 AUDIO
+    TITLE "C"
+    INDEX 01 00:00:00
+FILE "3.wav" WAVE
+  TRACK 04 AUDIO
+    TITLE "D"
+    INDEX 01 00:00:00"#;
+
+        fs::write(&output_cue, test_cue).unwrap();
+
+        let split_output = split(
+            &SplitArgs {
+                verify: true,
+                ..split_args(&output_cue)
+            },
+            &cli,
+            &mut Report::default(),
+        )
+        .unwrap();
+
+        assert_eq!(split_output.len(), 4);
+        assert!(split_output[3].ends_with("04 Artist - D.wav"));
+
+        for (file, duration) in split_output.iter().zip([17640, 26460, 44100, 44100]) {
+            assert_eq!(hound::WavReader::open(file).unwrap().duration(), duration);
+        }
+
+        check_file_hashes(&input[1..], &split_output[2..]);
+        remove_wavs(&split_output);
+        remove_tmp_files(false);
+    }
+
+    #[test]
+    #[serial]
+    fn test_wav_dry_run() {
+        remove_tmp_files(false);
+        let test_dir = get_test_dir();
+
+        let mut output = test_dir.clone();
+        output.push("output.wav");
+
+        let mut output_cue = test_dir.clone();
+        output_cue.push("output.cue");
+
+        let cli = Cli {
+            force: false,
+            totally_silent: false,
+            ..test_cli()
+        };
+
+        let input = create_test_wavs(false);
+
+        let run_merge = |performer: Option<String>| {
+            merge(
+                &MergeArgs {
+                    cue: true,
+                    performer: performer.clone(),
+                    dry_run: true,
+                    ..merge_args(&input, &output)
+                },
+                &cli,
+                &mut Report::default(),
+            )
+        };
+
+        let err = run_merge(None).unwrap_err();
+        assert_eq!(err.to_string(), "Dry run found 1 problem(s)");
+        assert_eq!(run_merge(Some("Artist".to_string())).unwrap().len(), 0);
+        assert!(!fs::exists(&output).unwrap());
+        assert!(!fs::exists(&output_cue).unwrap());
+
+        let run_split = |duration: u64| {
+            let test_cue = format!(
+                r#"TITLE "Album"
+PERFORMER "Artist"
+FILE "1.wav" WAVE
+  TRACK 01 AUDIO
+    TITLE "A"
+    PERFORMER "Artist"
+    REM DURATION 22050
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE "B"
+    PERFORMER "Artist"
+    REM DURATION {duration}
+    INDEX 01 00:00:30"#
+            );
+            fs::write(&output_cue, test_cue).unwrap();
+
+            split(
+                &SplitArgs {
+                    cue: true,
+                    dry_run: true,
+                    ..split_args(&output_cue)
+                },
+                &cli,
+                &mut Report::default(),
+            )
+        };
+
+        let err = run_split(22051).unwrap_err();
+        assert_eq!(err.to_string(), "Dry run found 1 problem(s)");
+        assert_eq!(run_split(22050).unwrap().len(), 0);
+
+        let written = fs::read_dir(&test_dir).unwrap().count();
+        assert_eq!(written, 4);
+
+        remove_tmp_files(false);
+    }
+
+    #[test]
+    #[serial]
+    fn test_wav_info() {
+        remove_tmp_files(false);
+        let test_dir = get_test_dir();
+
+        let mut output_cue = test_dir.clone();
+        output_cue.push("output.cue");
+
+        let cli = Cli {
+            force: false,
+            ..test_cli()
+        };
+
+        let input = create_test_wavs(false);
+
+        let test_cue = r#"TITLE "Album"
+PERFORMER "Artist"
+FILE "1.wav" WAVE
+  TRACK 01 AUDIO
+    TITLE "A"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE "B"
+    INDEX 00 00:00:30
+FILE "2.wav" WAVE
+    INDEX 01 00:00:00
+  TRACK 03 AUDIO
+    TITLE "C"
+    REM DURATION 50000
+    INDEX 01 00:00:30"#;
+
+        fs::write(&output_cue, test_cue).unwrap();
+        let mut report = Report::default();
+        info(&output_cue, &cli, &mut report).unwrap();
+
+        let files = &report.results[0].1["files"];
+        assert_eq!(files.as_array().unwrap().len(), 2);
+        assert_eq!(files[0]["tracks_end"], json!(17640));
+        assert_eq!(files[1]["tracks_end"], json!(67640));
+
+        let tracks = &files[1]["tracks"];
+        assert_eq!(tracks[0]["length"], json!(17640));
+        assert_eq!(tracks[0]["length_source"], json!("MSF"));
+        assert_eq!(tracks[1]["length_source"], json!("REM DURATION"));
+        assert_eq!(tracks[1]["overrun"], json!(true));
+
+        info(&input[0], &cli, &mut report).unwrap();
+        assert_eq!(report.results[1].1["samples"], json!(44100));
+
+        remove_tmp_files(false);
+    }
+
+    #[test]
+    #[serial]
+    fn test_wav_lint() {
+        remove_tmp_files(false);
+        let test_dir = get_test_dir();
+
+        let mut output_cue = test_dir.clone();
+        output_cue.push("output.cue");
+
+        let input = create_test_wavs(false);
+
+        let test_cue = r#"TITLE "Album"
+PERFORMER "Artist"
+FILE "1.wav" WAVE
+  TRACK 01 AUDIO
+    INDEX 01 00:00:00
+  TRACK 01 AUDIO
+    INDEX 01 00:00:75
+  TRACK 03 AUDIO
+    INDEX 00 00:00:40
+  TRACK 02 AUDIO
+    INDEX 01 00:00:30
+FILE "missing.wav" WAVE"#;
+
+        fs::write(&output_cue, test_cue).unwrap();
+        let issues: Vec<String> = lint_cue_sheet(&output_cue, None)
+            .unwrap()
+            .iter()
+            .map(|i| i.to_string())
+            .collect();
+        assert_eq!(
+            issues,
+            [
+                "line 6: Track 01 is listed twice",
+                "line 7: INDEX 01 00:00:75 of track 01 has 75 frames, the maximum is 74",
+                "line 8: Track 03 has no INDEX 01",
+                "line 10: Track 02 comes after track 03",
+                "line 11: INDEX 01 00:00:30 of track 02 is before the previous INDEX at 00:00:40",
+                "line 12: FILE \"missing.wav\" not found",
+            ]
+        );
+
+        fs::write(
+            &output_cue,
+            test_cue.replace("00:00:40", "307445734561825860:00:00"),
+        )
+        .unwrap();
+        let issues = lint_cue_sheet(&output_cue, None).unwrap();
+        assert_eq!(
+            issues[2].to_string(),
+            "line 9: INDEX 00 307445734561825860:00:00 of track 03 is out of range"
+        );
+
+        let write_cue = |duration: u64| {
+            let test_cue = format!(
+                r#"TITLE "Album"
+PERFORMER "Artist"
+FILE "1.wav" WAVE
+  TRACK 01 AUDIO
+    TITLE "A"
+    REM DURATION 22050
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE "B"
+    INDEX 01 00:00:30
+FILE "2.wav" WAVE
+  TRACK 03 AUDIO
+    TITLE "C"
+    REM DURATION {duration}
+    INDEX 01 00:00:00"#
+            );
+            fs::write(&output_cue, test_cue).unwrap();
+        };
+
+        write_cue(1000);
+        let issues = lint_cue_sheet(&output_cue, None).unwrap();
+        assert!(issues.iter().all(|i| i.severity == Severity::Warning));
+        assert_eq!(
+            issues[0].message,
+            "Track 01 REM DURATION ends at 00:00:38 (22050), the MSF timing at 00:00:30 (17640)"
+        );
+        assert_eq!(
+            issues[1].message,
+            "43100 samples of 2.wav after the last track are not in any track"
+        );
+
+        write_cue(50000);
+        let issues = lint_cue_sheet(&output_cue, None).unwrap();
+        assert_eq!(
+            issues[1].message,
+            "Track 03 ends 5900 samples after the end of 2.wav (00:01:00)"
+        );
+
+        let cli = test_cli();
+        let err = split(
+            &SplitArgs {
+                cue: true,
+                ..split_args(&output_cue)
+            },
+            &cli,
+            &mut Report::default(),
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "CUE sheet has 1 error(s)");
+        assert_eq!(fs::read_dir(&test_dir).unwrap().count(), input.len() + 1);
+
+        remove_tmp_files(false);
+    }
+
+    #[test]
+    #[serial]
+    fn test_wav_split_cue_encoding() {
+        remove_tmp_files(false);
+        let test_dir = get_test_dir();
+
+        let mut output_cue = test_dir.clone();
+        output_cue.push("output.cue");
+
+        let cli = test_cli();
+
+        let input = create_test_wavs(false);
+
+        let test_cue = r#"TITLE "Группа крови"
+PERFORMER "Кино"
+FILE "1.wav" WAVE
+  TRACK 01 AUDIO
+    TITLE "Группа крови"
+    PERFORMER "Кино"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE "Закрой за мной дверь, я ухожу"
+    INDEX 01 00:00:30"#;
+
+        let (cp1251, _, _) = WINDOWS_1251.encode(test_cue);
+        fs::write(&output_cue, &cp1251).unwrap();
+
+        let split_output = split(&split_args(&output_cue), &cli, &mut Report::default()).unwrap();
+        assert!(split_output[0].ends_with("01 Кино - Группа крови.wav"));
+        remove_wavs(&split_output);
+
+        let mut utf16 = vec![0xff, 0xfe];
+        utf16.extend(test_cue.encode_utf16().flat_map(|c| c.to_le_bytes()));
+        fs::write(&output_cue, &utf16).unwrap();
+        assert_eq!(
+            read_cue_text(&output_cue, Some(WINDOWS_1252)).unwrap(),
+            (test_cue.to_string(), UTF_16LE)
+        );
+
+        let test_cue = test_cue.replace("Кино", "椎名林檎");
+        let (shift_jis, _, _) = SHIFT_JIS.encode(&test_cue);
+        fs::write(&output_cue, format!("\u{feff}{}", test_cue)).unwrap();
+        assert_eq!(read_cue_text(&output_cue, None).unwrap().0, test_cue);
+        fs::write(&output_cue, &shift_jis).unwrap();
+        assert_eq!(
+            read_cue_text(&output_cue, Some(SHIFT_JIS)).unwrap().0,
+            test_cue
+        );
+
+        remove_wavs(&input);
+        remove_tmp_files(false);
+    }
+
+    #[test]
+    #[serial]
+    fn test_wav_split_disc_folders() {
+        remove_tmp_files(false);
+        let test_dir = get_test_dir();
+
+        let mut output_cue = test_dir.clone();
+        output_cue.push("output.cue");
+
+        let cli = test_cli();
+
+        let input = create_test_wavs(false);
+
+        let test_cue = r#"TITLE "Album"
+PERFORMER "Artist"
+FILE "1.wav" WAVE
+  TRACK 01 AUDIO
+    TITLE "A"
+    REM DISCNUMBER 1
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE "B"
+    INDEX 01 00:00:30
+FILE "2.wav" WAVE
+  TRACK 03 AUDIO
+    TITLE "A"
+    REM DISCNUMBER 2
+    INDEX 01 00:00:00
+  TRACK 04 AUDIO
+    TITLE "A"
+    INDEX 01 00:00:30"#;
+
+        fs::write(&output_cue, test_cue).unwrap();
+
+        let split_output = split(
+            &SplitArgs {
+                cue: true,
+                output_dir: Some(test_dir.join("rips")),
+                format: Some("%albumartist%/%album%/CD%discnumber%/%title%".to_string()),
+                ..split_args(&output_cue)
+            },
+            &cli,
+            &mut Report::default(),
+        )
+        .unwrap();
+
+        let album_dir = test_dir.join("rips").join("Artist").join("Album");
+        assert_eq!(
+            split_output,
+            [
+                album_dir.join("CD1").join("A.wav"),
+                album_dir.join("CD1").join("B.wav"),
+                album_dir.join("CD2").join("A.wav"),
+                album_dir.join("CD2").join("A (2).wav"),
+            ]
+        );
+        assert!(split_output.iter().all(|f| f.exists()));
+
+        let cue_multiple = fs::read_to_string(test_dir.join("output_multiple.cue")).unwrap();
+        assert!(cue_multiple.contains(&format!(
+            "FILE \"{}\" WAVE",
+            Path::new("rips/Artist/Album/CD2/A (2).wav").display()
+        )));
+
+        fs::remove_dir_all(test_dir.join("rips")).unwrap();
+        remove_wavs(&input);
+        remove_tmp_files(false);
+    }
+
+    #[test]
+    #[serial]
+    fn test_wav_split_recursive() {
+        remove_tmp_files(false);
+        let test_dir = get_test_dir();
+        let library = test_dir.join("library");
+        let output_dir = Some(test_dir.join("out"));
+
+        let cli = Cli {
+            force: true,
+            ..test_cli()
+        };
+
+        let input = create_test_wavs(false);
+
+        let album_cue = |file: &str| {
+            format!(
+                r#"TITLE "Album"
+PERFORMER "Artist"
+FILE "{file}" WAVE
+  TRACK 01 AUDIO
+    TITLE "A"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE "B"
+    INDEX 01 00:00:30"#
+            )
+        };
+
+        for (dir, file, cue) in [
+            ("1 Good", "1.wav", album_cue("1.wav")),
+            ("2 Missing", "2.wav", album_cue("missing.wav")),
+            (
+                "3 Broken",
+                "3.wav",
+                album_cue("3.wav").replace("00:00:30", "00:00:75"),
+            ),
+        ] {
+            fs::create_dir_all(library.join(dir)).unwrap();
+            fs::copy(test_dir.join(file), library.join(dir).join(file)).unwrap();
+            fs::write(library.join(dir).join("album.cue"), cue).unwrap();
+        }
+
+        let run_split = |resplit: bool, report: &mut Report| {
+            split_recursive(
+                &library,
+                &output_dir,
+                false,
+                resplit,
+                &cli,
+                report,
+                |input, output_dir, report| {
+                    split(
+                        &SplitArgs {
+                            output_dir: output_dir.clone(),
+                            ..split_args(input)
+                        },
+                        &cli,
+                        report,
+                    )
+                },
+            )
+        };
+
+        let statuses = |report: &Report| {
+            report
+                .results
+                .last()
+                .unwrap()
+                .1
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|album| (album["status"].clone(), album["message"].clone()))
+                .collect::<Vec<(Value, Value)>>()
+        };
+
+        let mut report = Report::default();
+        let err = run_split(false, &mut report).unwrap_err();
+        assert_eq!(err.to_string(), "1 of 3 albums failed");
+        let album = test_dir.join("out").join("1 Good");
+        assert!(album.join("01 Artist - A.wav").exists());
+        assert!(album.join("02 Artist - B.wav").exists());
+        assert!(album.join(".album.cue.wfcue-done").exists());
+
+        let status = statuses(&report);
+        assert_eq!(status[0], (json!("done"), Value::Null));
+        assert_eq!(
+            status[1],
+            (json!("skipped"), json!("missing.wav not found"))
+        );
+        assert_eq!(status[2].0, json!("failed"));
+
+        let mut report = Report::default();
+        run_split(false, &mut report).unwrap_err();
+        assert_eq!(
+            statuses(&report)[0],
+            (json!("skipped"), json!("already split"))
+        );
+
+        let mut report = Report::default();
+        run_split(true, &mut report).unwrap_err();
+        assert_eq!(statuses(&report)[0], (json!("done"), Value::Null));
+
+        let marker = album.join(".album.cue.wfcue-done");
+        fs::remove_file(&marker).unwrap();
+        fs::create_dir(&marker).unwrap();
+        let mut report = Report::default();
+        let err = run_split(true, &mut report).unwrap_err();
+        assert_eq!(err.to_string(), "2 of 3 albums failed");
+        let (status, message) = &statuses(&report)[0];
+        assert_eq!(status, &json!("failed"));
+        assert!(message.as_str().unwrap().starts_with("Failed to write"));
+
+        fs::remove_dir_all(&library).unwrap();
+        fs::remove_dir_all(test_dir.join("out")).unwrap();
+        remove_wavs(&input);
+        remove_tmp_files(false);
+    }
+
+    #[test]
+    #[serial]
+    fn test_wav_split_jobs() {
+        remove_tmp_files(false);
+        let test_dir = get_test_dir();
+        let image = test_dir.join("image.wav");
+        let image_cue = test_dir.join("image.cue");
+
+        let cli = test_cli();
+
+        fs::write(
+            &image_cue,
+            r#"TITLE "Album"
+PERFORMER "Artist"
+FILE "image.wav" WAVE
+  TRACK 01 AUDIO
+    TITLE "1"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE "2"
+    INDEX 01 00:00:40
+  TRACK 03 AUDIO
+    TITLE "3"
+    INDEX 01 00:01:10"#,
+        )
+        .unwrap();
+
+        // hound can't seek in 24 bit samples stored in 4 bytes
+        for (bits_per_sample, bytes_per_sample) in [(16, 2), (24, 3), (24, 4)] {
+            let spec = WavSpec {
+                channels: 2,
+                sample_rate: 44100,
+                bits_per_sample,
+                sample_format: SampleFormat::Int,
+            };
+            let spec_ex = WavSpecEx {
+                spec,
+                bytes_per_sample,
+            };
+            let mut writer =
+                WavWriter::new_with_spec_ex(File::create(&image).unwrap(), spec_ex).unwrap();
+            for i in 0..88200 * 2 {
+                writer
+                    .write_sample((i % 65536 - 32768) << (bits_per_sample - 16))
+                    .unwrap();
+            }
+            writer.finalize().unwrap();
+
+            let split_jobs = |jobs: u16, dir: &str| {
+                split(
+                    &SplitArgs {
+                        output_dir: Some(test_dir.join(dir)),
+                        verify: true,
+                        jobs,
+                        ..split_args(&image_cue)
+                    },
+                    &cli,
+                    &mut Report::default(),
+                )
+                .unwrap()
+            };
+
+            let sequential = split_jobs(1, "sequential");
+            let parallel = split_jobs(3, "parallel");
+
+            assert_eq!(parallel.len(), 3);
+            for (s, p) in sequential.iter().zip(&parallel) {
+                assert_eq!(s.file_name(), p.file_name());
+                assert_eq!(hash_file(s), hash_file(p));
+            }
+
+            fs::remove_dir_all(test_dir.join("sequential")).unwrap();
+            fs::remove_dir_all(test_dir.join("parallel")).unwrap();
+        }
+
+        fs::remove_file(image).unwrap();
+        fs::remove_file(image_cue).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_wav_split_tracks() {
+        remove_tmp_files(false);
+        let test_dir = get_test_dir();
+
+        let mut output_cue = test_dir.clone();
+        output_cue.push("output.cue");
+
+        let cli = test_cli();
+
+        let input = create_test_wavs(false);
+
+        fs::write(
+            &output_cue,
+            r#"TITLE "Album"
+PERFORMER "Artist"
+FILE "1.wav" WAVE
+  TRACK 01 AUDIO
+    TITLE "1"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE "2"
+    INDEX 00 00:00:30
+    INDEX 01 00:00:40
+FILE "2.wav" WAVE
+  TRACK 03 AUDIO
+    TITLE "3"
+    INDEX 01 00:00:00
+  TRACK 04 AUDIO
+    TITLE "4"
+    INDEX 01 00:00:50"#,
+        )
+        .unwrap();
+
+        let split_tracks = |tracks: &[TrackRange], dir: &str| {
+            split(
+                &SplitArgs {
+                    cue: true,
+                    output_dir: Some(test_dir.join(dir)),
+                    verify: true,
+                    tracks: tracks.to_vec(),
+                    ..split_args(&output_cue)
+                },
+                &cli,
+                &mut Report::default(),
+            )
+        };
+
+        let all = split_tracks(&[], "all").unwrap();
+        let some = split_tracks(
+            &[
+                TrackRange { first: 2, last: 2 },
+                TrackRange { first: 4, last: 4 },
+            ],
+            "some",
+        )
+        .unwrap();
+
+        assert_eq!(all.len(), 4);
+        assert_eq!(some.len(), 2);
+        for (a, s) in [(&all[1], &some[0]), (&all[3], &some[1])] {
+            assert_eq!(a.file_name(), s.file_name());
+            assert_eq!(hash_file(a), hash_file(s));
+        }
+
+        // The pregap of track 2 is in the file of track 1, the tracks keep their numbers
+        let cue_multiple = fs::read_to_string(test_dir.join("output_multiple.cue")).unwrap();
+        assert_eq!(
+            cue_multiple,
+            format!(
+                r#"TITLE "Album"
+PERFORMER "Artist"
+FILE "{}" WAVE
+  TRACK 02 AUDIO
+    TITLE "2"
+    PREGAP 00:00:10
+    INDEX 01 00:00:00
+FILE "{}" WAVE
+  TRACK 04 AUDIO
+    TITLE "4"
+    INDEX 01 00:00:00"#,
+                Path::new("some/02 Artist - 2.wav").display(),
+                Path::new("some/04 Artist - 4.wav").display()
+            )
+        );
+
+        let err = split_tracks(&[TrackRange { first: 3, last: 5 }], "some").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Track 5 is not in the CUE sheet, it has 4 tracks"
+        );
+
+        fs::remove_dir_all(test_dir.join("all")).unwrap();
+        fs::remove_dir_all(test_dir.join("some")).unwrap();
+        remove_wavs(&input);
+        remove_tmp_files(false);
+    }
+
+    #[test]
+    #[serial]
+    fn test_flac_split_tracks_seek() {
+        remove_tmp_files(true);
+        let test_dir = get_test_dir();
+        let image = test_dir.join("image.flac");
+        let image_cue = test_dir.join("image.cue");
+
+        let cli = test_cli();
+
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: 8000,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let mut writer = FlacWriter::create(&image, spec, 0, 8000 * 40, &[]).unwrap();
+        let mut noise: u32 = 1;
+        for _ in 0..8000 * 40 + 1234 {
+            noise = noise.wrapping_mul(1664525).wrapping_add(1013904223);
+            writer.write_sample((noise >> 16) as i16 as i32).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        fs::write(
+            &image_cue,
+            r#"TITLE "Album"
+PERFORMER "Artist"
+FILE "image.flac" WAVE
+  TRACK 01 AUDIO
+    TITLE "1"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE "2"
+    INDEX 01 00:12:00
+  TRACK 03 AUDIO
+    TITLE "3"
+    INDEX 01 00:25:10
+  TRACK 04 AUDIO
+    TITLE "4"
+    INDEX 01 00:39:74"#,
+        )
+        .unwrap();
+
+        let split_tracks = |tracks: &[TrackRange], jobs: u16, dir: &str| {
+            split(
+                &SplitArgs {
+                    output_dir: Some(test_dir.join(dir)),
+                    verify: true,
+                    jobs,
+                    tracks: tracks.to_vec(),
+                    ..split_args(&image_cue)
+                },
+                &cli,
+                &mut Report::default(),
+            )
+            .unwrap()
+        };
+
+        let all = split_tracks(&[], 1, "all");
+        // Track 4 starts in the last frame, which is shorter than the others
+        let selected = [
+            TrackRange { first: 2, last: 2 },
+            TrackRange { first: 4, last: 4 },
+        ];
+
+        // With the SEEKTABLE, then with the frames found by a scan
+        for seek_table in [true, false] {
+            if !seek_table {
+                let mut data = fs::read(&image).unwrap();
+                assert_eq!(data[42] & 0x7f, 3);
+                data[42] = data[42] & 0x80 | 1;
+                fs::write(&image, data).unwrap();
+            }
+
+            // Every worker of --jobs seeks to its own track
+            for jobs in [1, 2] {
+                let some = split_tracks(&selected, jobs, "some");
+
+                assert_eq!(some.len(), 2);
+                for (a, s) in [(&all[1], &some[0]), (&all[3], &some[1])] {
+                    assert_eq!(a.file_name(), s.file_name());
+                    assert_eq!(hash_file(a), hash_file(s));
+                }
+
+                fs::remove_dir_all(test_dir.join("some")).unwrap();
+            }
+
+            let parallel = split_tracks(&[], 3, "parallel");
+
+            assert_eq!(parallel.len(), all.len());
+            for (a, p) in all.iter().zip(&parallel) {
+                assert_eq!(a.file_name(), p.file_name());
+                assert_eq!(hash_file(a), hash_file(p));
+            }
+
+            fs::remove_dir_all(test_dir.join("parallel")).unwrap();
+        }
+
+        fs::remove_dir_all(test_dir.join("all")).unwrap();
+        fs::remove_file(image).unwrap();
+        fs::remove_file(image_cue).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_wav_split_tags() {
+        remove_tmp_files(true);
+        let test_dir = get_test_dir();
+
+        let mut output_cue = test_dir.clone();
+        output_cue.push("output.cue");
+
+        let cli = Cli {
+            totally_silent: false,
+            ..test_cli()
+        };
+
+        let input = create_test_wavs(false);
+
+        let test_cue = r#"REM GENRE "Jazz"
+REM DATE 1959
+TITLE "Album"
+PERFORMER "Band"
+FILE "1.wav" WAVE
+  TRACK 01 AUDIO
+    TITLE "A"
+    PERFORMER "Artist"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE "B"
+    INDEX 01 00:00:30"#;
+
+        fs::write(&output_cue, test_cue).unwrap();
+
+        let split_output = split(
+            &SplitArgs {
+                id3: true,
+                ..split_args(&output_cue)
+            },
+            &cli,
+            &mut Report::default(),
+        )
+        .unwrap();
+
+        let info = b"LIST\x52\x00\x00\x00INFO\
+INAM\x02\x00\x00\x00B\x00\
+IART\x05\x00\x00\x00Band\x00\x00\
+IPRD\x06\x00\x00\x00Album\x00\
+ITRK\x04\x00\x00\x002/2\x00\
+ICRD\x05\x00\x00\x001959\x00\x00\
+IGNR\x05\x00\x00\x00Jazz\x00\x00";
+
+        let data = fs::read(&split_output[1]).unwrap();
+        let riff_size = u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize;
+        let list = data.windows(4).position(|w| w == b"LIST").unwrap();
+        let id3 = data.windows(4).position(|w| w == b"id3 ").unwrap();
+
+        assert_eq!(riff_size, data.len() - 8);
+        assert_eq!(&data[list..id3], info);
+        assert_eq!(&data[id3 + 8..id3 + 13], b"ID3\x04\x00");
+        assert_eq!(
+            hound::WavReader::open(&split_output[1]).unwrap().duration(),
+            26460
+        );
+
+        let flac_output = split(
+            &SplitArgs {
+                verify: true,
+                output_format: OutputFormat::Flac,
+                ..split_args(&output_cue)
+            },
+            &cli,
+            &mut Report::default(),
+        )
+        .unwrap();
+
+        let reader = FlacReader::open(&flac_output[0]).unwrap();
+        let tags: Vec<(&str, &str)> = reader.tags().collect();
+        assert_eq!(
+            tags,
+            vec![
+                ("TITLE", "A"),
+                ("ARTIST", "Artist"),
+                ("ALBUM", "Album"),
+                ("ALBUMARTIST", "Band"),
+                ("TRACKNUMBER", "1"),
+                ("TRACKTOTAL", "2"),
+                ("DATE", "1959"),
+                ("GENRE", "Jazz"),
+            ]
+        );
+
+        remove_wavs(&split_output);
+        remove_wavs(&flac_output);
+        remove_wavs(&input);
+        remove_tmp_files(true);
+    }
+
+    #[test]
+    #[serial]
+    fn test_split_embedded_cuesheet() {
+        remove_tmp_files(true);
+        let test_dir = get_test_dir();
+
+        let mut image = test_dir.clone();
+        image.push("output.flac");
+
+        let cli = Cli {
+            totally_silent: false,
+            ..test_cli()
+        };
+
+        let input = create_test_wavs(false);
+        let spec = get_audio_info(&input[0]).unwrap().spec;
+        let samples: Vec<i32> = hound::WavReader::open(&input[0])
+            .unwrap()
+            .samples::<i32>()
+            .map(|s| s.unwrap())
+            .collect();
+
+        let cue_comment = r#"CUESHEET=TITLE "Album"
+PERFORMER "Artist"
+FILE "CDImage.wav" WAVE
+  TRACK 01 AUDIO
+    TITLE "A"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE "B"
+    INDEX 01 00:00:20
+  TRACK 03 AUDIO
+    TITLE "C"
+    INDEX 00 00:00:34
+    INDEX 01 00:00:40"#;
+
+        let block_tracks = [
+            (1, 0, vec![(1, 0)]),
+            (2, 11770, vec![(1, 0)]),
+            (3, 20000, vec![(0, 0), (1, 3530)]),
+            (255, 44100, vec![]),
+        ]
+        .map(|(number, offset, indices)| CueSheetTrack {
+            number,
+            offset,
+            indices,
+        });
+
+        let tests = [
+            (true, false, [11760, 11760, 20580], "03 Artist - C.wav"),
+            (true, true, [11770, 11760, 20570], "03 Artist - C.wav"),
+            (
+                false,
+                true,
+                [11770, 11760, 20570],
+                "03 Artist - Track 03.wav",
+            ),
+        ];
+
+        for (comment, block, lengths, last_name) in tests {
+            let comments: Vec<String> = if comment {
+                vec![cue_comment.to_string()]
+            } else {
+                vec!["ARTIST=Artist".to_string()]
+            };
+
+            let mut writer =
+                FlacWriter::create(&image, spec, 5, samples.len() as u64 / 2, &comments).unwrap();
+            samples
+                .iter()
+                .for_each(|s| writer.write_sample(*s).unwrap());
+            writer.finalize().unwrap();
+
+            if block {
+                add_cuesheet_block(&image, &block_tracks);
+            }
+
+            let split_output = split(
+                &SplitArgs {
+                    cue: true,
+                    verify: true,
+                    ..split_args(&image)
+                },
+                &cli,
+                &mut Report::default(),
+            )
+            .unwrap();
+
+            let split_lengths: Vec<u32> = split_output
+                .iter()
+                .map(|f| hound::WavReader::open(f).unwrap().duration())
+                .collect();
+
+            assert_eq!(split_lengths, lengths);
+            assert!(split_output[2].ends_with(last_name));
+
+            let mut cue_multiple = test_dir.clone();
+            cue_multiple.push("output_multiple.cue");
+            assert!(fs::read_to_string(&cue_multiple)
+                .unwrap()
+                .contains(&format!("FILE \"{}\" WAVE", last_name)));
+
+            remove_wavs(&split_output);
+        }
+
+        remove_wavs(&input);
+        remove_tmp_files(true);
+    }
+
+    #[test]
+    #[serial]
+    fn test_wav_merge_tags() {
+        remove_tmp_files(true);
+        let test_dir = get_test_dir();
+
+        let mut output = test_dir.clone();
+        output.push("output.wav");
+
+        let mut output_cue = test_dir.clone();
+        output_cue.push("output.cue");
+
+        let cli = Cli {
+            totally_silent: false,
+            ..test_cli()
+        };
+
+        let mut input = create_test_wavs(false);
+
+        let info = Tags {
+            title: Some("First".to_string()),
+            artist: Some("Band".to_string()),
+            album: Some("Album".to_string()),
+            date: Some("1971".to_string()),
+            ..Default::default()
+        };
+        append_wav_chunks(&input[0], &[info.riff_info_chunk()]).unwrap();
+
+        let id3 = Tags {
+            title: Some("Second \"Live\"".to_string()),
+            artist: Some("Band".to_string()),
+            isrc: Some("USABC7100002".to_string()),
+            ..Default::default()
+        };
+        append_wav_chunks(&input[1], &[id3.riff_info_chunk(), id3.id3_chunk()]).unwrap();
+        assert_eq!(Tags::read(&input[1]).unwrap(), id3);
+
+        let spec = get_audio_info(&input[2]).unwrap().spec;
+        let samples: Vec<i32> = hound::WavReader::open(&input[2])
+            .unwrap()
+            .samples::<i32>()
+            .map(|s| s.unwrap())
+            .collect();
+        let comments =
+            ["TITLE=Third", "ARTIST=Band", "GENRE=Progressive Rock"].map(|c| c.to_string());
+        fs::remove_file(&input[2]).unwrap();
+        input[2].set_extension("flac");
+        let mut writer =
+            FlacWriter::create(&input[2], spec, 5, samples.len() as u64 / 2, &comments).unwrap();
+        samples
+            .iter()
+            .for_each(|s| writer.write_sample(*s).unwrap());
+        writer.finalize().unwrap();
+
+        let test_cue = r#"REM GENRE "Progressive Rock"
+REM DATE 1971
+TITLE "Album"
+PERFORMER "Band"
+FILE "output.wav" WAVE
+  TRACK 01 AUDIO
+    TITLE "First"
+    PERFORMER "Band"
+    INDEX 01 00:00:00
+    REM DURATION 44100
+  TRACK 02 AUDIO
+    TITLE "Second 'Live'"
+    PERFORMER "Band"
+    ISRC USABC7100002
+    INDEX 01 00:01:00
+    REM DURATION 44100
+  TRACK 03 AUDIO
+    TITLE "Third"
+    PERFORMER "Band"
+    INDEX 01 00:02:00
+    REM DURATION 44100"#;
+
+        for (title, test_cue) in [
+            (None, test_cue.to_string()),
+            (
+                Some("Other".to_string()),
+                test_cue.replace(r#"TITLE "Album""#, r#"TITLE "Other""#),
+            ),
+        ] {
+            merge(
+                &MergeArgs {
+                    cue: true,
+                    title: title.clone(),
+                    verify: true,
+                    ..merge_args(&input, &output)
+                },
+                &cli,
+                &mut Report::default(),
+            )
+            .unwrap();
+
+            let cue = fs::read_to_string(&output_cue).unwrap();
+            assert_eq!(cue, test_cue);
+            assert_eq!(CueSheet::try_from(cue.as_ref()).unwrap().to_string(), cue);
+        }
+
+        remove_wavs(&input);
+        remove_tmp_files(true);
+    }
+
+    #[test]
+    #[serial]
+    fn test_wav_merge_sort() {
+        remove_tmp_files(false);
+        let test_dir = get_test_dir();
+
+        let mut output = test_dir.clone();
+        output.push("output.wav");
+
+        let mut output_cue = test_dir.clone();
+        output_cue.push("output.cue");
+
+        let cli = Cli {
+            totally_silent: false,
+            ..test_cli()
+        };
+
+        let mut input = create_test_wavs(false);
+        let mut renamed = test_dir.clone();
+        renamed.push("10.wav");
+        fs::rename(&input[1], &renamed).unwrap();
+        input[1] = renamed;
+
+        for (file, track_number) in input.iter().zip([3, 2, 1]) {
+            let tags = Tags {
+                track_number: Some(track_number),
+                ..Default::default()
+            };
+            append_wav_chunks(file, &[tags.riff_info_chunk()]).unwrap();
+        }
+
+        let glob_order = vec![input[0].clone(), input[1].clone(), input[2].clone()];
+
+        for (sort, titles) in [
+            (InputSort::None, ["1", "10", "3"]),
+            (InputSort::Natural, ["1", "3", "10"]),
+            (InputSort::Tracknumber, ["3", "10", "1"]),
+        ] {
+            merge(
+                &MergeArgs {
+                    cue: true,
+                    sort,
+                    ..merge_args(&glob_order, &output)
+                },
+                &cli,
+                &mut Report::default(),
+            )
+            .unwrap();
+
+            let cue = CueSheet::try_from(fs::read_to_string(&output_cue).unwrap().as_ref())
+                .unwrap()
+                .cue;
+            let cue_titles: Vec<&str> = cue.tracks.iter().map(|t| t.1.title.as_str()).collect();
+            assert_eq!(cue_titles, titles);
+        }
+
+        input.push(output);
+        remove_wavs(&input);
+        remove_tmp_files(false);
+    }
+
+    #[test]
+    #[serial]
+    fn test_wav_disc_id_toc() {
+        remove_tmp_files(false);
+        let test_dir = get_test_dir();
+
+        let input = create_test_wavs(false);
+
+        let test_cue = r#"TITLE "Album"
+PERFORMER "Artist"
+FILE "1.wav" WAVE
+  TRACK 01 AUDIO
+    TITLE "A"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE "B"
+    INDEX 01 00:00:30
+FILE "2.wav" WAVE
+  TRACK 03 AUDIO
+    TITLE "C"
+    INDEX 00 00:00:00
+    INDEX 01 00:00:15
+FILE "3.wav" WAVE
+  TRACK 04 AUDIO
+    TITLE "D"
+    INDEX 01 00:00:00"#;
+
+        let cue_sheet = CueSheet::try_from(test_cue).unwrap();
+        let toc = Toc::from_cue(&cue_sheet, &test_dir).unwrap();
+
+        assert_eq!(
+            toc,
+            Toc {
+                offsets: vec![0, 30, 90, 150],
+                leadout: 225,
+            }
+        );
+
+        remove_wavs(&input);
+        remove_tmp_files(false);
+    }
+
+    #[test]
+    #[serial]
+    fn test_wav_split_gaps() {
+        remove_tmp_files(false);
+        let test_dir = get_test_dir();
+
+        let mut output = test_dir.clone();
+        output.push("output.wav");
+
+        let mut output_cue = test_dir.clone();
+        output_cue.push("output.cue");
+
+        let mut output_cue_multiple = test_dir.clone();
+        output_cue_multiple.push("output_multiple.cue");
+
+        let cli = Cli {
+            totally_silent: false,
+            ..test_cli()
+        };
+
+        let input = create_test_wavs(false);
+
+        merge(&merge_args(&input, &output), &cli, &mut Report::default()).unwrap();
+
+        let test_cue = r#"TITLE "Album"
+PERFORMER "Artist"
+FILE "output.wav" WAVE
+  TRACK 01 AUDIO
+    TITLE "A"
+    PERFORMER "Artist"
+    INDEX 00 00:00:00
+    INDEX 01 00:00:30
+  TRACK 02 AUDIO
+    TITLE "B"
+    PERFORMER "Artist"
+    INDEX 00 00:01:00
+    INDEX 01 00:01:30
+  TRACK 03 AUDIO
+    TITLE "C"
+    PERFORMER "Artist"
+    INDEX 01 00:02:00"#;
 
-    #[test]
-    #[serial]
-    fn test_wav_split_msf() {
-        test_split(false, true);
-    }
+        fs::write(&output_cue, test_cue).unwrap();
 
-    #[test]
-    #[serial]
-    fn test_flac_split_msf() {
-        test_split(true, true);
-    }
+        let tests = [
+            (
+                GapsMode::Append,
+                vec![17640, 44100, 26460, 44100],
+                r#"TITLE "Album"
+PERFORMER "Artist"
+FILE "00 Artist - Hidden Track.wav" WAVE
+  TRACK 01 AUDIO
+    TITLE "A"
+    PERFORMER "Artist"
+    INDEX 00 00:00:00
+FILE "01 Artist - A.wav" WAVE
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE "B"
+    PERFORMER "Artist"
+    INDEX 00 00:00:45
+FILE "02 Artist - B.wav" WAVE
+    INDEX 01 00:00:00
+FILE "03 Artist - C.wav" WAVE
+  TRACK 03 AUDIO
+    TITLE "C"
+    PERFORMER "Artist"
+    INDEX 01 00:00:00"#,
+            ),
+            (
+                GapsMode::Prepend,
+                vec![44100, 44100, 44100],
+                r#"TITLE "Album"
+PERFORMER "Artist"
+FILE "01 Artist - A.wav" WAVE
+  TRACK 01 AUDIO
+    TITLE "A"
+    PERFORMER "Artist"
+    INDEX 00 00:00:00
+    INDEX 01 00:00:30
+FILE "02 Artist - B.wav" WAVE
+  TRACK 02 AUDIO
+    TITLE "B"
+    PERFORMER "Artist"
+    INDEX 00 00:00:00
+    INDEX 01 00:00:30
+FILE "03 Artist - C.wav" WAVE
+  TRACK 03 AUDIO
+    TITLE "C"
+    PERFORMER "Artist"
+    INDEX 01 00:00:00"#,
+            ),
+            (
+                GapsMode::Discard,
+                vec![26460, 26460, 44100],
+                r#"TITLE "Album"
+PERFORMER "Artist"
+FILE "01 Artist - A.wav" WAVE
+  TRACK 01 AUDIO
+    TITLE "A"
+    PERFORMER "Artist"
+    PREGAP 00:00:30
+    INDEX 01 00:00:00
+FILE "02 Artist - B.wav" WAVE
+  TRACK 02 AUDIO
+    TITLE "B"
+    PERFORMER "Artist"
+    PREGAP 00:00:30
+    INDEX 01 00:00:00
+FILE "03 Artist - C.wav" WAVE
+  TRACK 03 AUDIO
+    TITLE "C"
+    PERFORMER "Artist"
+    INDEX 01 00:00:00"#,
+            ),
+        ];
+
+        for (gaps, lengths, test_cue_multiple) in tests {
+            let split_output = split(
+                &SplitArgs {
+                    cue: true,
+                    verify: true,
+                    gaps,
+                    ..split_args(&output_cue)
+                },
+                &cli,
+                &mut Report::default(),
+            )
+            .unwrap();
+
+            let split_lengths: Vec<u32> = split_output
+                .iter()
+                .map(|f| hound::WavReader::open(f).unwrap().duration())
+                .collect();
+
+            assert_eq!(split_lengths, lengths);
+
+            let cue_multiple = fs::read_to_string(&output_cue_multiple).unwrap();
+            assert_eq!(cue_multiple, test_cue_multiple);
+            assert_eq!(
+                CueSheet::try_from(cue_multiple.as_ref())
+                    .unwrap()
+                    .to_string(),
+                test_cue_multiple
+            );
 
-    #[test]
-    #[serial]
-    fn test_wav_split() {
-        test_split(false, false);
+            remove_wavs(&split_output);
+        }
+
+        remove_wavs(&input);
+        remove_tmp_files(false);
     }
 
     #[test]
     #[serial]
-    fn test_flac_split() {
-        test_split(true, false);
+    fn test_wav_verify_mismatch() {
+        remove_tmp_files(false);
+        let test_dir = get_test_dir();
+
+        let cli = test_cli();
+
+        let input = create_test_wavs(false);
+        let spec = get_audio_info(&input[0]).unwrap().spec;
+        let samples: Vec<i32> = hound::WavReader::open(&input[0])
+            .unwrap()
+            .samples::<i32>()
+            .map(|s| s.unwrap())
+            .collect();
+
+        let write_copy = |name: &str, samples: &[i32]| {
+            let mut file = test_dir.clone();
+            file.push(name);
+            let mut writer = WavWriter::create(&file, spec).unwrap();
+            samples
+                .iter()
+                .for_each(|s| writer.write_sample(*s as i16).unwrap());
+            writer.finalize().unwrap();
+            file
+        };
+
+        let mut corrupted = samples.clone();
+        corrupted[30001] += 1;
+
+        let outputs = vec![
+            write_copy("ok.wav", &samples),
+            write_copy("corrupted.wav", &corrupted),
+            test_dir.join("missing.wav"),
+            write_copy("truncated.wav", &samples[..40000]),
+            write_copy("cut.wav", &[&samples[..], &[0; 10]].concat()),
+        ];
+
+        // The samples after the segment can't be decoded
+        let cut = File::options().write(true).open(&outputs[4]).unwrap();
+        cut.set_len(cut.metadata().unwrap().len() - 3).unwrap();
+
+        let verifier = OutputVerifier::new();
+        for output in &outputs {
+            let mut hasher = verifier.hasher();
+            samples
+                .iter()
+                .for_each(|s| hasher.update_sample(s).unwrap());
+
+            let segment = Segment {
+                name: output.file_name().unwrap().to_str().unwrap().to_string(),
+                source: SampleSource::Range {
+                    file: input[0].clone(),
+                    start: 0,
+                    length: samples.len() as u64,
+                },
+                hasher,
+            };
+            verifier.check(output, spec, vec![segment]).unwrap();
+        }
+
+        // The missing file doesn't stop the files after it from being verified
+        let err = verifier
+            .finish(&cli, &mut Report::default())
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("Verify FAILED: Samples mismatch in 4 of 5 tracks"));
+        assert!(err.contains("corrupted.wav (first mismatch at 00:00:25 (sample 15000))"));
+        assert!(err.contains("missing.wav (can't be read back: "));
+        assert!(err.contains("truncated.wav (truncated at 00:00:34 (sample 20000))"));
+        assert!(err.contains("cut.wav (can't be read back: "));
+        assert!(!err.contains("ok.wav"));
+
+        remove_wavs(&outputs);
+        remove_wavs(&input);
+        remove_tmp_files(false);
     }
 
     #[test]
     #[serial]
-    fn test_wav_merge() {
-        test_merge(false, true);
+    fn test_wav_verify_command() {
+        remove_tmp_files(false);
+        let test_dir = get_test_dir();
+
+        let mut output = test_dir.clone();
+        output.push("output.wav");
+
+        let mut output_cue = test_dir.clone();
+        output_cue.push("output.cue");
+
+        let mut tracks_dir = test_dir.clone();
+        tracks_dir.push("tracks");
+        fs::create_dir_all(&tracks_dir).unwrap();
+
+        let cli = test_cli();
+
+        let input = create_test_wavs(false);
+
+        merge(
+            &MergeArgs {
+                cue: true,
+                title: Some("Album".to_string()),
+                performer: Some("Artist".to_string()),
+                ..merge_args(&input, &output)
+            },
+            &cli,
+            &mut Report::default(),
+        )
+        .unwrap();
+
+        let split_output = split(
+            &SplitArgs {
+                output_dir: Some(tracks_dir.clone()),
+                output_format: OutputFormat::Flac,
+                ..split_args(&output_cue)
+            },
+            &cli,
+            &mut Report::default(),
+        )
+        .unwrap();
+
+        verify(&input, &output, &cli, &mut Report::default()).unwrap();
+        verify(
+            &[tracks_dir.clone()],
+            &output_cue,
+            &cli,
+            &mut Report::default(),
+        )
+        .unwrap();
+        verify(&split_output, &output_cue, &cli, &mut Report::default()).unwrap();
+
+        let swapped = vec![input[0].clone(), input[2].clone(), input[1].clone()];
+        let err = verify(&swapped, &output, &cli, &mut Report::default())
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("Verify FAILED: Samples mismatch in 2 of 3 tracks"));
+        assert!(err.contains("3.wav (first mismatch at 00:00:00 (sample 47))"));
+
+        let err = verify(&input[..2], &output, &cli, &mut Report::default())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("2.wav (88200 extra samples at the end)"));
+
+        remove_wavs(&split_output);
+        fs::remove_dir(&tracks_dir).unwrap();
+        remove_tmp_files(false);
     }
 
     #[test]
     #[serial]
-    fn test_flac_merge() {
-        test_merge(true, true);
+    fn test_wav_split_accuraterip() {
+        remove_tmp_files(false);
+        let test_dir = get_test_dir();
+
+        let mut output_cue = test_dir.clone();
+        output_cue.push("output.cue");
+
+        let mut output_bin = test_dir.clone();
+        output_bin.push("output.bin");
+
+        let cli = test_cli();
+
+        let input = create_test_wavs(false);
+
+        let test_cue = r#"TITLE "Album"
+PERFORMER "Artist"
+FILE "1.wav" WAVE
+  TRACK 01 AUDIO
+    TITLE "A"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE "B"
+    INDEX 01 00:00:30
+FILE "2.wav" WAVE
+  TRACK 03 AUDIO
+    TITLE "C"
+    INDEX 00 00:00:00
+    INDEX 01 00:00:15
+FILE "3.wav" WAVE
+  TRACK 04 AUDIO
+    TITLE "D"
+    INDEX 01 00:00:00"#;
+
+        fs::write(&output_cue, test_cue).unwrap();
+
+        // Reference CRCs straight from the stereo samples of the image, the
+        // pregap of track 3 belongs to track 2
+        let image: Vec<u32> = input
+            .iter()
+            .flat_map(|f| {
+                let samples: Vec<i32> = hound::WavReader::open(f)
+                    .unwrap()
+                    .samples::<i32>()
+                    .map(|s| s.unwrap())
+                    .collect();
+                samples
+                    .chunks(2)
+                    .map(|c| (c[1] as u16 as u32) << 16 | c[0] as u16 as u32)
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+        let bounds = [0, 17640, 44100 + 8820, 88200, 132300];
+        let expected: Vec<(u32, u32)> = (0..4)
+            .map(|t| {
+                let track = &image[bounds[t]..bounds[t + 1]];
+                let (mut v1, mut v2) = (0u32, 0u32);
+                for (i, sample) in track.iter().enumerate() {
+                    let multiplier = i as u64 + 1;
+                    if (t == 0 && multiplier < 2940)
+                        || (t == 3 && multiplier > track.len() as u64 - 2940)
+                    {
+                        continue;
+                    }
+                    let product = *sample as u64 * multiplier;
+                    v1 = v1.wrapping_add(product as u32);
+                    v2 = v2
+                        .wrapping_add(product as u32)
+                        .wrapping_add((product >> 32) as u32);
+                }
+                (v1, v2)
+            })
+            .collect();
+
+        let write_bin = |crcs: &[u32]| {
+            let mut data = vec![4u8];
+            data.extend([0u8; 12]);
+            for crc in crcs {
+                data.push(7);
+                data.extend(crc.to_le_bytes());
+                data.extend([0u8; 4]);
+            }
+            fs::write(&output_bin, data).unwrap();
+        };
+
+        let run_split = || {
+            split(
+                &SplitArgs {
+                    gaps: GapsMode::Discard,
+                    accuraterip: AccurateRipArgs {
+                        accuraterip: false,
+                        accuraterip_bin: vec![output_bin.clone()],
+                    },
+                    ..split_args(&output_cue)
+                },
+                &cli,
+                &mut Report::default(),
+            )
+        };
+
+        write_bin(&[expected[0].0, expected[1].1, expected[2].0, expected[3].1]);
+        let split_output = run_split().unwrap();
+        assert_eq!(split_output.len(), 4);
+
+        write_bin(&[
+            expected[0].1,
+            expected[1].0,
+            expected[2].1 ^ 1,
+            expected[3].0,
+        ]);
+        let err = run_split().unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "AccurateRip: 1 of 4 tracks not found in the responses: 03"
+        );
+
+        fs::remove_file(&output_bin).unwrap();
+        remove_wavs(&split_output);
+        remove_wavs(&input);
+        remove_tmp_files(false);
     }
 
     fn test_merge(flac: bool, remove_test_files: bool) -> Vec<PathBuf> {
@@ -62,28 +1893,22 @@ mod commands {
             force: false,
             silent: false,
             totally_silent: false,
-            command: cli::Commands::Merge {
-                cue: true,
-                title: Some("Album".to_string()),
-                performer: Some("Artist".to_string()),
-                rem: None,
-                verify: true,
-                input: vec![],
-                output: PathBuf::new(),
-            },
+            ..test_cli()
         };
 
         let input = create_test_wavs(flac);
 
         merge(
-            true,
-            &Some("Album".to_string()),
-            &Some("Artist".to_string()),
-            &Some(vec![r#"COMPOSER "TEST""#.to_string()]),
-            true,
-            &input,
-            &output,
+            &MergeArgs {
+                cue: true,
+                title: Some("Album".to_string()),
+                performer: Some("Artist".to_string()),
+                rem: Some(vec![r#"COMPOSER "TEST""#.to_string()]),
+                verify: true,
+                ..merge_args(&input, &output)
+            },
             &cli,
+            &mut Report::default(),
         )
         .unwrap();
 
@@ -145,13 +1970,7 @@ FILE "output.wav" WAVE
             force: false,
             silent: false,
             totally_silent: false,
-            command: Commands::Split {
-                cue: true,
-                input: output_cue.clone(),
-                output_dir: Some(test_dir.clone()),
-                verify: true,
-                format: None,
-            },
+            ..test_cli()
         };
 
         if test_msf {
@@ -178,8 +1997,17 @@ FILE "{}" WAVE
             fs::write(&output_cue, test_cue_msf).unwrap();
         }
 
-        let mut split_output =
-            split(true, &output_cue, &Some(test_dir), true, &None, &cli).unwrap();
+        let mut split_output = split(
+            &SplitArgs {
+                cue: true,
+                output_dir: Some(test_dir),
+                verify: true,
+                ..split_args(&output_cue)
+            },
+            &cli,
+            &mut Report::default(),
+        )
+        .unwrap();
 
         let duration_rem = match test_msf {
             true => "",
@@ -228,6 +2056,60 @@ FILE "03 Artist - 3.wav" WAVE
         remove_wavs(&split_output);
     }
 
+    /// Overwrites output files and prints nothing, tests override what they check.
+    fn test_cli() -> Cli {
+        Cli {
+            force: true,
+            silent: true,
+            totally_silent: true,
+            json: false,
+            cue_encoding: None,
+            command: cli::Commands::Examples {},
+        }
+    }
+
+    /// `merge` arguments with the command line defaults.
+    fn merge_args(input: &[PathBuf], output: &Path) -> MergeArgs {
+        MergeArgs {
+            input: input.to_vec(),
+            output: output.to_path_buf(),
+            cue: false,
+            title: None,
+            performer: None,
+            rem: None,
+            verify: false,
+            output_format: None,
+            compression_level: 5,
+            convert: ConvertArgs::default(),
+            msf_rounding: MsfRounding::Nearest,
+            cue_format: CueFormatArgs::default(),
+            sort: InputSort::None,
+            dry_run: false,
+        }
+    }
+
+    /// `split` arguments with the command line defaults.
+    fn split_args(input: &Path) -> SplitArgs {
+        SplitArgs {
+            input: input.to_path_buf(),
+            cue: false,
+            recursive: false,
+            resplit: false,
+            output_dir: None,
+            verify: false,
+            format: None,
+            output_format: OutputFormat::Wav,
+            compression_level: 5,
+            gaps: GapsMode::Append,
+            id3: false,
+            accuraterip: AccurateRipArgs::default(),
+            tracks: vec![],
+            jobs: 1,
+            cue_format: CueFormatArgs::default(),
+            dry_run: false,
+        }
+    }
+
     fn create_test_wavs(flac: bool) -> Vec<PathBuf> {
         let test_dir = get_test_dir();
 
@@ -265,7 +2147,7 @@ FILE "03 Artist - 3.wav" WAVE
         input
     }
 
-    fn encode_to_flac(file: &PathBuf) {
+    fn encode_to_flac(file: &Path) {
         let output = Command::new("flac")
             .args(["--delete-input-file", file.to_str().unwrap()])
             .output()
@@ -274,7 +2156,42 @@ FILE "03 Artist - 3.wav" WAVE
         assert!(output.status.success());
     }
 
-    fn check_file_hashes(input: &Vec<PathBuf>, output: &Vec<PathBuf>) {
+    /// Adds a CUESHEET metadata block after the last metadata block.
+    fn add_cuesheet_block(file: &PathBuf, tracks: &[CueSheetTrack]) {
+        let mut block = vec![0u8; 128 + 8 + 1 + 258];
+        block.push(tracks.len() as u8);
+        for track in tracks {
+            block.extend(track.offset.to_be_bytes());
+            block.push(track.number);
+            block.extend([0u8; 12 + 1 + 13]);
+            block.push(track.indices.len() as u8);
+            for (index, index_offset) in &track.indices {
+                block.extend(index_offset.to_be_bytes());
+                block.push(*index);
+                block.extend([0u8; 3]);
+            }
+        }
+
+        let mut data = fs::read(file).unwrap();
+        let mut p = 4;
+        loop {
+            let last = data[p] & 0x80 != 0;
+            let length = u32::from_be_bytes([0, data[p + 1], data[p + 2], data[p + 3]]);
+            if last {
+                data[p] &= 0x7f;
+                p += 4 + length as usize;
+                break;
+            }
+            p += 4 + length as usize;
+        }
+
+        let mut header = (0x85u32 << 24 | block.len() as u32).to_be_bytes().to_vec();
+        header.extend(block);
+        data.splice(p..p, header);
+        fs::write(file, data).unwrap();
+    }
+
+    fn check_file_hashes(input: &[PathBuf], output: &[PathBuf]) {
         assert_eq!(input.len(), output.len());
 
         let input_iter = input.iter();
@@ -282,10 +2199,20 @@ FILE "03 Artist - 3.wav" WAVE
 
         input_iter.for_each(|a| {
             let b = output_iter.next().unwrap();
-            assert_eq!(hash_file(a), hash_file(b))
+            assert_eq!(hash_file(a), hash_file_without_tags(b, a))
         });
     }
 
+    /// Hash of a split WAV track without the tag chunks after the samples, the
+    /// RIFF size is taken from `input`.
+    fn hash_file_without_tags(file: &PathBuf, input: &PathBuf) -> Hash {
+        let input = fs::read(input).unwrap();
+        let mut data = fs::read(file).unwrap();
+        data.truncate(input.len());
+        data[4..8].copy_from_slice(&input[4..8]);
+        blake3::hash(&data)
+    }
+
     fn hash_file(file: &PathBuf) -> Hash {
         let mut hasher = blake3::Hasher::new();
         hasher.update_reader(File::open(file).unwrap()).unwrap();
@@ -365,3 +2292,348 @@ FILE "03 Artist - 3.wav" WAVE
         }
     }
 }
+
+#[cfg(test)]
+mod utils {
+    use std::{cmp::Ordering, path::PathBuf};
+
+    use crate::utils::natural_cmp;
+
+    #[test]
+    fn test_natural_cmp() {
+        let mut files: Vec<PathBuf> = [
+            "Track 10.wav",
+            "track 2.wav",
+            "Track 01.wav",
+            "Track 1b.wav",
+            "disc2/01.wav",
+            "disc10/01.wav",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+
+        files.sort_by(|a, b| natural_cmp(a, b));
+
+        assert_eq!(
+            files,
+            [
+                "disc2/01.wav",
+                "disc10/01.wav",
+                "Track 01.wav",
+                "Track 1b.wav",
+                "track 2.wav",
+                "Track 10.wav",
+            ]
+            .map(PathBuf::from)
+        );
+        assert_eq!(
+            natural_cmp(&PathBuf::from("01.wav"), &PathBuf::from("1.wav")),
+            Ordering::Equal
+        );
+    }
+}
+
+#[cfg(test)]
+mod track_msf {
+    use crate::{cli::MsfRounding, cue::CueSheet, track_msf::TrackMSF};
+
+    #[test]
+    fn test_msf_sample_rates() {
+        for rate in [44100, 48000, 88200, 96000, 192000] {
+            let msf = TrackMSF::try_from("12:34:56").unwrap();
+            let samples = msf.to_samples(rate);
+            assert_eq!(samples, msf.frames() * rate as u64 / 75);
+            assert_eq!(
+                TrackMSF::from_samples(samples, rate, MsfRounding::Nearest),
+                msf
+            );
+        }
+    }
+
+    #[test]
+    fn test_msf_rounding() {
+        // 22050 Hz: one CD frame is 294 samples
+        assert_eq!(
+            TrackMSF::from_samples(146, 22050, MsfRounding::Nearest).frames(),
+            0
+        );
+        assert_eq!(
+            TrackMSF::from_samples(147, 22050, MsfRounding::Nearest).frames(),
+            1
+        );
+        assert_eq!(
+            TrackMSF::from_samples(293, 22050, MsfRounding::Down).frames(),
+            0
+        );
+        assert_eq!(
+            TrackMSF::from_samples(1, 22050, MsfRounding::Up).frames(),
+            1
+        );
+        assert_eq!(
+            TrackMSF::from_samples(294, 22050, MsfRounding::Up).frames(),
+            1
+        );
+
+        // 11025 Hz: 147 samples per frame
+        assert_eq!(
+            TrackMSF::try_from("00:00:01").unwrap().to_samples(11025),
+            147
+        );
+        // 32000 Hz is not a multiple of 75, the frame start is rounded down
+        assert_eq!(
+            TrackMSF::try_from("00:00:01").unwrap().to_samples(32000),
+            426
+        );
+    }
+
+    #[test]
+    fn test_msf_parse() {
+        assert!(TrackMSF::try_from("00:60:00").is_err());
+        assert!(TrackMSF::try_from("00:00:75").is_err());
+        assert!(TrackMSF::try_from("00:00").is_err());
+        assert!(TrackMSF::try_from("00::00").is_err());
+        assert!(TrackMSF::try_from("5000000000000000:00:00").is_err());
+        assert!(TrackMSF::try_from("307445734561825860:59:00").is_err());
+        assert_eq!(
+            TrackMSF::try_from("300:59:74").unwrap().to_string(),
+            "300:59:74"
+        );
+    }
+
+    #[test]
+    fn test_long_cue() {
+        let cue = r#"TITLE "Audiobook"
+PERFORMER "Reader"
+FILE "book.wav" WAVE
+  TRACK 01 AUDIO
+    TITLE "Part 1"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE "Part 2"
+    INDEX 01 150:00:00
+  TRACK 03 AUDIO
+    TITLE "Part 3"
+    INDEX 00 299:59:70
+    INDEX 01 300:00:01"#;
+
+        let cue_sheet = CueSheet::try_from(cue).unwrap();
+        assert_eq!(cue_sheet.indices.len(), 3);
+        assert_eq!(
+            cue_sheet.indices[2][1].position.to_samples(44100),
+            (300 * 60 * 75 + 1) * 588
+        );
+        assert_eq!(cue_sheet.to_string(), cue);
+    }
+}
+
+#[cfg(test)]
+mod cue {
+    use crate::{
+        cli::{CueDialect, CueFileType, CueFormatArgs},
+        cue::{CueSheet, CueStyle},
+    };
+
+    #[test]
+    fn test_cue_dialects() {
+        let cue = r#"REM GENRE Jazz
+TITLE "Kind of Blue"
+PERFORMER "Miles Davis"
+FILE "Image.wav" WAVE
+  TRACK 01 AUDIO
+    TITLE "So What"
+    INDEX 01 00:00:00
+    REM DURATION 44100
+    REM COMMENT test
+  TRACK 02 AUDIO
+    TITLE "Blue"
+    INDEX 01 00:01:00"#;
+
+        let cue_sheet = CueSheet::try_from(cue).unwrap();
+        let format = |cue_format: CueFormatArgs| cue_sheet.format(&CueStyle::from(&cue_format));
+        let dialect = |cue_dialect: CueDialect| CueFormatArgs {
+            cue_dialect,
+            ..Default::default()
+        };
+
+        assert_eq!(format(CueFormatArgs::default()), cue);
+
+        let eac = format(dialect(CueDialect::Eac));
+        assert!(eac.starts_with("\u{feff}REM GENRE Jazz\r\nTITLE \"Kind of Blue\"\r\n"));
+        assert!(eac.contains("TITLE \"So What\"\r\n    REM COMMENT test\r\n    INDEX 01"));
+        assert!(!eac.contains("DURATION"));
+
+        let strict = format(dialect(CueDialect::Strict));
+        assert!(strict.contains("\nFILE Image.wav WAVE\n"));
+        assert!(strict.contains("\n    TITLE \"So What\"\n    REM COMMENT test\n"));
+        assert!(strict.contains("\n    TITLE Blue\n"));
+        assert_eq!(
+            CueSheet::try_from(strict.as_ref()).unwrap().cue.tracks[1]
+                .1
+                .title,
+            "Blue"
+        );
+
+        let binary = format(CueFormatArgs {
+            cue_file_type: Some(CueFileType::Binary),
+            cue_rem_duration: Some(true),
+            ..dialect(CueDialect::Xld)
+        });
+        assert!(binary.contains("FILE \"Image.wav\" BINARY\n"));
+        assert!(binary.contains("    REM DURATION 44100\n    REM COMMENT test\n    INDEX 01"));
+    }
+}
+
+#[cfg(test)]
+mod template {
+    use std::path::PathBuf;
+
+    use crate::{
+        tags::Tags,
+        template::{sanitize_file_name, unique_file_name, Template, TemplateFields},
+    };
+
+    #[test]
+    fn test_template() {
+        let tags = Tags {
+            title: Some("Who Are You / Live".to_string()),
+            album: Some("Live".to_string()),
+            album_artist: Some("The Who".to_string()),
+            track_number: Some(7),
+            track_total: Some(12),
+            ..Default::default()
+        };
+        let render = |format: &str, comments: &[&str]| {
+            Template::try_from(format)
+                .unwrap()
+                .render(&TemplateFields {
+                    tags: &tags,
+                    comments: comments.iter().map(|c| c.to_string()).collect(),
+                })
+                .to_string_lossy()
+                .to_string()
+        };
+
+        assert_eq!(
+            render("%track% %artist|albumartist% - %title%", &[]),
+            "07 The Who - Who Are You _ Live"
+        );
+        assert_eq!(
+            render("[%discnumber%-]%track:3% of %totaltracks:3%", &[]),
+            "007 of 012"
+        );
+        assert_eq!(
+            render("[%discnumber%-]%track:1% [(%date%)]", &["DISCNUMBER 2"]),
+            "2-7"
+        );
+        assert_eq!(
+            render(
+                "%artist|'Unknown'% - %title:7% %rem:comment%",
+                &["COMMENT \"EAC rip\""]
+            ),
+            "Unknown - Who Are EAC rip"
+        );
+        assert_eq!(render("%genre%%title:3%", &[]), "Who");
+        assert_eq!(
+            render("%albumartist%/[%date% - ]%album%//%track% %title%", &[]),
+            "The Who/Live/07 Who Are You _ Live"
+        );
+        assert_eq!(render("%rem:dir%/%track%", &["DIR .."]), "_/07");
+
+        assert_eq!(render("%track% 100%% %title:3%", &[]), "07 100% Who");
+
+        assert!(Template::try_from("%track% %foo%").is_err());
+        assert!(Template::try_from("%track% - %title").is_err());
+        assert!(Template::try_from("[%track%").is_err());
+        assert!(Template::try_from("%track%]").is_err());
+
+        assert_eq!(sanitize_file_name("AC/DC: Live?", false), "AC_DC: Live?");
+        assert_eq!(sanitize_file_name("AC/DC: Live?", true), "AC_DC_ Live_");
+        assert_eq!(sanitize_file_name("Intro. ", true), "Intro");
+        assert_eq!(sanitize_file_name("con.wav", true), "_con.wav");
+        assert_eq!(sanitize_file_name("COM10", true), "COM10");
+
+        let mut used = vec![];
+        assert_eq!(
+            unique_file_name(PathBuf::from("Intro"), "wav", &mut used),
+            PathBuf::from("Intro.wav")
+        );
+        assert_eq!(
+            unique_file_name(PathBuf::from("intro"), "wav", &mut used),
+            PathBuf::from("intro (2).wav")
+        );
+        assert_eq!(
+            unique_file_name(PathBuf::from("Intro"), "wav", &mut used),
+            PathBuf::from("Intro (3).wav")
+        );
+    }
+}
+
+#[cfg(test)]
+mod disc_id {
+    use crate::disc_id::{DiscIds, Toc};
+
+    #[test]
+    fn test_disc_ids() {
+        let toc = Toc {
+            offsets: vec![0, 21445, 34792, 53650, 72000],
+            leadout: 95312,
+        };
+
+        assert_eq!(
+            toc.disc_ids(),
+            DiscIds {
+                musicbrainz: "jQ1X9V0j7LY_7QgPMhp6H45Rwmw-".to_string(),
+                freedb: 0x4204f605,
+                accuraterip: (0x00043acf, 0x0013bdab),
+                cuetools: "SK6eNiKOP6pjOqpptyW_CdsD9YY-".to_string(),
+            }
+        );
+        assert_eq!(
+            toc.accuraterip_file_name(),
+            "dBAR-005-00043acf-0013bdab-4204f605.bin"
+        );
+    }
+}
+
+#[cfg(test)]
+mod report {
+    use std::path::Path;
+
+    use anyhow::anyhow;
+    use serde_json::json;
+
+    use crate::report::{FileEntry, Report, VerifyEntry};
+
+    #[test]
+    fn test_to_json() {
+        let mut report = Report::default();
+        report
+            .outputs
+            .push(FileEntry::audio(Path::new("out/1.wav"), 22050, 44100));
+        report.verify.push(VerifyEntry {
+            name: "1.wav".to_string(),
+            ok: true,
+            message: "OK".to_string(),
+            hash: "abc".to_string(),
+        });
+        report.results.push(("extra", json!([1])));
+
+        let document = report.to_json("split", Some(&anyhow!("Broken")));
+        let keys: Vec<&String> = document.as_object().unwrap().keys().collect();
+
+        assert_eq!(
+            keys,
+            ["command", "success", "inputs", "outputs", "verify", "warnings", "errors", "extra"]
+        );
+        assert_eq!(document["success"], json!(false));
+        assert_eq!(
+            document["outputs"][0],
+            json!({ "path": "out/1.wav", "samples": 22050, "duration": 0.5, "hash": "abc" })
+        );
+        assert_eq!(
+            document["errors"],
+            json!([{ "code": "error", "message": "Broken" }])
+        );
+    }
+}
@@ -1,53 +1,68 @@
 use std::fmt;
 use thiserror::Error;
 
+use crate::cli::MsfRounding;
+
+/// CD frames (sectors) per second.
+pub const FRAMES_PER_SECOND: u64 = 75;
+
 #[derive(Error, Debug)]
 pub enum TrackMSFParseError {
     #[error("Invalid timestamp: {0}")]
     InvalidTimeStamp(String),
 }
 
-#[derive(Debug, PartialEq)]
+/// CUE timestamp held as a whole number of CD frames, so conversions to and
+/// from samples are exact and there is no upper limit on the minutes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct TrackMSF {
-    minutes: u8,
-    seconds: u8,
-    fractions: u8,
+    frames: u64,
 }
 
 #[allow(dead_code)]
 impl TrackMSF {
-    pub fn new(seconds: f64) -> Self {
-        let (m, s) = Self::divmod(seconds, 60.0);
-        let ms = s % 1.0;
+    pub fn from_frames(frames: u64) -> Self {
+        TrackMSF { frames }
+    }
+
+    /// Converts a sample position to CD frames. Unless the sample rate is a
+    /// multiple of 75 a position may fall between two frames, `rounding`
+    /// decides which one is used.
+    pub fn from_samples(samples: u64, sample_rate: u32, rounding: MsfRounding) -> Self {
+        let numerator = samples as u128 * FRAMES_PER_SECOND as u128;
+        let rate = sample_rate as u128;
+
+        let frames = match rounding {
+            MsfRounding::Down => numerator / rate,
+            MsfRounding::Up => numerator.div_ceil(rate),
+            MsfRounding::Nearest => (numerator + rate / 2) / rate,
+        };
 
         TrackMSF {
-            minutes: m as u8,
-            seconds: s as u8,
-            fractions: (ms * 75.0).round() as u8,
+            frames: frames as u64,
         }
     }
 
-    pub fn minutes(&self) -> u8 {
-        self.minutes
+    /// First sample of the frame. For sample rates that are not a multiple of
+    /// 75 the position is rounded down.
+    pub fn to_samples(self, sample_rate: u32) -> u64 {
+        (self.frames as u128 * sample_rate as u128 / FRAMES_PER_SECOND as u128) as u64
     }
 
-    pub fn seconds(&self) -> u8 {
-        self.seconds
+    pub fn frames(&self) -> u64 {
+        self.frames
     }
 
-    pub fn fractions(&self) -> u8 {
-        self.fractions
+    pub fn minutes(&self) -> u64 {
+        self.frames / (60 * FRAMES_PER_SECOND)
     }
 
-    pub fn to_duration_seconds(&self) -> f64 {
-        ((self.minutes as u64 * 60) + self.seconds as u64) as f64
-            + (self.fractions as f64 * 0.013333333333)
+    pub fn seconds(&self) -> u64 {
+        self.frames / FRAMES_PER_SECOND % 60
     }
 
-    fn divmod(x: f64, y: f64) -> (f64, f64) {
-        let quotient = x / y;
-        let remainder = x % y;
-        (quotient, remainder)
+    pub fn fractions(&self) -> u64 {
+        self.frames % FRAMES_PER_SECOND
     }
 }
 
@@ -58,23 +73,26 @@ impl TryFrom<&str> for TrackMSF {
         let err = || TrackMSFParseError::InvalidTimeStamp(value.to_owned());
 
         let split = value.split(':').collect::<Vec<_>>();
-        if split.len() != 3 {
+        if split.len() != 3 || split.iter().any(|s| s.is_empty()) {
             Err(err())?
         }
 
         let numbers = split
             .into_iter()
-            .map(|s| s.parse::<u8>().map_err(|_| err()))
+            .map(|s| s.parse::<u64>().map_err(|_| err()))
             .collect::<Result<Vec<_>, _>>()?;
-        if numbers.iter().any(|&n| n >= 100) {
+        if numbers[1] >= 60 || numbers[2] >= FRAMES_PER_SECOND {
             Err(err())?
         }
 
-        Ok(Self {
-            minutes: numbers[0],
-            seconds: numbers[1],
-            fractions: numbers[2],
-        })
+        let frames = numbers[0]
+            .checked_mul(60)
+            .and_then(|s| s.checked_add(numbers[1]))
+            .and_then(|s| s.checked_mul(FRAMES_PER_SECOND))
+            .and_then(|f| f.checked_add(numbers[2]))
+            .ok_or_else(err)?;
+
+        Ok(Self { frames })
     }
 }
 
@@ -83,7 +101,9 @@ impl fmt::Display for TrackMSF {
         write!(
             f,
             "{:0>2}:{:0>2}:{:0>2}",
-            self.minutes, self.seconds, self.fractions
+            self.minutes(),
+            self.seconds(),
+            self.fractions()
         )
     }
 }
@@ -1,10 +1,32 @@
-use std::{fmt::Write, path::PathBuf};
+use std::{cmp::Ordering, fmt::Write, path::Path};
 
 use anyhow::{bail, Context};
 use indicatif::{ProgressBar, ProgressState, ProgressStyle};
 use inquire::Confirm;
 
-use crate::cli::Cli;
+use crate::{cli::Cli, report::Report};
+
+/// `println!` for progress and results, on stderr with `--json` so stdout only
+/// has the result document.
+macro_rules! progress {
+    ($cli:expr, $($arg:tt)*) => {
+        if $cli.json {
+            eprintln!($($arg)*)
+        } else {
+            println!($($arg)*)
+        }
+    };
+}
+
+pub(crate) use progress;
+
+/// Prints and records a warning for the `--json` document.
+pub fn warn(cli: &Cli, report: &mut Report, code: &'static str, message: String) {
+    if !cli.totally_silent {
+        progress!(cli, "Warning: {}", message);
+    }
+    report.warnings.push((code, message));
+}
 
 pub fn create_sample_progress(len: u64, cli: &Cli) -> Result<Option<ProgressBar>, anyhow::Error> {
     if cli.silent || cli.totally_silent {
@@ -27,7 +49,7 @@ pub fn create_sample_progress(len: u64, cli: &Cli) -> Result<Option<ProgressBar>
     Ok(Some(pb))
 }
 
-pub fn promt_output_in_input(file: &PathBuf) -> Result<bool, anyhow::Error> {
+pub fn promt_output_in_input(file: &Path) -> Result<bool, anyhow::Error> {
     let ans = Confirm::new(&format!(
         "Input file list contains {} output file, are you sure you want to continue?",
         file.file_name()
@@ -44,7 +66,7 @@ pub fn promt_output_in_input(file: &PathBuf) -> Result<bool, anyhow::Error> {
     }
 }
 
-pub fn promt_overwrite(file: &PathBuf) -> Result<bool, anyhow::Error> {
+pub fn promt_overwrite(file: &Path) -> Result<bool, anyhow::Error> {
     let ans = Confirm::new(&format!(
         "File {} already exists, overwrite?",
         file.file_name()
@@ -60,3 +82,43 @@ pub fn promt_overwrite(file: &PathBuf) -> Result<bool, anyhow::Error> {
         Err(e) => bail!(e),
     }
 }
+
+/// Compares paths with runs of digits as numbers, so `2.wav` comes before
+/// `10.wav`. Text is compared case-insensitively.
+pub fn natural_cmp(a: &Path, b: &Path) -> Ordering {
+    let a = a.to_string_lossy().to_lowercase();
+    let b = b.to_string_lossy().to_lowercase();
+    let mut a = a.as_str();
+    let mut b = b.as_str();
+
+    loop {
+        let (Some(ca), Some(cb)) = (a.chars().next(), b.chars().next()) else {
+            return a.len().cmp(&b.len());
+        };
+
+        let ordering = match (ca.is_ascii_digit(), cb.is_ascii_digit()) {
+            (true, true) => {
+                let run_a = a.len() - a.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+                let run_b = b.len() - b.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+                let digits_a = a[..run_a].trim_start_matches('0');
+                let digits_b = b[..run_b].trim_start_matches('0');
+                a = &a[run_a..];
+                b = &b[run_b..];
+
+                digits_a
+                    .len()
+                    .cmp(&digits_b.len())
+                    .then_with(|| digits_a.cmp(digits_b))
+            }
+            _ => {
+                a = &a[ca.len_utf8()..];
+                b = &b[cb.len_utf8()..];
+                ca.cmp(&cb)
+            }
+        };
+
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
@@ -0,0 +1,556 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{channel, sync_channel, Sender, SyncSender},
+    },
+    thread::{self, JoinHandle},
+};
+
+use anyhow::{anyhow, bail, Context};
+use blake3::{Hash, Hasher};
+use claxon::FlacReader;
+use hound::{SampleFormat, WavReader, WavSpec};
+use num_traits::ToBytes;
+
+use crate::{
+    audio::{format_spec, get_audio_info},
+    cli::{Cli, MsfRounding},
+    convert::{ConvertedSample, SpecConverter},
+    report::{Failure, Report, VerifyEntry},
+    track_msf::TrackMSF,
+    utils::progress,
+};
+
+/// Bytes collected before they are handed over to the hashing thread.
+const HASH_CHUNK_SIZE: usize = 1 << 20;
+
+/// Messages of the hashing thread, keyed by the id of their `StreamHasher`.
+enum HashMessage {
+    Update(u64, Vec<u8>),
+    Finalize(u64, Sender<Hash>),
+}
+
+/// Collects the bytes of the samples written to an output file and hands
+/// them over to the hashing thread of its `OutputVerifier`, so the copy loop
+/// doesn't have to hash them.
+pub struct StreamHasher {
+    id: u64,
+    buffer: Vec<u8>,
+    samples: u64,
+    sender: SyncSender<HashMessage>,
+}
+
+impl StreamHasher {
+    /// Samples are hashed as big endian bytes of their `i32` or `f32` value,
+    /// the same way `SampleReader` reads them back.
+    pub fn update_sample<S: ToBytes>(&mut self, sample: &S) -> Result<(), anyhow::Error> {
+        self.buffer.extend_from_slice(sample.to_be_bytes().as_ref());
+        self.samples += 1;
+
+        if self.buffer.len() >= HASH_CHUNK_SIZE {
+            let chunk = std::mem::replace(&mut self.buffer, Vec::with_capacity(HASH_CHUNK_SIZE));
+            self.sender
+                .send(HashMessage::Update(self.id, chunk))
+                .context("Hashing thread stopped")?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the hash and the number of hashed samples.
+    pub fn finalize(self) -> Result<(Hash, u64), anyhow::Error> {
+        let (sender, receiver) = channel();
+        self.sender
+            .send(HashMessage::Update(self.id, self.buffer))
+            .and_then(|_| self.sender.send(HashMessage::Finalize(self.id, sender)))
+            .context("Hashing thread stopped")?;
+
+        let hash = receiver.recv().context("Hashing thread stopped")?;
+
+        Ok((hash, self.samples))
+    }
+}
+
+/// Where the samples of a segment were read from. Only read again to find
+/// the first mismatch of a failed segment.
+#[derive(Clone)]
+pub enum SampleSource {
+    /// `length` interleaved samples of `file` starting at `start`
+    Range {
+        file: PathBuf,
+        start: u64,
+        length: u64,
+    },
+    /// All samples of `file` converted to `spec`
+    Converted { file: PathBuf, spec: WavSpec },
+}
+
+/// Part of an output file that is verified on its own, a track of a merged
+/// file or a whole split track.
+pub struct Segment {
+    pub name: String,
+    pub source: SampleSource,
+    pub hasher: StreamHasher,
+}
+
+enum SegmentStatus {
+    Ok,
+    /// Interleaved index of the first sample that differs
+    Mismatch(u64),
+    /// The output file ends at this interleaved sample index
+    Truncated(u64),
+    /// The output file has samples after the last segment
+    Extra(u64),
+    /// The output file couldn't be read back
+    Unreadable(String),
+}
+
+/// Segment with the hash of the samples written to it.
+struct WrittenSegment {
+    name: String,
+    source: SampleSource,
+    hash: Hash,
+    samples: u64,
+}
+
+struct SegmentResult {
+    name: String,
+    spec: WavSpec,
+    status: SegmentStatus,
+    hash: Hash,
+}
+
+/// Reads back finished output files on a separate thread and compares their
+/// samples with the hashes of the segments that were written to them, so the
+/// next output can be written in the meantime.
+///
+/// The samples of all outputs are hashed while they are written by a single
+/// hashing thread of the verifier, however many outputs are written at the
+/// same time.
+pub struct OutputVerifier {
+    sender: Sender<(PathBuf, WavSpec, Vec<WrittenSegment>)>,
+    thread: JoinHandle<Vec<SegmentResult>>,
+    hash_sender: SyncSender<HashMessage>,
+    hash_thread: JoinHandle<()>,
+    next_hasher: AtomicU64,
+}
+
+impl OutputVerifier {
+    pub fn new() -> OutputVerifier {
+        let (hash_sender, hash_receiver) = sync_channel::<HashMessage>(4);
+        let hash_thread = thread::spawn(move || {
+            let mut hashers: HashMap<u64, Hasher> = HashMap::new();
+
+            for message in hash_receiver {
+                match message {
+                    HashMessage::Update(id, chunk) => {
+                        hashers.entry(id).or_default().update(&chunk);
+                    }
+                    HashMessage::Finalize(id, sender) => {
+                        let hash = hashers.remove(&id).unwrap_or_default().finalize();
+                        // The hasher is gone if it failed on its side
+                        let _ = sender.send(hash);
+                    }
+                }
+            }
+        });
+
+        let (sender, receiver) = channel::<(PathBuf, WavSpec, Vec<WrittenSegment>)>();
+        let thread = thread::spawn(move || {
+            let mut results = vec![];
+
+            // A file that can't be read fails its segments, the other files
+            // are still verified
+            for (file, spec, segments) in receiver {
+                match verify_file(&file, spec, &segments) {
+                    Ok(file_results) => results.extend(file_results),
+                    Err(e) => results.extend(segments.iter().map(|segment| SegmentResult {
+                        name: segment.name.clone(),
+                        spec,
+                        status: SegmentStatus::Unreadable(format!("{:#}", e)),
+                        hash: segment.hash,
+                    })),
+                }
+            }
+
+            results
+        });
+
+        OutputVerifier {
+            sender,
+            thread,
+            hash_sender,
+            hash_thread,
+            next_hasher: AtomicU64::new(0),
+        }
+    }
+
+    /// Hasher of the samples of one segment written to an output file.
+    pub fn hasher(&self) -> StreamHasher {
+        StreamHasher {
+            id: self.next_hasher.fetch_add(1, Ordering::Relaxed),
+            buffer: Vec::with_capacity(HASH_CHUNK_SIZE),
+            samples: 0,
+            sender: self.hash_sender.clone(),
+        }
+    }
+
+    /// `segments` are the parts of `file` in the order they were written.
+    pub fn check(
+        &self,
+        file: &Path,
+        spec: WavSpec,
+        segments: Vec<Segment>,
+    ) -> Result<(), anyhow::Error> {
+        let mut written = vec![];
+        for segment in segments {
+            let (hash, samples) = segment.hasher.finalize()?;
+            written.push(WrittenSegment {
+                name: segment.name,
+                source: segment.source,
+                hash,
+                samples,
+            });
+        }
+
+        self.sender
+            .send((file.to_path_buf(), spec, written))
+            .context("Verify thread stopped")
+    }
+
+    /// Waits until all output files are read back and prints the result of
+    /// every segment.
+    pub fn finish(self, cli: &Cli, report: &mut Report) -> Result<(), anyhow::Error> {
+        if !cli.silent {
+            progress!(cli, "Verifying ...");
+        }
+
+        drop(self.hash_sender);
+        self.hash_thread
+            .join()
+            .map_err(|_| anyhow!("Hashing thread panicked"))?;
+
+        drop(self.sender);
+
+        let results = self
+            .thread
+            .join()
+            .map_err(|_| anyhow!("Verify thread panicked"))?;
+
+        report_results(&results, cli, report)
+    }
+}
+
+/// Compares the samples of `tracks` with the samples of `image`, each list
+/// read as one stream, so split tracks can be checked against their image or
+/// the files of a multiple file CUE sheet against the single file. Track
+/// hashes are the same as the ones of `--verify`.
+pub fn verify_tracks(
+    tracks: &[PathBuf],
+    image: &[PathBuf],
+    cli: &Cli,
+    report: &mut Report,
+) -> Result<(), anyhow::Error> {
+    if !cli.silent {
+        progress!(cli, "Verifying ...");
+    }
+
+    let spec = get_audio_info(image.first().context("No image files")?)?.spec;
+    let mut mismatched = vec![];
+    for file in tracks.iter().chain(image) {
+        let file_spec = get_audio_info(file)?.spec;
+        if file_spec != spec {
+            mismatched.push(format!(
+                "  {} ({})",
+                file.display(),
+                format_spec(&file_spec)
+            ));
+        }
+    }
+
+    if !mismatched.is_empty() {
+        bail!(Failure::SpecMismatch(format!(
+            "Files do not match the image audio spec ({}):\n{}",
+            format_spec(&spec),
+            mismatched.join("\n")
+        )));
+    }
+
+    let mut image_readers = image
+        .iter()
+        .map(|f| SampleReader::open(f, spec.sample_format))
+        .collect::<Result<Vec<SampleReader>, anyhow::Error>>()?;
+    let mut image_samples = image_readers.iter_mut().flat_map(|r| r.samples());
+    let mut results = vec![];
+
+    for track in tracks {
+        let mut reader = SampleReader::open(track, spec.sample_format)?;
+        let mut hasher = Hasher::new();
+        let mut status = SegmentStatus::Ok;
+
+        for (i, sample) in reader.samples().enumerate() {
+            let sample = sample?;
+            hasher.update(&sample.to_be_bytes());
+
+            match (image_samples.next().transpose()?, &status) {
+                (Some(s), _) if s == sample => (),
+                (Some(_), SegmentStatus::Ok) => status = SegmentStatus::Mismatch(i as u64),
+                (None, SegmentStatus::Ok) => status = SegmentStatus::Truncated(i as u64),
+                _ => (),
+            }
+        }
+
+        results.push(SegmentResult {
+            name: track
+                .file_name()
+                .context("Failed to get file name")?
+                .to_string_lossy()
+                .to_string(),
+            spec,
+            status,
+            hash: hasher.finalize(),
+        });
+    }
+
+    // A decode error after the last track still fails the verify
+    let extra = image_samples.try_fold(0u64, |n, s| s.map(|_| n + 1))?;
+    if extra > 0 {
+        if let Some(last) = results.last_mut() {
+            if matches!(last.status, SegmentStatus::Ok) {
+                last.status = SegmentStatus::Extra(extra);
+            }
+        }
+    }
+
+    report_results(&results, cli, report)
+}
+
+/// Prints the result of every segment and fails unless all of them are OK.
+fn report_results(
+    results: &[SegmentResult],
+    cli: &Cli,
+    report: &mut Report,
+) -> Result<(), anyhow::Error> {
+    let failed: Vec<String> = results
+        .iter()
+        .filter(|r| !matches!(r.status, SegmentStatus::Ok))
+        .map(|r| format!("{} ({})", r.name, r.describe()))
+        .collect();
+
+    if !cli.totally_silent && (!cli.silent || !failed.is_empty()) {
+        for result in results {
+            match result.status {
+                SegmentStatus::Ok => progress!(cli, "  OK      {}", result.name),
+                _ => progress!(cli, "  FAILED  {}: {}", result.name, result.describe()),
+            }
+        }
+    }
+
+    report.verify.extend(results.iter().map(|r| VerifyEntry {
+        name: r.name.clone(),
+        ok: matches!(r.status, SegmentStatus::Ok),
+        message: r.describe(),
+        hash: r.hash.to_hex().to_string(),
+    }));
+
+    if !failed.is_empty() {
+        bail!(Failure::Verify(format!(
+            "Verify FAILED: Samples mismatch in {} of {} tracks: {}",
+            failed.len(),
+            results.len(),
+            failed.join(", ")
+        )));
+    } else if !cli.totally_silent {
+        progress!(cli, "Verify OK");
+    }
+
+    Ok(())
+}
+
+impl SegmentResult {
+    fn describe(&self) -> String {
+        let position = |sample: u64| {
+            let frame = sample / self.spec.channels as u64;
+            format!(
+                "{} (sample {})",
+                TrackMSF::from_samples(frame, self.spec.sample_rate, MsfRounding::Down),
+                frame
+            )
+        };
+
+        match &self.status {
+            SegmentStatus::Ok => "OK".to_string(),
+            SegmentStatus::Mismatch(s) => format!("first mismatch at {}", position(*s)),
+            SegmentStatus::Truncated(s) => format!("truncated at {}", position(*s)),
+            SegmentStatus::Extra(s) => format!("{} extra samples at the end", s),
+            SegmentStatus::Unreadable(e) => format!("can't be read back: {}", e),
+        }
+    }
+}
+
+fn verify_file(
+    file: &Path,
+    spec: WavSpec,
+    segments: &[WrittenSegment],
+) -> Result<Vec<SegmentResult>, anyhow::Error> {
+    let mut results = vec![];
+    let mut failed = vec![];
+
+    {
+        let mut reader = SampleReader::open(file, spec.sample_format)?;
+        let mut samples = reader.samples();
+        let mut offset = 0;
+
+        for (i, segment) in segments.iter().enumerate() {
+            let mut hasher = Hasher::new();
+            let mut read = 0;
+
+            while read < segment.samples {
+                match samples.next() {
+                    Some(s) => hasher.update(&s?.to_be_bytes()),
+                    None => break,
+                };
+                read += 1;
+            }
+
+            let status = if read < segment.samples {
+                SegmentStatus::Truncated(read)
+            } else if hasher.finalize() != segment.hash {
+                SegmentStatus::Mismatch(0)
+            } else {
+                SegmentStatus::Ok
+            };
+
+            if matches!(status, SegmentStatus::Mismatch(_)) {
+                failed.push((i, offset));
+            }
+
+            results.push(SegmentResult {
+                name: segment.name.clone(),
+                spec,
+                status,
+                hash: segment.hash,
+            });
+            offset += segment.samples;
+        }
+
+        let extra = samples.try_fold(0u64, |n, s| s.map(|_| n + 1))?;
+        if extra > 0 {
+            if let Some(last) = results.last_mut() {
+                if matches!(last.status, SegmentStatus::Ok) {
+                    last.status = SegmentStatus::Extra(extra);
+                }
+            }
+        }
+    }
+
+    for (i, offset) in failed {
+        let position = first_mismatch(file, spec, offset, &segments[i].source)?;
+        results[i].status = SegmentStatus::Mismatch(position);
+    }
+
+    Ok(results)
+}
+
+/// Interleaved index of the first sample of the segment at `offset` in
+/// `file` that differs from its source.
+fn first_mismatch(
+    file: &Path,
+    spec: WavSpec,
+    offset: u64,
+    source: &SampleSource,
+) -> Result<u64, anyhow::Error> {
+    let mut output_reader = SampleReader::open(file, spec.sample_format)?;
+    let mut output = output_reader.samples().skip(offset as usize);
+
+    match source {
+        SampleSource::Range {
+            file: source_file,
+            start,
+            length,
+        } => {
+            let mut source_reader = SampleReader::open(source_file, spec.sample_format)?;
+            let mut source = source_reader.samples().skip(*start as usize);
+
+            for i in 0..*length {
+                match (source.next().transpose()?, output.next().transpose()?) {
+                    (Some(a), Some(b)) if a == b => (),
+                    _ => return Ok(i),
+                }
+            }
+
+            Ok(*length)
+        }
+        SampleSource::Converted {
+            file: source_file,
+            spec: target,
+        } => {
+            let from = crate::audio::get_audio_info(source_file)?.spec;
+            let mut index = 0;
+            let mut found = None;
+
+            let result = SpecConverter::new(from, *target)?.convert_file(source_file, None, |s| {
+                let expected = match s {
+                    ConvertedSample::Int(v) => v as u32,
+                    ConvertedSample::Float(v) => v.to_bits(),
+                };
+
+                match output.next() {
+                    Some(Ok(v)) if v == expected => {
+                        index += 1;
+                        Ok(())
+                    }
+                    _ => {
+                        found = Some(index);
+                        bail!("Mismatch found")
+                    }
+                }
+            });
+
+            match found {
+                Some(i) => Ok(i),
+                None => result.map(|_| index),
+            }
+        }
+    }
+}
+
+/// Reads samples of a WAV or FLAC file as the bits of their `i32` or `f32`
+/// value.
+enum SampleReader {
+    Wav(WavReader<BufReader<File>>, SampleFormat),
+    Flac(FlacReader<File>),
+}
+
+impl SampleReader {
+    fn open(file: &Path, sample_format: SampleFormat) -> Result<SampleReader, anyhow::Error> {
+        match file
+            .extension()
+            .context("Failed to get file extension")?
+            .to_str()
+            .context("to_str failed")?
+            .to_lowercase()
+            .as_ref()
+        {
+            "wav" => Ok(SampleReader::Wav(WavReader::open(file)?, sample_format)),
+            "flac" => Ok(SampleReader::Flac(FlacReader::open(file)?)),
+            _ => bail!("Unsupported format"),
+        }
+    }
+
+    fn samples(&mut self) -> Box<dyn Iterator<Item = Result<u32, anyhow::Error>> + '_> {
+        match self {
+            SampleReader::Wav(r, SampleFormat::Int) => {
+                Box::new(r.samples::<i32>().map(|s| Ok(s? as u32)))
+            }
+            SampleReader::Wav(r, SampleFormat::Float) => {
+                Box::new(r.samples::<f32>().map(|s| Ok(s?.to_bits())))
+            }
+            SampleReader::Flac(r) => Box::new(r.samples().map(|s| Ok(s? as u32))),
+        }
+    }
+}
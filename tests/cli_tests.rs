@@ -142,6 +142,58 @@ fn test_sh_split_wav() {
     remove_tmp_files(false);
 }
 
+#[test]
+#[serial]
+fn test_cmd_merge_json() {
+    remove_tmp_files(false);
+    create_test_wavs(false);
+
+    let test_dir = get_test_dir();
+    let output = cmd!(
+        wfcue_exe(),
+        "--json",
+        "merge",
+        "--cue",
+        "--title",
+        "Album",
+        "--performer",
+        "Artist",
+        "--verify",
+        "--input",
+        "1.wav,2.wav,3.wav",
+        "--output",
+        "output.wav"
+    )
+    .dir(&test_dir)
+    .stdout_capture()
+    .stderr_capture()
+    .run()
+    .unwrap();
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stdout.starts_with("{\n  \"command\": \"merge\",\n  \"success\": true,"));
+    assert!(stdout.contains("\"samples\": 132300,"));
+    assert_eq!(stdout.matches("\"ok\": true").count(), 3);
+    assert!(stderr.contains("Merging 1.wav ..."));
+    assert!(stderr.contains("Done."));
+
+    let output = cmd!(wfcue_exe(), "--json", "split", "--input", "missing.cue")
+        .dir(&test_dir)
+        .stdout_capture()
+        .stderr_capture()
+        .unchecked()
+        .run()
+        .unwrap();
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(!output.status.success());
+    assert!(stdout.contains("\"success\": false,"));
+    assert!(stdout.contains("\"code\": \"io_error\""));
+
+    remove_tmp_files(false);
+}
+
 fn create_test_wavs(spaces: bool) -> Vec<PathBuf> {
     let test_dir = get_test_dir();
 